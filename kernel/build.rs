@@ -0,0 +1,212 @@
+//! Embed a kernel symbol table used to resolve backtrace addresses
+//!
+//! Symbols are read from the `nm` output named by `OROS_SYMBOL_MAP`, taken
+//! from a previous build of the same kernel:
+//!
+//! ```text
+//! nm -n -C target/x86_64-unknown-none/debug/oros-kernel > kernel.sym
+//! OROS_SYMBOL_MAP=$PWD/kernel.sym cargo build ...
+//! ```
+//!
+//! Without it an empty table is embedded and backtraces show addresses only.
+//!
+//! Every build gets an id hashed from the sources, features, flags and
+//! compiler it is built from, passed to the kernel as `OROS_BUILD_ID`. The
+//! kernel exports `oros_symbol_anchor` as `oros_symbol_anchor_<id>`, so the
+//! map names the build it was taken from. The kernel refuses a table taken
+//! from a build with another id, its addresses belong to other code.
+//!
+//! Table layout, all integers little endian:
+//! `[build_id: u64][anchor: u64][count: u64][count * (addr: u64, name_offset: u32, name_len: u32)][names]`
+//! where `build_id` is the id of the build the map was taken from and
+//! `anchor` the address of `oros_symbol_anchor`, used by the kernel to find
+//! the offset it was loaded at
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SYMBOL_MAP_VAR: &str = "OROS_SYMBOL_MAP";
+const BUILD_ID_VAR: &str = "OROS_BUILD_ID";
+const ANCHOR: &str = "oros_symbol_anchor";
+
+/// Variables set by cargo which change the generated code
+const BUILD_VARS: &[&str] = &[
+    "TARGET",
+    "PROFILE",
+    "OPT_LEVEL",
+    "DEBUG",
+    "CARGO_ENCODED_RUSTFLAGS",
+];
+
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-env-changed={}", SYMBOL_MAP_VAR);
+
+    let build_id = build_id(&manifest_dir);
+    println!("cargo:rustc-env={}={:016x}", BUILD_ID_VAR, build_id);
+
+    let table = match env::var_os(SYMBOL_MAP_VAR) {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            println!("cargo:rerun-if-changed={}", path.display());
+            let map = fs::read_to_string(&path)
+                .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+            let table = build_table(&map);
+            if map_build_id(&table) != build_id {
+                println!(
+                    "cargo:warning={} was not taken from this build, backtraces show addresses only",
+                    path.display()
+                );
+            }
+            table
+        }
+        None => build_table(""),
+    };
+
+    fs::write(out_dir.join("symbols.bin"), table).expect("failed to write symbol table");
+}
+
+/// Hash everything the kernel code is built from
+///
+/// The symbol table is not part of it, embedding the table of a previous
+/// build keeps the id of that build
+fn build_id(manifest_dir: &Path) -> u64 {
+    let mut files = vec![manifest_dir.join("Cargo.toml")];
+    collect_files(&manifest_dir.join("src"), &mut files);
+    // workspace lock file, pinning the dependencies
+    if let Some(workspace_dir) = manifest_dir.parent() {
+        let lock_file = workspace_dir.join("Cargo.lock");
+        println!("cargo:rerun-if-changed={}", lock_file.display());
+        files.push(lock_file);
+    }
+    files.sort();
+
+    // directories are watched for changes of any file below them
+    println!(
+        "cargo:rerun-if-changed={}",
+        manifest_dir.join("src").display()
+    );
+    println!(
+        "cargo:rerun-if-changed={}",
+        manifest_dir.join("Cargo.toml").display()
+    );
+
+    let mut hash = FNV_OFFSET;
+    for file in &files {
+        let name = file.strip_prefix(manifest_dir).unwrap_or(file);
+        hash = fnv1a(hash, name.to_string_lossy().as_bytes());
+        hash = fnv1a(hash, &fs::read(file).unwrap_or_default());
+    }
+
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| {
+            name.starts_with("CARGO_FEATURE_") || BUILD_VARS.contains(&name.as_str())
+        })
+        .collect();
+    vars.sort();
+    for (name, value) in vars {
+        hash = fnv1a(hash, name.as_bytes());
+        hash = fnv1a(hash, value.as_bytes());
+    }
+
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    if let Ok(output) = Command::new(rustc).arg("-vV").output() {
+        hash = fnv1a(hash, &output.stdout);
+    }
+
+    hash
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Build id recorded in the table header
+fn map_build_id(table: &[u8]) -> u64 {
+    u64::from_le_bytes(table[..8].try_into().unwrap())
+}
+
+/// Parse lines of `nm` output, `<addr> [size] <type> <name>`
+fn parse_line(line: &str) -> Option<(u64, &str, &str)> {
+    let mut fields = line.splitn(2, ' ');
+    let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let mut rest = fields.next()?;
+
+    // optional size column of `nm -S`
+    let (first, tail) = rest.split_once(' ')?;
+    if first.len() > 1 {
+        rest = tail;
+    }
+
+    let (kind, name) = rest.split_once(' ')?;
+    Some((addr, kind, name.trim()))
+}
+
+fn build_table(map: &str) -> Vec<u8> {
+    let mut build_id = 0;
+    let mut anchor = 0;
+    let mut symbols = Vec::new();
+
+    for (addr, kind, name) in map.lines().filter_map(parse_line) {
+        // text symbols only
+        if !matches!(kind, "t" | "T" | "W") {
+            continue;
+        }
+        // exported as `oros_symbol_anchor_<build id>`
+        let id = name
+            .strip_prefix(ANCHOR)
+            .and_then(|id| id.strip_prefix('_'))
+            .and_then(|id| u64::from_str_radix(id, 16).ok());
+        if let Some(id) = id {
+            build_id = id;
+            anchor = addr;
+        }
+        symbols.push((addr, name));
+    }
+
+    symbols.sort_by_key(|(addr, _)| *addr);
+    symbols.dedup_by_key(|(addr, _)| *addr);
+
+    let mut table = Vec::new();
+    table.extend_from_slice(&build_id.to_le_bytes());
+    table.extend_from_slice(&anchor.to_le_bytes());
+    table.extend_from_slice(&(symbols.len() as u64).to_le_bytes());
+
+    let mut names = Vec::new();
+    for (addr, name) in &symbols {
+        table.extend_from_slice(&addr.to_le_bytes());
+        table.extend_from_slice(&(names.len() as u32).to_le_bytes());
+        table.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        names.extend_from_slice(name.as_bytes());
+    }
+    table.extend_from_slice(&names);
+
+    table
+}
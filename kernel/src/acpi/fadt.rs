@@ -0,0 +1,108 @@
+//! Fixed ACPI Description Table, power management registers and the DSDT
+
+use x86_64::PhysAddr;
+
+use super::{AcpiError, AddressSpace, Bytes, GenericAddress, Sdt, Signature};
+
+/// `RESET_REG` is supported
+const FLAG_RESET_REG_SUP: u32 = 1 << 10;
+/// No fixed hardware, power management only through ACPI registers
+const FLAG_HW_REDUCED_ACPI: u32 = 1 << 20;
+/// IA-PC boot architecture flag for an 8042 keyboard controller
+const BOOT_ARCH_8042: u16 = 1 << 1;
+
+/// Fixed fields read up to the PM timer block
+const MIN_LENGTH: usize = 92;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fadt {
+    pub revision: u8,
+    /// Differentiated System Description Table with the AML code
+    pub dsdt: PhysAddr,
+    /// Interrupt of the ACPI system control interrupt
+    pub sci_interrupt: u16,
+    /// I/O port taking `acpi_enable` and `acpi_disable`, 0 if ACPI mode is
+    /// always on
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event: Option<GenericAddress>,
+    pub pm1b_event: Option<GenericAddress>,
+    pub pm1a_control: Option<GenericAddress>,
+    pub pm1b_control: Option<GenericAddress>,
+    pub pm_timer: Option<GenericAddress>,
+    /// CMOS RAM index of the century, 0 if the RTC has none
+    pub century: u8,
+    pub boot_arch: u16,
+    pub flags: u32,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    pub fn parse(sdt: &Sdt) -> Result<Self, AcpiError> {
+        sdt.expect(Signature::FADT)?;
+
+        let bytes = sdt.bytes();
+        if sdt.data.len() < MIN_LENGTH {
+            return Err(AcpiError::InvalidLength(Signature::FADT));
+        }
+
+        // 64 bit addresses of ACPI 2.0 replace the 32 bit ones if set
+        let dsdt = bytes
+            .u64(140)
+            .filter(|&addr| addr != 0)
+            .unwrap_or(bytes.u32(40).unwrap_or(0) as u64);
+
+        let register = |x_offset: usize, offset: usize, len_offset: usize| {
+            GenericAddress::parse(bytes, x_offset)
+                .or_else(|| io_register(bytes, offset, len_offset))
+        };
+
+        Ok(Self {
+            revision: sdt.revision,
+            dsdt: PhysAddr::new(dsdt),
+            sci_interrupt: bytes.u16(46).unwrap_or(0),
+            smi_command: bytes.u32(48).unwrap_or(0),
+            acpi_enable: bytes.u8(52).unwrap_or(0),
+            acpi_disable: bytes.u8(53).unwrap_or(0),
+            pm1a_event: register(148, 56, 88),
+            pm1b_event: register(160, 60, 88),
+            pm1a_control: register(172, 64, 89),
+            pm1b_control: register(184, 68, 89),
+            pm_timer: register(208, 76, 91),
+            century: bytes.u8(108).unwrap_or(0),
+            boot_arch: bytes.u16(109).unwrap_or(0),
+            flags: bytes.u32(112).unwrap_or(0),
+            reset_register: GenericAddress::parse(bytes, 116),
+            reset_value: bytes.u8(128).unwrap_or(0),
+        })
+    }
+
+    /// Reset register can be written to reboot
+    pub fn reset_supported(&self) -> bool {
+        self.flags & FLAG_RESET_REG_SUP != 0 && self.reset_register.is_some()
+    }
+
+    pub fn hardware_reduced(&self) -> bool {
+        self.flags & FLAG_HW_REDUCED_ACPI != 0
+    }
+
+    /// Machine has an 8042 keyboard controller, assumed for ACPI 1.0
+    pub fn has_8042(&self) -> bool {
+        self.revision < 2 || self.boot_arch & BOOT_ARCH_8042 != 0
+    }
+}
+
+/// Legacy I/O port block at `offset` with its length at `len_offset`
+fn io_register(bytes: Bytes, offset: usize, len_offset: usize) -> Option<GenericAddress> {
+    let port = bytes.u32(offset).filter(|&port| port != 0)?;
+    let len = bytes.u8(len_offset)?;
+    Some(GenericAddress {
+        space: AddressSpace::SystemIo,
+        bit_width: len * 8,
+        bit_offset: 0,
+        access_size: 0,
+        address: port as u64,
+    })
+}
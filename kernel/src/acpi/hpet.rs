@@ -0,0 +1,42 @@
+//! High Precision Event Timer description table
+
+use x86_64::PhysAddr;
+
+use super::{AcpiError, GenericAddress, Sdt, Signature, SDT_HEADER_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hpet {
+    /// Physical address of the HPET registers
+    pub base: PhysAddr,
+    /// Sequence number of the HPET in the machine
+    pub number: u8,
+    /// Smallest periodic tick in counter cycles
+    pub min_tick: u16,
+    /// Number of comparators (timers)
+    pub comparators: u8,
+    pub counter_64bit: bool,
+    /// Comparator 0 can replace the PIT and RTC interrupts
+    pub legacy_replacement: bool,
+    pub pci_vendor_id: u16,
+}
+
+impl Hpet {
+    pub fn parse(sdt: &Sdt) -> Result<Self, AcpiError> {
+        sdt.expect(Signature::HPET)?;
+
+        let bytes = sdt.bytes();
+        let invalid = AcpiError::InvalidLength(Signature::HPET);
+        let block_id = bytes.u32(SDT_HEADER_SIZE).ok_or(invalid)?;
+        let base = GenericAddress::parse(bytes, SDT_HEADER_SIZE + 4).ok_or(invalid)?;
+
+        Ok(Self {
+            base: PhysAddr::new(base.address),
+            number: bytes.u8(52).ok_or(invalid)?,
+            min_tick: bytes.u16(53).ok_or(invalid)?,
+            comparators: ((block_id >> 8) & 0x1f) as u8 + 1,
+            counter_64bit: block_id & (1 << 13) != 0,
+            legacy_replacement: block_id & (1 << 15) != 0,
+            pci_vendor_id: (block_id >> 16) as u16,
+        })
+    }
+}
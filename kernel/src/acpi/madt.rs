@@ -0,0 +1,181 @@
+//! Multiple APIC Description Table, the interrupt controllers of the machine
+
+use alloc::vec::Vec;
+
+use x86_64::PhysAddr;
+
+use super::{AcpiError, Bytes, Sdt, Signature, SDT_HEADER_SIZE};
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_NMI: u8 = 4;
+const ENTRY_LOCAL_APIC_ADDRESS: u8 = 5;
+
+/// MADT flag set if the machine also has 8259 PICs
+const PCAT_COMPAT: u32 = 1;
+
+/// Processor and its local APIC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApicEntry {
+    pub processor_id: u8,
+    pub apic_id: u8,
+    pub enabled: bool,
+    /// Disabled processor which can be brought online
+    pub online_capable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// First global system interrupt of the I/O APIC
+    pub gsi_base: u32,
+}
+
+/// Polarity of an interrupt input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// Default of the bus, active high for ISA
+    Conforming,
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// Default of the bus, edge triggered for ISA
+    Conforming,
+    Edge,
+    Level,
+}
+
+/// MPS INTI flags of overrides and NMI entries
+fn inti_flags(flags: u16) -> (Polarity, TriggerMode) {
+    let polarity = match flags & 0b11 {
+        0b01 => Polarity::ActiveHigh,
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::Conforming,
+    };
+    let trigger = match (flags >> 2) & 0b11 {
+        0b01 => TriggerMode::Edge,
+        0b11 => TriggerMode::Level,
+        _ => TriggerMode::Conforming,
+    };
+    (polarity, trigger)
+}
+
+/// ISA IRQ delivered to another GSI than its number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptSourceOverride {
+    pub bus: u8,
+    pub irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+}
+
+/// Local APIC input wired to the NMI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApicNmi {
+    /// ACPI processor ID, 0xff for all processors
+    pub processor_id: u8,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+    /// LINT0 or LINT1
+    pub lint: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    /// Machine has 8259 PICs, which must be masked when using the APIC
+    pub pcat_compat: bool,
+    pub processors: Vec<LocalApicEntry>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptSourceOverride>,
+    pub nmis: Vec<LocalApicNmi>,
+}
+
+impl Madt {
+    pub fn parse(sdt: &Sdt) -> Result<Self, AcpiError> {
+        sdt.expect(Signature::MADT)?;
+
+        let bytes = sdt.bytes();
+        let invalid = AcpiError::InvalidLength(Signature::MADT);
+        let mut madt = Self {
+            local_apic_address: PhysAddr::new(bytes.u32(SDT_HEADER_SIZE).ok_or(invalid)? as u64),
+            pcat_compat: bytes.u32(SDT_HEADER_SIZE + 4).ok_or(invalid)? & PCAT_COMPAT != 0,
+            processors: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+            nmis: Vec::new(),
+        };
+
+        let mut offset = SDT_HEADER_SIZE + 8;
+        while offset + 2 <= sdt.data.len() {
+            let kind = sdt.data[offset];
+            let len = sdt.data[offset + 1] as usize;
+            if len < 2 || offset + len > sdt.data.len() {
+                return Err(invalid);
+            }
+            let entry = Bytes(&sdt.data[offset..offset + len]);
+            madt.parse_entry(kind, entry).ok_or(invalid)?;
+            offset += len;
+        }
+
+        Ok(madt)
+    }
+
+    /// Add entry of type `kind`, unknown types are skipped
+    fn parse_entry(&mut self, kind: u8, entry: Bytes) -> Option<()> {
+        match kind {
+            ENTRY_LOCAL_APIC => {
+                let flags = entry.u32(4)?;
+                self.processors.push(LocalApicEntry {
+                    processor_id: entry.u8(2)?,
+                    apic_id: entry.u8(3)?,
+                    enabled: flags & 1 != 0,
+                    online_capable: flags & 2 != 0,
+                });
+            }
+            ENTRY_IO_APIC => self.io_apics.push(IoApicEntry {
+                id: entry.u8(2)?,
+                address: PhysAddr::new(entry.u32(4)? as u64),
+                gsi_base: entry.u32(8)?,
+            }),
+            ENTRY_SOURCE_OVERRIDE => {
+                let (polarity, trigger) = inti_flags(entry.u16(8)?);
+                self.overrides.push(InterruptSourceOverride {
+                    bus: entry.u8(2)?,
+                    irq: entry.u8(3)?,
+                    gsi: entry.u32(4)?,
+                    polarity,
+                    trigger,
+                });
+            }
+            ENTRY_LOCAL_APIC_NMI => {
+                let (polarity, trigger) = inti_flags(entry.u16(3)?);
+                self.nmis.push(LocalApicNmi {
+                    processor_id: entry.u8(2)?,
+                    polarity,
+                    trigger,
+                    lint: entry.u8(5)?,
+                });
+            }
+            ENTRY_LOCAL_APIC_ADDRESS => {
+                self.local_apic_address = PhysAddr::new(entry.u64(4)?);
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    /// I/O APIC handling `gsi`
+    pub fn io_apic_for(&self, gsi: u32) -> Option<&IoApicEntry> {
+        self.io_apics
+            .iter()
+            .filter(|io_apic| io_apic.gsi_base <= gsi)
+            .max_by_key(|io_apic| io_apic.gsi_base)
+    }
+}
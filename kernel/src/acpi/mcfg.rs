@@ -0,0 +1,62 @@
+//! PCI Express memory mapped configuration space description table
+
+use alloc::vec::Vec;
+
+use x86_64::PhysAddr;
+
+use super::{AcpiError, Bytes, Sdt, Signature, SDT_HEADER_SIZE};
+
+/// Entries follow 8 reserved bytes after the header
+const ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 8;
+const ENTRY_SIZE: usize = 16;
+
+/// Configuration space of the buses of one PCI segment group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McfgEntry {
+    /// Address of bus 0, even if `start_bus` is higher
+    pub base: PhysAddr,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgEntry {
+    /// Configuration space of function `function` of `device` on `bus`
+    pub fn function_address(&self, bus: u8, device: u8, function: u8) -> Option<PhysAddr> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+        let offset = ((bus as u64) << 20) | ((device as u64) << 15) | ((function as u64) << 12);
+        Some(self.base + offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mcfg {
+    pub entries: Vec<McfgEntry>,
+}
+
+impl Mcfg {
+    pub fn parse(sdt: &Sdt) -> Result<Self, AcpiError> {
+        sdt.expect(Signature::MCFG)?;
+
+        let invalid = AcpiError::InvalidLength(Signature::MCFG);
+        let entries = sdt
+            .data
+            .get(ENTRIES_OFFSET..)
+            .ok_or(invalid)?
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let entry = Bytes(entry);
+                McfgEntry {
+                    base: PhysAddr::new(entry.u64(0).unwrap_or(0)),
+                    segment_group: entry.u16(8).unwrap_or(0),
+                    start_bus: entry.u8(10).unwrap_or(0),
+                    end_bus: entry.u8(11).unwrap_or(0),
+                }
+            })
+            .collect();
+
+        Ok(Self { entries })
+    }
+}
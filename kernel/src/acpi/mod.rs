@@ -0,0 +1,506 @@
+//! ACPI tables describing the interrupt controllers, timers, power
+//! management and PCIe configuration space of the machine
+//!
+//! The bootloader passes the physical address of the RSDP, which points at
+//! the XSDT (or the RSDT on ACPI 1.0 machines) listing all other tables.
+//! Tables are read through the physical memory mapping and checked against
+//! their checksums before they are parsed
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+
+use alloc::vec::Vec;
+use core::{fmt, mem, ptr, slice, str};
+
+use conquer_once::spin::OnceCell;
+use x86_64::{instructions::port::Port, PhysAddr};
+
+use crate::memory::{self, mmio::MmioRegion};
+
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::Madt;
+pub use mcfg::Mcfg;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// Bytes covered by the checksum of ACPI 1.0 RSDPs
+const RSDP_V1_LENGTH: usize = 20;
+const RSDP_V2_LENGTH: usize = 36;
+
+/// Size of the header every system description table starts with
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// Tables found at boot, None if the machine has no ACPI
+static TABLES: OnceCell<AcpiTables> = OnceCell::uninit();
+
+/// Errors found while reading ACPI tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// Bootloader found no RSDP
+    NoRsdp,
+    InvalidSignature(Signature),
+    InvalidChecksum(Signature),
+    /// Table is shorter than its header or fixed fields
+    InvalidLength(Signature),
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AcpiError::NoRsdp => write!(f, "no RSDP"),
+            AcpiError::InvalidSignature(signature) => {
+                write!(f, "unexpected table signature {}", signature)
+            }
+            AcpiError::InvalidChecksum(signature) => write!(f, "{} checksum mismatch", signature),
+            AcpiError::InvalidLength(signature) => write!(f, "{} too short", signature),
+        }
+    }
+}
+
+/// Four character table signature, e.g. `APIC` for the MADT
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub [u8; 4]);
+
+impl Signature {
+    pub const RSDP: Signature = Signature(*b"RSDP");
+    pub const XSDT: Signature = Signature(*b"XSDT");
+    pub const RSDT: Signature = Signature(*b"RSDT");
+    pub const MADT: Signature = Signature(*b"APIC");
+    pub const FADT: Signature = Signature(*b"FACP");
+    pub const HPET: Signature = Signature(*b"HPET");
+    pub const MCFG: Signature = Signature(*b"MCFG");
+    pub const DSDT: Signature = Signature(*b"DSDT");
+
+    pub fn as_str(&self) -> &str {
+        str::from_utf8(&self.0).unwrap_or("????")
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// Register location in one of the ACPI address spaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    /// Size of the structure in tables
+    pub const SIZE: usize = 12;
+
+    fn parse(bytes: Bytes, offset: usize) -> Option<Self> {
+        let address = bytes.u64(offset + 4)?;
+        if address == 0 {
+            return None;
+        }
+        Some(Self {
+            space: AddressSpace::from(bytes.u8(offset)?),
+            bit_width: bytes.u8(offset + 1)?,
+            bit_offset: bytes.u8(offset + 2)?,
+            access_size: bytes.u8(offset + 3)?,
+            address,
+        })
+    }
+
+    /// Bytes accessed at once, from the access size or the register width
+    fn access_bytes(&self) -> u8 {
+        match self.access_size {
+            1..=4 => 1 << (self.access_size - 1),
+            _ => (self.bit_width / 8).clamp(1, 8),
+        }
+    }
+
+    /// Map a system memory register uncached for one access
+    ///
+    /// Device registers must not go through the cached physical memory
+    /// mapping. Needs the memory globals, None if the VMM has no room
+    fn map_mmio(&self) -> Option<MmioRegion> {
+        memory::map_mmio(PhysAddr::new(self.address), mem::size_of::<u64>()).ok()
+    }
+
+    /// Read the register, only system memory and I/O registers are supported
+    ///
+    /// # Safety
+    ///
+    /// Reading the register must have no unwanted side effects
+    pub unsafe fn read(&self) -> Option<u64> {
+        let bytes = self.access_bytes();
+        match self.space {
+            AddressSpace::SystemIo => {
+                let port = self.address as u16;
+                Some(match bytes {
+                    1 => Port::<u8>::new(port).read() as u64,
+                    2 => Port::<u16>::new(port).read() as u64,
+                    _ => Port::<u32>::new(port).read() as u64,
+                })
+            }
+            AddressSpace::SystemMemory => {
+                let mmio = self.map_mmio()?;
+                Some(match bytes {
+                    1 => mmio.read8(0) as u64,
+                    2 => mmio.read16(0) as u64,
+                    4 => mmio.read32(0) as u64,
+                    _ => mmio.read64(0),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Write the register, returns false for unsupported address spaces
+    ///
+    /// # Safety
+    ///
+    /// Writing the register must not break memory safety, e.g. by resetting
+    /// the machine in the middle of an update
+    pub unsafe fn write(&self, value: u64) -> bool {
+        let bytes = self.access_bytes();
+        match self.space {
+            AddressSpace::SystemIo => {
+                let port = self.address as u16;
+                match bytes {
+                    1 => Port::<u8>::new(port).write(value as u8),
+                    2 => Port::<u16>::new(port).write(value as u16),
+                    _ => Port::<u32>::new(port).write(value as u32),
+                }
+                true
+            }
+            AddressSpace::SystemMemory => {
+                let mmio = match self.map_mmio() {
+                    Some(mmio) => mmio,
+                    None => return false,
+                };
+                match bytes {
+                    1 => mmio.write8(0, value as u8),
+                    2 => mmio.write16(0, value as u16),
+                    4 => mmio.write32(0, value as u32),
+                    _ => mmio.write64(0, value),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfig,
+    Other(u8),
+}
+
+impl From<u8> for AddressSpace {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfig,
+            other => AddressSpace::Other(other),
+        }
+    }
+}
+
+/// Little endian reads from a table, None past its end
+#[derive(Debug, Clone, Copy)]
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl<'a> Bytes<'a> {
+    pub fn u8(&self, offset: usize) -> Option<u8> {
+        self.0.get(offset).copied()
+    }
+
+    pub fn u16(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(self.array(offset)?))
+    }
+
+    pub fn u32(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(self.array(offset)?))
+    }
+
+    pub fn u64(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(self.array(offset)?))
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.0.get(offset..offset + N)?.try_into().ok()
+    }
+}
+
+/// System description table, header and body
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    pub signature: Signature,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    /// Whole table including the header
+    pub data: &'static [u8],
+}
+
+impl Sdt {
+    /// Read table at physical address `phys`
+    ///
+    /// # Safety
+    ///
+    /// `phys` must point at a table header in mapped physical memory
+    pub unsafe fn at(phys: PhysAddr) -> Result<Self, AcpiError> {
+        let header = slice::from_raw_parts(phys_ptr(phys), SDT_HEADER_SIZE);
+        let length = Bytes(header).u32(4).unwrap_or(0) as usize;
+        if length < SDT_HEADER_SIZE {
+            return Err(AcpiError::InvalidLength(signature(header)));
+        }
+        Self::from_bytes(slice::from_raw_parts(phys_ptr(phys), length))
+    }
+
+    /// Parse table in `data`, which must hold the whole table
+    pub fn from_bytes(data: &'static [u8]) -> Result<Self, AcpiError> {
+        if data.len() < SDT_HEADER_SIZE {
+            return Err(AcpiError::InvalidLength(signature(data)));
+        }
+
+        let signature = signature(data);
+        let length = Bytes(data).u32(4).unwrap_or(0) as usize;
+        if length < SDT_HEADER_SIZE || length > data.len() {
+            return Err(AcpiError::InvalidLength(signature));
+        }
+
+        let data = &data[..length];
+        if checksum(data) != 0 {
+            return Err(AcpiError::InvalidChecksum(signature));
+        }
+
+        let mut oem_id = [0; 6];
+        oem_id.copy_from_slice(&data[10..16]);
+
+        Ok(Self {
+            signature,
+            revision: data[8],
+            oem_id,
+            data,
+        })
+    }
+
+    /// Check the table has signature `expected`
+    pub fn expect(&self, expected: Signature) -> Result<(), AcpiError> {
+        if self.signature == expected {
+            Ok(())
+        } else {
+            Err(AcpiError::InvalidSignature(self.signature))
+        }
+    }
+
+    pub fn bytes(&self) -> Bytes<'static> {
+        Bytes(self.data)
+    }
+
+    /// Table contents after the header
+    pub fn body(&self) -> &'static [u8] {
+        &self.data[SDT_HEADER_SIZE..]
+    }
+}
+
+fn signature(data: &[u8]) -> Signature {
+    let mut signature = [b'?'; 4];
+    let len = data.len().min(4);
+    signature[..len].copy_from_slice(&data[..len]);
+    Signature(signature)
+}
+
+/// Sum of all bytes, 0 for valid tables
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn phys_ptr(phys: PhysAddr) -> *const u8 {
+    (memory::phys_mem_offset() + phys.as_u64()).as_ptr()
+}
+
+/// Root System Description Pointer
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub rsdt_addr: PhysAddr,
+    /// XSDT, only present since ACPI 2.0
+    pub xsdt_addr: Option<PhysAddr>,
+}
+
+impl Rsdp {
+    /// Read and validate the RSDP at `phys`
+    ///
+    /// # Safety
+    ///
+    /// `phys` must be mapped physical memory
+    pub unsafe fn at(phys: PhysAddr) -> Result<Self, AcpiError> {
+        let v1 = slice::from_raw_parts(phys_ptr(phys), RSDP_V1_LENGTH);
+        if &v1[..8] != RSDP_SIGNATURE {
+            return Err(AcpiError::InvalidSignature(Signature::RSDP));
+        }
+        let revision = v1[15];
+        let length = if revision >= 2 {
+            let length = ptr::read_unaligned(phys_ptr(phys + 20u64) as *const u32);
+            (length as usize).max(RSDP_V2_LENGTH)
+        } else {
+            RSDP_V1_LENGTH
+        };
+        Self::from_bytes(slice::from_raw_parts(phys_ptr(phys), length))
+    }
+
+    /// Parse RSDP in `data`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, AcpiError> {
+        if data.len() < RSDP_V1_LENGTH {
+            return Err(AcpiError::InvalidLength(Signature::RSDP));
+        }
+        if &data[..8] != RSDP_SIGNATURE {
+            return Err(AcpiError::InvalidSignature(Signature::RSDP));
+        }
+        if checksum(&data[..RSDP_V1_LENGTH]) != 0 {
+            return Err(AcpiError::InvalidChecksum(Signature::RSDP));
+        }
+
+        let bytes = Bytes(data);
+        let revision = data[15];
+        let mut oem_id = [0; 6];
+        oem_id.copy_from_slice(&data[9..15]);
+
+        let mut xsdt_addr = None;
+        if revision >= 2 {
+            let length = bytes.u32(20).unwrap_or(0) as usize;
+            if length < RSDP_V2_LENGTH || length > data.len() {
+                return Err(AcpiError::InvalidLength(Signature::RSDP));
+            }
+            if checksum(&data[..length]) != 0 {
+                return Err(AcpiError::InvalidChecksum(Signature::RSDP));
+            }
+            xsdt_addr = bytes.u64(24).filter(|&addr| addr != 0).map(PhysAddr::new);
+        }
+
+        Ok(Self {
+            revision,
+            oem_id,
+            rsdt_addr: PhysAddr::new(bytes.u32(16).unwrap_or(0) as u64),
+            xsdt_addr,
+        })
+    }
+}
+
+/// Parsed tables, those not understood by the kernel are kept as addresses
+#[derive(Debug)]
+pub struct AcpiTables {
+    pub rsdp: Rsdp,
+    /// Every table listed by the XSDT or RSDT with its address
+    pub tables: Vec<(Signature, PhysAddr)>,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+    pub mcfg: Option<Mcfg>,
+}
+
+impl AcpiTables {
+    /// Find the tables listed by the RSDP at `rsdp_addr`
+    ///
+    /// Invalid tables other than the root table are skipped
+    ///
+    /// # Safety
+    ///
+    /// `rsdp_addr` must be the RSDP address given by the firmware
+    pub unsafe fn parse(rsdp_addr: PhysAddr) -> Result<Self, AcpiError> {
+        let rsdp = Rsdp::at(rsdp_addr)?;
+
+        let (root, entry_size) = match rsdp.xsdt_addr {
+            Some(xsdt) => (Sdt::at(xsdt)?, 8),
+            None => (Sdt::at(rsdp.rsdt_addr)?, 4),
+        };
+        root.expect(if entry_size == 8 {
+            Signature::XSDT
+        } else {
+            Signature::RSDT
+        })?;
+
+        let mut tables = Vec::new();
+        let body = Bytes(root.body());
+        for i in 0..root.body().len() / entry_size {
+            let addr = match entry_size {
+                8 => body.u64(i * 8),
+                _ => body.u32(i * 4).map(u64::from),
+            };
+            let addr = match addr {
+                Some(addr) if addr != 0 => PhysAddr::new(addr),
+                _ => continue,
+            };
+            let header = slice::from_raw_parts(phys_ptr(addr), 4);
+            tables.push((signature(header), addr));
+        }
+
+        let mut acpi = Self {
+            rsdp,
+            tables,
+            madt: None,
+            fadt: None,
+            hpet: None,
+            mcfg: None,
+        };
+        acpi.madt = acpi.load(Signature::MADT, Madt::parse);
+        acpi.fadt = acpi.load(Signature::FADT, Fadt::parse);
+        acpi.hpet = acpi.load(Signature::HPET, Hpet::parse);
+        acpi.mcfg = acpi.load(Signature::MCFG, Mcfg::parse);
+        Ok(acpi)
+    }
+
+    /// Address of the first table with `signature`
+    pub fn find(&self, signature: Signature) -> Option<PhysAddr> {
+        self.tables
+            .iter()
+            .find(|(s, _)| *s == signature)
+            .map(|(_, addr)| *addr)
+    }
+
+    /// Read and validate the table with `signature`
+    pub fn table(&self, signature: Signature) -> Option<Sdt> {
+        let sdt = unsafe { Sdt::at(self.find(signature)?) }.ok()?;
+        Some(sdt)
+    }
+
+    fn load<T>(&self, signature: Signature, parse: fn(&Sdt) -> Result<T, AcpiError>) -> Option<T> {
+        self.table(signature).and_then(|sdt| parse(&sdt).ok())
+    }
+}
+
+/// Read the ACPI tables found by the bootloader, after `memory::init_globals`
+///
+/// Machines without ACPI are left without tables
+pub fn init(rsdp_addr: Option<u64>) -> Result<&'static AcpiTables, AcpiError> {
+    let rsdp_addr = rsdp_addr.ok_or(AcpiError::NoRsdp)?;
+    let acpi = unsafe { AcpiTables::parse(PhysAddr::new(rsdp_addr))? };
+    TABLES
+        .try_init_once(|| acpi)
+        .expect("acpi::init should only be called once");
+    Ok(tables())
+}
+
+/// Tables read at boot, None without ACPI
+pub fn try_tables() -> Option<&'static AcpiTables> {
+    TABLES.try_get().ok()
+}
+
+/// Tables read at boot, panics without ACPI
+pub fn tables() -> &'static AcpiTables {
+    try_tables().expect("ACPI tables not initialized")
+}
@@ -0,0 +1,152 @@
+//! Stack unwinding along the frame pointer chain
+//!
+//! The kernel is built with frame pointers, every function saves the
+//! caller's `rbp` next to its return address:
+//!
+//! ```text
+//! [rbp + 8] return address
+//! [rbp]     rbp of the caller
+//! ```
+//!
+//! Return addresses are resolved to function names if a symbol table was
+//! embedded, see `symbols`
+
+pub mod symbols;
+
+use core::arch::asm;
+use core::fmt;
+
+use x86_64::{structures::paging::Translate, VirtAddr};
+
+use crate::memory::{self, address_space::KERNEL_SPACE_START};
+
+/// Most frames kept in a backtrace
+pub const MAX_FRAMES: usize = 32;
+
+/// Return addresses of the frames on the stack, innermost first
+///
+/// Kept inline, so it can be taken without the heap in panic and
+/// exception handlers
+#[derive(Clone, Copy)]
+pub struct Backtrace {
+    frames: [u64; MAX_FRAMES],
+    len: usize,
+    /// First frame is the interrupted instruction, not a return address
+    starts_at_rip: bool,
+}
+
+impl Backtrace {
+    /// Walk the stack of the caller
+    #[inline(never)]
+    pub fn capture() -> Self {
+        let rbp: u64;
+        unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack)) };
+
+        let mut backtrace = Self::empty();
+        backtrace.walk(rbp);
+        backtrace
+    }
+
+    /// Walk the stack of interrupted code, starting at instruction `rip`
+    /// with frame pointer `rbp`
+    pub fn from_frame(rip: VirtAddr, rbp: u64) -> Self {
+        let mut backtrace = Self::empty();
+        backtrace.starts_at_rip = true;
+        backtrace.push(rip.as_u64());
+        backtrace.walk(rbp);
+        backtrace
+    }
+
+    fn empty() -> Self {
+        Self {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+            starts_at_rip: false,
+        }
+    }
+
+    fn push(&mut self, addr: u64) -> bool {
+        if self.len == MAX_FRAMES {
+            return false;
+        }
+        self.frames[self.len] = addr;
+        self.len += 1;
+        true
+    }
+
+    /// Follow saved frame pointers until one is null or not readable
+    fn walk(&mut self, mut rbp: u64) {
+        while is_frame(rbp) {
+            let frame = rbp as *const u64;
+            let (caller_rbp, return_addr) = unsafe { (*frame, *frame.add(1)) };
+
+            if return_addr == 0 || !self.push(return_addr) {
+                break;
+            }
+            // callers are higher up the stack, anything else is corrupt
+            if caller_rbp <= rbp {
+                break;
+            }
+            rbp = caller_rbp;
+        }
+    }
+
+    /// Return addresses, innermost first
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.len]
+    }
+}
+
+/// Check `rbp` points at a readable saved frame
+fn is_frame(rbp: u64) -> bool {
+    if rbp == 0 || rbp % 8 != 0 || rbp < KERNEL_SPACE_START {
+        return false;
+    }
+
+    let end = match rbp.checked_add(15) {
+        Some(end) => end,
+        None => return false,
+    };
+    let (start, end) = match (VirtAddr::try_new(rbp), VirtAddr::try_new(end)) {
+        (Ok(start), Ok(end)) => (start, end),
+        _ => return false,
+    };
+
+    // without page tables at hand only the address checks above are done
+    let phys_mem_offset = match memory::try_phys_mem_offset() {
+        Some(phys_mem_offset) => phys_mem_offset,
+        None => return true,
+    };
+    let mapper = unsafe {
+        x86_64::structures::paging::OffsetPageTable::new(
+            memory::active_lvl_4_table(phys_mem_offset),
+            phys_mem_offset,
+        )
+    };
+    mapper.translate_addr(start).is_some() && mapper.translate_addr(end).is_some()
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Backtrace:")?;
+        for (i, &addr) in self.frames().iter().enumerate() {
+            write!(f, "\n  {:2}: {:#018x}", i, addr)?;
+            // return addresses point after the call, look up the call itself
+            let lookup = if i == 0 && self.starts_at_rip {
+                addr
+            } else {
+                addr - 1
+            };
+            if let Some(symbol) = symbols::resolve(VirtAddr::new_truncate(lookup)) {
+                write!(f, "  {}+{:#x}", symbol.name, addr - symbol.addr.as_u64())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.frames()).finish()
+    }
+}
@@ -0,0 +1,119 @@
+//! Kernel symbol table embedded by `build.rs`
+//!
+//! The table lives in `.data`, after the code, so embedding it doesn't
+//! move any function and the addresses taken from the previous build stay
+//! valid. It is only read through raw pointers, which keeps its size out of
+//! the generated code.
+//!
+//! A table taken from a build with another id is ignored, its addresses
+//! belong to other code
+
+use core::ptr::{self, addr_of};
+
+use x86_64::VirtAddr;
+
+const TABLE_LEN: usize = include_bytes!(concat!(env!("OUT_DIR"), "/symbols.bin")).len();
+
+#[link_section = ".data.oros_symbols"]
+static mut SYMBOL_TABLE: [u8; TABLE_LEN] =
+    *include_bytes!(concat!(env!("OUT_DIR"), "/symbols.bin"));
+
+const HEADER_SIZE: usize = 24;
+const ENTRY_SIZE: usize = 16;
+
+/// Id of this build, hashed by `build.rs` from what the code is built from
+pub const BUILD_ID: &str = env!("OROS_BUILD_ID");
+
+/// Function with a known link address, its runtime address gives the
+/// offset the kernel was loaded at
+///
+/// Exported with the build id in its name, so symbol maps name the build
+/// they were taken from
+#[export_name = concat!("oros_symbol_anchor_", env!("OROS_BUILD_ID"))]
+#[inline(never)]
+pub extern "C" fn oros_symbol_anchor() {}
+
+/// Function an address belongs to
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub name: &'static str,
+    /// Runtime address of the first instruction
+    pub addr: VirtAddr,
+}
+
+fn read_u64(offset: usize) -> u64 {
+    unsafe { ptr::read_unaligned(table_ptr().add(offset) as *const u64) }
+}
+
+fn read_u32(offset: usize) -> u32 {
+    unsafe { ptr::read_unaligned(table_ptr().add(offset) as *const u32) }
+}
+
+fn table_ptr() -> *const u8 {
+    addr_of!(SYMBOL_TABLE) as *const u8
+}
+
+/// Whether the table was taken from this build
+pub fn is_current() -> bool {
+    u64::from_str_radix(BUILD_ID, 16) == Ok(read_u64(0))
+}
+
+/// Number of symbols in the table, 0 if none was embedded or the table
+/// was taken from another build
+pub fn count() -> usize {
+    if !is_current() {
+        return 0;
+    }
+    read_u64(16) as usize
+}
+
+/// Difference between runtime and link addresses
+fn load_offset() -> u64 {
+    let anchor = read_u64(8);
+    (oros_symbol_anchor as *const () as usize as u64).wrapping_sub(anchor)
+}
+
+fn entry_addr(index: usize) -> u64 {
+    read_u64(HEADER_SIZE + index * ENTRY_SIZE)
+}
+
+fn entry_name(index: usize) -> &'static str {
+    let entry = HEADER_SIZE + index * ENTRY_SIZE;
+    let offset = read_u32(entry + 8) as usize;
+    let len = read_u32(entry + 12) as usize;
+    let names = HEADER_SIZE + count() * ENTRY_SIZE;
+
+    let bytes = unsafe { core::slice::from_raw_parts(table_ptr().add(names + offset), len) };
+    // names were written from strings by build.rs
+    core::str::from_utf8(bytes).unwrap_or("<invalid symbol>")
+}
+
+/// Find the function containing `addr`
+///
+/// Returns the closest symbol at or below the address, None without a
+/// symbol table or below the first symbol
+pub fn resolve(addr: VirtAddr) -> Option<Symbol> {
+    let count = count();
+    if count == 0 {
+        return None;
+    }
+
+    let link_addr = addr.as_u64().wrapping_sub(load_offset());
+
+    // last entry at or below the address
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if entry_addr(mid) <= link_addr {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let index = low.checked_sub(1)?;
+
+    Some(Symbol {
+        name: entry_name(index),
+        addr: VirtAddr::new_truncate(entry_addr(index).wrapping_add(load_offset())),
+    })
+}
@@ -0,0 +1,51 @@
+//! Kernel command line
+//!
+//! The bootloader passes no command line, it is read from the QEMU
+//! firmware configuration file `FW_CFG_FILE` instead. Options are
+//! whitespace separated `key=value` pairs, e.g.
+//! `-fw_cfg name=opt/oros/cmdline,string=interrupt_controller=pic`
+
+use alloc::string::String;
+
+use conquer_once::spin::OnceCell;
+
+use crate::port::fw_cfg;
+
+/// Firmware configuration file holding the command line
+pub const FW_CFG_FILE: &str = "opt/oros/cmdline";
+/// Longest command line read, the rest is ignored
+const MAX_LEN: usize = 1024;
+
+static CMDLINE: OnceCell<String> = OnceCell::uninit();
+
+/// Read the command line, empty without the firmware configuration file
+///
+/// Needs the heap
+pub fn init() {
+    CMDLINE.init_once(|| {
+        let mut buf = [0; MAX_LEN];
+        let len = fw_cfg::read_file(FW_CFG_FILE, &mut buf).unwrap_or(0);
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    });
+}
+
+/// Command line read at boot, empty before `init`
+pub fn get() -> &'static str {
+    CMDLINE.get().map_or("", String::as_str)
+}
+
+/// Value of option `key` on the command line read at boot
+pub fn option(key: &str) -> Option<&'static str> {
+    find_option(get(), key)
+}
+
+/// Value of option `key` in `cmdline`, the last one if given more than once
+pub fn find_option<'a>(cmdline: &'a str, key: &str) -> Option<&'a str> {
+    cmdline
+        .split_whitespace()
+        .rev()
+        .filter_map(|option| option.split_once('='))
+        .filter(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+        .next()
+}
@@ -2,11 +2,12 @@ use bootloader_api::BootInfo;
 use x86_64::instructions;
 use x86_64::VirtAddr;
 
-use crate::screen;
+use crate::interrupts::apic::ApicConfig;
 use crate::{
-    interrupts,
-    memory::{self, allocator, frame},
+    acpi, interrupts,
+    memory::{self, bitmap::BitmapFrameAllocator},
 };
+use crate::{cmdline, time};
 
 pub fn init(boot_info: &'static mut BootInfo) {
     // initialize interrupts and GDT
@@ -14,6 +15,9 @@ pub fn init(boot_info: &'static mut BootInfo) {
     interrupts::gdt::init();
     unsafe { interrupts::pic::PICS.lock().initialize() };
 
+    // tick at a known rate before interrupts arrive
+    time::init();
+
     // enable interrupts
     instructions::interrupts::enable();
 
@@ -25,10 +29,52 @@ pub fn init(boot_info: &'static mut BootInfo) {
     let mut mapper = unsafe { memory::init(phys_mem_offset_addr) };
     // allocator
     let mut frame_allocator =
-        unsafe { frame::BootInfoFrameAllocator::init(&boot_info.memory_regions) };
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_regions, phys_mem_offset_addr) };
 
     // heap allocatotion init
     memory::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
 
+    // boot options, kept on the heap
+    cmdline::init();
+
+    // contiguous physical memory pool
+    memory::init_buddy(&mut frame_allocator, phys_mem_offset_addr);
+
+    // reference counts of frames shared copy-on-write
+    memory::cow::init(&mut frame_allocator, phys_mem_offset_addr);
+
+    // kernel half of the page tables, shared by all address spaces
+    memory::address_space::init(&mut mapper, &mut frame_allocator);
+
+    // reserve virtual memory in use
+    memory::init_vmm(
+        &mut mapper,
+        &boot_info.memory_regions,
+        boot_info.framebuffer.as_ref(),
+    );
+
+    // keep mapper and frame allocator to map memory after boot
+    memory::init_globals(mapper, frame_allocator);
+
+    // move interrupt stacks to stacks with guard pages
+    interrupts::gdt::init_stacks();
+
+    // find the ACPI tables describing the machine
+    let acpi = acpi::init(boot_info.rsdp_addr.into_option()).ok();
+
+    // read time from the TSC or HPET instead of counting ticks
+    time::init_clock(acpi.and_then(|acpi| acpi.hpet.as_ref()));
+
+    // read the date kept by the RTC
+    let fadt = acpi.and_then(|acpi| acpi.fadt.as_ref());
+    time::init_wall_clock(fadt.map_or(0, |fadt| fadt.century));
+
+    // route hardware interrupts through the APIC if there is one
+    let apic_config = acpi
+        .and_then(|acpi| acpi.madt.as_ref())
+        .and_then(ApicConfig::from_madt)
+        .unwrap_or_default();
+    interrupts::controller::init(interrupts::controller::preferred(), &apic_config);
+
     // initialize memory'
 }
@@ -0,0 +1,370 @@
+//! Local APIC and I/O APIC, the interrupt controllers replacing the 8259 PICs
+//!
+//! Every CPU has a local APIC receiving interrupts and taking their end of
+//! interrupt. External interrupts enter through the I/O APIC, whose
+//! redirection table maps each input (global system interrupt, GSI) to a
+//! vector and destination local APIC
+
+use alloc::vec::Vec;
+use core::arch::x86_64::__cpuid;
+
+use conquer_once::spin::OnceCell;
+use x86_64::{registers::model_specific::Msr, PhysAddr};
+
+use super::pic::InterruptIndex;
+use crate::acpi::madt::{InterruptSourceOverride, Madt, Polarity, TriggerMode};
+use crate::memory::{self, mmio::MmioRegion, vmm::VmmError};
+use crate::serial_println;
+
+/// Model specific register holding the local APIC base and enable bit
+const IA32_APIC_BASE: Msr = Msr::new(0x1b);
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// I/O APIC address on PCs, used unless ACPI reports another one
+pub const DEFAULT_IO_APIC_BASE: u64 = 0xfec0_0000;
+
+/// Vector of spurious interrupts, which must not be acknowledged
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+// local APIC registers, offsets into its MMIO page
+const LAPIC_ID: usize = 0x20;
+const LAPIC_VERSION: usize = 0x30;
+const LAPIC_TPR: usize = 0x80;
+const LAPIC_EOI: usize = 0xb0;
+const LAPIC_SVR: usize = 0xf0;
+const LAPIC_ESR: usize = 0x280;
+const LAPIC_ICR_LOW: usize = 0x300;
+const LAPIC_ICR_HIGH: usize = 0x310;
+const LAPIC_LVT_LINT0: usize = 0x350;
+const LAPIC_LVT_LINT1: usize = 0x360;
+const LAPIC_LVT_ERROR: usize = 0x370;
+
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+
+const ICR_DELIVERY_NMI: u32 = 0b100 << 8;
+const ICR_PENDING: u32 = 1 << 12;
+const ICR_ASSERT: u32 = 1 << 14;
+
+// I/O APIC registers, accessed indirectly through select and window
+const IOAPIC_SELECT: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_ID: u32 = 0x00;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+/// ISA IRQs routed through the I/O APIC
+pub const ISA_IRQS: [(u8, InterruptIndex); 4] = [
+    (0, InterruptIndex::Timer),
+    (1, InterruptIndex::Keyboard),
+    (4, InterruptIndex::Serial),
+    (8, InterruptIndex::Rtc),
+];
+
+/// ISA IRQ connected to another I/O APIC input than its number, or not
+/// edge triggered and active high as ISA interrupts normally are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+/// Overrides found on PCs without MADT, the PIT is wired to input 2
+pub const DEFAULT_OVERRIDES: [IrqOverride; 1] = [IrqOverride {
+    irq: 0,
+    gsi: 2,
+    active_low: false,
+    level_triggered: false,
+}];
+
+/// Where the I/O APIC is and how ISA IRQs are wired to it
+#[derive(Debug, Clone)]
+pub struct ApicConfig {
+    pub io_apic_base: PhysAddr,
+    /// First GSI handled by the I/O APIC
+    pub gsi_base: u32,
+    pub overrides: Vec<IrqOverride>,
+}
+
+impl Default for ApicConfig {
+    fn default() -> Self {
+        Self {
+            io_apic_base: PhysAddr::new(DEFAULT_IO_APIC_BASE),
+            gsi_base: 0,
+            overrides: DEFAULT_OVERRIDES.to_vec(),
+        }
+    }
+}
+
+impl ApicConfig {
+    /// Config of the I/O APIC handling the ISA IRQs, None if the MADT lists
+    /// no I/O APIC
+    pub fn from_madt(madt: &Madt) -> Option<Self> {
+        let io_apic = madt.io_apic_for(0)?;
+        let overrides = madt
+            .overrides
+            .iter()
+            .filter(|o| o.bus == 0)
+            .map(IrqOverride::from)
+            .collect();
+
+        Some(Self {
+            io_apic_base: io_apic.address,
+            gsi_base: io_apic.gsi_base,
+            overrides,
+        })
+    }
+}
+
+impl From<&InterruptSourceOverride> for IrqOverride {
+    fn from(o: &InterruptSourceOverride) -> Self {
+        // conforming means ISA defaults, active high and edge triggered
+        Self {
+            irq: o.irq,
+            gsi: o.gsi,
+            active_low: o.polarity == Polarity::ActiveLow,
+            level_triggered: o.trigger == TriggerMode::Level,
+        }
+    }
+}
+
+static LOCAL_APIC: OnceCell<LocalApic> = OnceCell::uninit();
+static IO_APIC: OnceCell<IoApic> = OnceCell::uninit();
+
+/// Check CPUID for an on-chip local APIC
+pub fn is_supported() -> bool {
+    let features = unsafe { __cpuid(1) };
+    features.edx & (1 << 9) != 0
+}
+
+/// Local APIC of the boot CPU, None until `init`
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LOCAL_APIC.try_get().ok()
+}
+
+/// I/O APIC, None until `init`
+pub fn io_apic() -> Option<&'static IoApic> {
+    IO_APIC.try_get().ok()
+}
+
+/// Enable the local APIC and route the ISA IRQs through the I/O APIC
+///
+/// The 8259 PICs must be masked by the caller, interrupts should be
+/// disabled while switching
+pub fn init(config: &ApicConfig) -> Result<(), VmmError> {
+    let local_apic = LocalApic::map()?;
+    let io_apic = IoApic::map(config.io_apic_base, config.gsi_base)?;
+
+    local_apic.enable();
+
+    let destination = local_apic.id();
+    for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.max_entries() {
+        io_apic.mask(gsi);
+    }
+    for (irq, index) in ISA_IRQS {
+        // overrides come from firmware, one to a GSI outside the redirection
+        // table is ignored
+        let irq_override = config.overrides.iter().find(|o| o.irq == irq).filter(|o| {
+            let handled = io_apic.handles(o.gsi);
+            if !handled {
+                serial_println!(
+                    "WARNING: ignoring override of IRQ {} to GSI {}, not handled by the I/O APIC",
+                    irq,
+                    o.gsi
+                );
+            }
+            handled
+        });
+        let (gsi, flags) = match irq_override {
+            Some(o) => {
+                let mut flags = 0;
+                if o.active_low {
+                    flags |= REDIRECTION_ACTIVE_LOW;
+                }
+                if o.level_triggered {
+                    flags |= REDIRECTION_LEVEL;
+                }
+                (o.gsi, flags)
+            }
+            None => (irq as u32, 0),
+        };
+        if !io_apic.handles(gsi) {
+            serial_println!(
+                "WARNING: IRQ {} not routed, GSI {} not handled by the I/O APIC",
+                irq,
+                gsi
+            );
+            continue;
+        }
+        io_apic.route(gsi, index.into(), destination, flags);
+    }
+
+    LOCAL_APIC
+        .try_init_once(|| local_apic)
+        .expect("apic::init should only be called once");
+    IO_APIC
+        .try_init_once(|| io_apic)
+        .expect("apic::init should only be called once");
+    Ok(())
+}
+
+/// Local APIC of the current CPU
+#[derive(Debug)]
+pub struct LocalApic {
+    mmio: MmioRegion,
+}
+
+impl LocalApic {
+    /// Map the local APIC registers at the base set in `IA32_APIC_BASE`
+    fn map() -> Result<Self, VmmError> {
+        let base = unsafe { IA32_APIC_BASE.read() };
+        let mmio = memory::map_mmio(PhysAddr::new(base & APIC_BASE_MASK), 4096)?;
+        Ok(Self { mmio })
+    }
+
+    /// Enable the APIC, accept all priorities and mask the legacy inputs
+    fn enable(&self) {
+        unsafe {
+            let mut msr = IA32_APIC_BASE;
+            let base = msr.read();
+            msr.write(base | APIC_BASE_ENABLE);
+        }
+
+        self.mmio.write32(LAPIC_TPR, 0);
+        // external interrupts come from the I/O APIC, not LINT0/1
+        self.mmio.write32(LAPIC_LVT_LINT0, LVT_MASKED);
+        self.mmio.write32(LAPIC_LVT_LINT1, LVT_MASKED);
+        self.mmio.write32(LAPIC_LVT_ERROR, LVT_MASKED);
+        // error status is cleared by writing it
+        self.mmio.write32(LAPIC_ESR, 0);
+        self.mmio
+            .write32(LAPIC_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+        self.end_of_interrupt();
+    }
+
+    pub fn id(&self) -> u8 {
+        (self.mmio.read32(LAPIC_ID) >> 24) as u8
+    }
+
+    pub fn version(&self) -> u8 {
+        self.mmio.read32(LAPIC_VERSION) as u8
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mmio.read32(LAPIC_SVR) & SVR_ENABLE != 0
+    }
+
+    /// Acknowledge the interrupt in service
+    pub fn end_of_interrupt(&self) {
+        self.mmio.write32(LAPIC_EOI, 0);
+    }
+
+    /// Send an NMI to the local APIC with id `destination`
+    ///
+    /// Waits until the interrupt command is accepted
+    pub fn send_nmi(&self, destination: u8) {
+        self.mmio
+            .write32(LAPIC_ICR_HIGH, (destination as u32) << 24);
+        // writing the low half sends the interrupt
+        self.mmio
+            .write32(LAPIC_ICR_LOW, ICR_DELIVERY_NMI | ICR_ASSERT);
+        while self.mmio.read32(LAPIC_ICR_LOW) & ICR_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// I/O APIC routing a range of GSIs starting at `gsi_base`
+#[derive(Debug)]
+pub struct IoApic {
+    mmio: MmioRegion,
+    gsi_base: u32,
+}
+
+impl IoApic {
+    fn map(phys: PhysAddr, gsi_base: u32) -> Result<Self, VmmError> {
+        let mmio = memory::map_mmio(phys, 0x20)?;
+        Ok(Self { mmio, gsi_base })
+    }
+
+    fn read(&self, reg: u32) -> u32 {
+        self.mmio.write32(IOAPIC_SELECT, reg);
+        self.mmio.read32(IOAPIC_WINDOW)
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        self.mmio.write32(IOAPIC_SELECT, reg);
+        self.mmio.write32(IOAPIC_WINDOW, value);
+    }
+
+    pub fn id(&self) -> u8 {
+        ((self.read(IOAPIC_ID) >> 24) & 0xf) as u8
+    }
+
+    /// Number of redirection entries
+    pub fn max_entries(&self) -> u32 {
+        ((self.read(IOAPIC_VERSION) >> 16) & 0xff) + 1
+    }
+
+    pub fn gsi_base(&self) -> u32 {
+        self.gsi_base
+    }
+
+    /// Whether `gsi` is one of the inputs of the redirection table
+    pub fn handles(&self, gsi: u32) -> bool {
+        gsi.checked_sub(self.gsi_base)
+            .is_some_and(|index| index < self.max_entries())
+    }
+
+    /// Redirection entry of `gsi`, panics if the I/O APIC doesn't handle it
+    pub fn redirection(&self, gsi: u32) -> u64 {
+        let reg = self.redirection_reg(gsi);
+        let low = self.read(reg) as u64;
+        let high = self.read(reg + 1) as u64;
+        (high << 32) | low
+    }
+
+    fn set_redirection(&self, gsi: u32, entry: u64) {
+        let reg = self.redirection_reg(gsi);
+        // mask while the destination is changed
+        self.write(reg, REDIRECTION_MASKED as u32);
+        self.write(reg + 1, (entry >> 32) as u32);
+        self.write(reg, entry as u32);
+    }
+
+    fn redirection_reg(&self, gsi: u32) -> u32 {
+        if !self.handles(gsi) {
+            panic!("GSI {} not handled by I/O APIC", gsi);
+        }
+        IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2
+    }
+
+    /// Deliver `gsi` as `vector` to local APIC `destination`
+    ///
+    /// `flags` sets polarity and trigger mode, 0 for edge triggered and
+    /// active high
+    pub fn route(&self, gsi: u32, vector: u8, destination: u8, flags: u64) {
+        let entry = ((destination as u64) << 56) | flags | vector as u64;
+        self.set_redirection(gsi, entry);
+    }
+
+    pub fn mask(&self, gsi: u32) {
+        self.set_redirection(gsi, REDIRECTION_MASKED);
+    }
+
+    pub fn is_masked(&self, gsi: u32) -> bool {
+        self.redirection(gsi) & REDIRECTION_MASKED != 0
+    }
+}
+
+/// Vector a redirection entry delivers
+pub fn redirection_vector(entry: u64) -> u8 {
+    entry as u8
+}
@@ -0,0 +1,113 @@
+//! Interrupt controller taking hardware interrupts, the APIC when the CPU
+//! has one and the 8259 PICs otherwise
+//!
+//! Interrupts go through the PICs during boot, `init` switches to the APIC
+//! once memory is set up to map its registers, unless the kernel command
+//! line asks to keep the PICs
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::cmdline;
+
+use super::{
+    apic::{self, ApicConfig},
+    pic::{InterruptIndex, PICS},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Controller {
+    /// Chained 8259 PICs
+    Pic,
+    /// Local APIC and I/O APIC
+    Apic,
+}
+
+static APIC_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Command line option picking the controller, `pic` or `apic`
+pub const OPTION: &str = "interrupt_controller";
+
+impl Controller {
+    /// Controller named `name` in `OPTION`
+    pub fn from_name(name: &str) -> Option<Controller> {
+        match name {
+            "pic" => Some(Controller::Pic),
+            "apic" => Some(Controller::Apic),
+            _ => None,
+        }
+    }
+}
+
+/// Controller picked on the kernel command line, the APIC if not given
+pub fn preferred() -> Controller {
+    cmdline::option(OPTION)
+        .and_then(Controller::from_name)
+        .unwrap_or(Controller::Apic)
+}
+
+/// Switch to the `preferred` controller, falls back to the PICs if the CPU
+/// has no APIC or its registers can't be mapped
+///
+/// Returns the controller in use
+pub fn init(preferred: Controller, config: &ApicConfig) -> Controller {
+    if preferred == Controller::Pic || !apic::is_supported() {
+        return Controller::Pic;
+    }
+
+    without_interrupts(|| {
+        let mut pics = PICS.lock();
+        let masks = unsafe { pics.read_masks() };
+        unsafe { pics.disable() };
+
+        match apic::init(config) {
+            Ok(()) => {
+                APIC_ACTIVE.store(true, Ordering::SeqCst);
+                Controller::Apic
+            }
+            Err(_) => {
+                unsafe { pics.write_masks(masks[0], masks[1]) };
+                Controller::Pic
+            }
+        }
+    })
+}
+
+/// Controller taking interrupts
+pub fn active() -> Controller {
+    if APIC_ACTIVE.load(Ordering::SeqCst) {
+        Controller::Apic
+    } else {
+        Controller::Pic
+    }
+}
+
+/// Acknowledge hardware interrupt `index` at the active controller
+pub fn end_of_interrupt(index: InterruptIndex) {
+    match apic::local_apic() {
+        Some(local_apic) if APIC_ACTIVE.load(Ordering::SeqCst) => local_apic.end_of_interrupt(),
+        _ => unsafe { PICS.lock().notify_end_of_interrupt(index.into()) },
+    }
+}
+
+/// Unmask ISA `irq` at the PICs, the APIC routes all of `apic::ISA_IRQS`
+/// unmasked at init
+pub fn unmask_irq(irq: u8) {
+    if active() == Controller::Apic {
+        return;
+    }
+
+    without_interrupts(|| {
+        let mut pics = PICS.lock();
+        let [mut primary, mut secondary] = unsafe { pics.read_masks() };
+        if irq < 8 {
+            primary &= !(1 << irq);
+        } else {
+            // IRQs of the secondary PIC come through IRQ 2
+            primary &= !(1 << 2);
+            secondary &= !(1 << (irq - 8));
+        }
+        unsafe { pics.write_masks(primary, secondary) };
+    });
+}
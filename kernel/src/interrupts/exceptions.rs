@@ -0,0 +1,420 @@
+//! Handlers for the CPU exception vectors
+//!
+//! Every exception enters through a small assembly stub which pushes a
+//! zero error code if the CPU doesn't push one, the vector number and all
+//! general purpose registers, then calls `dispatch` with the saved state.
+//! The `x86-interrupt` calling convention can't give access to the
+//! registers of the interrupted code, so the stubs are written by hand.
+//!
+//! Reserved vectors 15, 22-27 and 31 get no handler
+
+use core::arch::global_asm;
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptStackFrameValue, PageFaultErrorCode, SelectorErrorCode},
+    VirtAddr,
+};
+
+use crate::backtrace::Backtrace;
+use crate::memory::{self, vmm::VMM};
+use crate::{println, serial_println};
+
+/// General purpose registers saved by the exception stubs, in the order
+/// they are found on the stack
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// State of the interrupted code, built on the stack by the exception stubs
+///
+/// Changes to the registers or the stack frame are restored on return
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub registers: Registers,
+    pub vector: u64,
+    /// Error code pushed by the CPU, 0 for vectors without one
+    pub error_code: u64,
+    pub stack_frame: InterruptStackFrameValue,
+}
+
+/// Error code decoded according to the exception vector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Vector without error code
+    None,
+    Selector(SelectorErrorCode),
+    PageFault(PageFaultErrorCode),
+    /// Error code without further structure, always 0 for some vectors
+    Other(u64),
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorCode::None => write!(f, "none"),
+            ErrorCode::Selector(selector) => {
+                write!(
+                    f,
+                    "{:?} selector index {}",
+                    selector.descriptor_table(),
+                    selector.index()
+                )?;
+                if selector.external() {
+                    write!(f, ", external event")?;
+                }
+                Ok(())
+            }
+            ErrorCode::PageFault(code) => write!(f, "{:?}", code),
+            ErrorCode::Other(code) => write!(f, "{:#x}", code),
+        }
+    }
+}
+
+/// Name and mnemonic of every architecturally defined exception vector
+const EXCEPTIONS: [(&str, &str); 32] = [
+    ("DIVIDE ERROR", "#DE"),
+    ("DEBUG", "#DB"),
+    ("NON MASKABLE INTERRUPT", "NMI"),
+    ("BREAKPOINT", "#BP"),
+    ("OVERFLOW", "#OF"),
+    ("BOUND RANGE EXCEEDED", "#BR"),
+    ("INVALID OPCODE", "#UD"),
+    ("DEVICE NOT AVAILABLE", "#NM"),
+    ("DOUBLE FAULT", "#DF"),
+    ("COPROCESSOR SEGMENT OVERRUN", "-"),
+    ("INVALID TSS", "#TS"),
+    ("SEGMENT NOT PRESENT", "#NP"),
+    ("STACK SEGMENT FAULT", "#SS"),
+    ("GENERAL PROTECTION FAULT", "#GP"),
+    ("PAGE FAULT", "#PF"),
+    ("RESERVED", "-"),
+    ("X87 FLOATING POINT", "#MF"),
+    ("ALIGNMENT CHECK", "#AC"),
+    ("MACHINE CHECK", "#MC"),
+    ("SIMD FLOATING POINT", "#XM"),
+    ("VIRTUALIZATION", "#VE"),
+    ("CONTROL PROTECTION", "#CP"),
+    ("RESERVED", "-"),
+    ("RESERVED", "-"),
+    ("RESERVED", "-"),
+    ("RESERVED", "-"),
+    ("RESERVED", "-"),
+    ("RESERVED", "-"),
+    ("HYPERVISOR INJECTION", "#HV"),
+    ("VMM COMMUNICATION", "#VC"),
+    ("SECURITY", "#SX"),
+    ("RESERVED", "-"),
+];
+
+/// Name of exception vector, "UNKNOWN" for interrupt vectors
+pub fn exception_name(vector: u64) -> &'static str {
+    EXCEPTIONS.get(vector as usize).map_or("UNKNOWN", |e| e.0)
+}
+
+/// Vectors reserved by the CPU, they get no entry stub
+pub fn is_reserved(vector: u64) -> bool {
+    matches!(vector, 15 | 22..=27 | 31)
+}
+
+/// Vectors the CPU pushes an error code for
+pub fn has_error_code(vector: u64) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+impl ExceptionFrame {
+    pub fn name(&self) -> &'static str {
+        exception_name(self.vector)
+    }
+
+    pub fn decode_error_code(&self) -> ErrorCode {
+        match self.vector {
+            10..=13 => ErrorCode::Selector(SelectorErrorCode::new_truncate(self.error_code)),
+            14 => ErrorCode::PageFault(PageFaultErrorCode::from_bits_truncate(self.error_code)),
+            vector if has_error_code(vector) => ErrorCode::Other(self.error_code),
+            _ => ErrorCode::None,
+        }
+    }
+}
+
+impl fmt::Display for ExceptionFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = EXCEPTIONS.get(self.vector as usize).map_or("-", |e| e.1);
+        writeln!(
+            f,
+            "EXCEPTION: {} ({}, vector {})",
+            self.name(),
+            mnemonic,
+            self.vector
+        )?;
+        writeln!(
+            f,
+            "Error Code: {:#x} ({})",
+            self.error_code,
+            self.decode_error_code()
+        )?;
+        writeln!(f, "{:#?}", self.stack_frame)?;
+        write!(f, "{}", self.registers)
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "RAX={:016x} RBX={:016x} RCX={:016x} RDX={:016x}",
+            self.rax, self.rbx, self.rcx, self.rdx
+        )?;
+        writeln!(
+            f,
+            "RSI={:016x} RDI={:016x} RBP={:016x} R8 ={:016x}",
+            self.rsi, self.rdi, self.rbp, self.r8
+        )?;
+        writeln!(
+            f,
+            "R9 ={:016x} R10={:016x} R11={:016x} R12={:016x}",
+            self.r9, self.r10, self.r11, self.r12
+        )?;
+        write!(
+            f,
+            "R13={:016x} R14={:016x} R15={:016x}",
+            self.r13, self.r14, self.r15
+        )
+    }
+}
+
+/// Function given every exception not resolved by the kernel itself
+///
+/// Returning true resumes the interrupted code with the, possibly
+/// changed, frame instead of reporting the exception
+pub type ExceptionHook = fn(&mut ExceptionFrame) -> bool;
+
+static EXCEPTION_HOOK: Mutex<Option<ExceptionHook>> = Mutex::new(None);
+
+/// Replace the exception hook, returns the previous one
+pub fn set_exception_hook(hook: Option<ExceptionHook>) -> Option<ExceptionHook> {
+    core::mem::replace(&mut *EXCEPTION_HOOK.lock(), hook)
+}
+
+/// Handle exception saved in `frame`, called by every exception stub
+///
+/// Page faults on lazily backed or copy-on-write pages are resolved.
+/// Debug, breakpoint and NMI exceptions are reported and resumed, all
+/// others are reported and panic
+pub extern "C" fn dispatch(frame: &mut ExceptionFrame) {
+    if frame.vector == 14 {
+        let addr = Cr2::read();
+        let error_code = PageFaultErrorCode::from_bits_truncate(frame.error_code);
+        // lazily backed memory is mapped on first access
+        if memory::fault::handle_page_fault(addr, error_code) {
+            return;
+        }
+    }
+
+    // lock is only tried, the hook may be replaced when the exception hit
+    let hook = EXCEPTION_HOOK.try_lock().and_then(|hook| *hook);
+    if let Some(hook) = hook {
+        if hook(frame) {
+            return;
+        }
+    }
+
+    report(frame);
+
+    match frame.vector {
+        1..=3 => {}
+        _ => panic!("EXCEPTION: {}", frame.name()),
+    }
+}
+
+/// Print the exception and a backtrace of the interrupted code to serial
+/// and screen
+fn report(frame: &ExceptionFrame) {
+    serial_println!("{}", frame);
+    println!("{}", frame);
+
+    let backtrace =
+        Backtrace::from_frame(frame.stack_frame.instruction_pointer, frame.registers.rbp);
+    serial_println!("{}", backtrace);
+    println!("{}", backtrace);
+
+    if frame.vector == 14 {
+        let addr = Cr2::read();
+        serial_println!("Accessed Address: {:?}", addr);
+        println!("Accessed Address: {:?}", addr);
+
+        match VMM.try_lock().and_then(|vmm| vmm.find(addr).copied()) {
+            Some(region) => {
+                serial_println!(
+                    "Region: {:?} {:?}-{:?} {:?}",
+                    region.kind,
+                    region.start,
+                    region.end(),
+                    region.backing
+                );
+                println!(
+                    "Region: {:?} {:?}-{:?} {:?}",
+                    region.kind,
+                    region.start,
+                    region.end(),
+                    region.backing
+                );
+            }
+            None => {
+                serial_println!("Region: not reserved");
+                println!("Region: not reserved");
+            }
+        }
+    }
+}
+
+// save registers, pass the frame to `dispatch` and restore the possibly
+// changed state. 176 bytes are pushed on top of the 16 byte aligned
+// interrupt frame, so the stack stays aligned for the call
+global_asm!(
+    ".pushsection .text.oros_exception_common,\"ax\",@progbits",
+    ".global oros_exception_common",
+    "oros_exception_common:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "cld",
+    "call {dispatch}",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    // drop vector and error code
+    "add rsp, 16",
+    "iretq",
+    ".popsection",
+    dispatch = sym dispatch,
+);
+
+/// Entry stub of an exception vector, pushes a zero error code for
+/// vectors the CPU doesn't push one for
+macro_rules! exception_stub {
+    ($name:ident, $vector:literal) => {
+        exception_stub!(@emit $name, $vector, "push 0");
+    };
+    ($name:ident, $vector:literal, error_code) => {
+        exception_stub!(@emit $name, $vector, "");
+    };
+    (@emit $name:ident, $vector:literal, $push:literal) => {
+        global_asm!(
+            concat!(".pushsection .text.", stringify!($name), ",\"ax\",@progbits"),
+            concat!(".global ", stringify!($name)),
+            concat!(stringify!($name), ":"),
+            $push,
+            concat!("push ", stringify!($vector)),
+            "jmp oros_exception_common",
+            ".popsection",
+        );
+
+        extern "C" {
+            fn $name();
+        }
+    };
+}
+
+exception_stub!(oros_exception_0, 0);
+exception_stub!(oros_exception_1, 1);
+exception_stub!(oros_exception_2, 2);
+exception_stub!(oros_exception_3, 3);
+exception_stub!(oros_exception_4, 4);
+exception_stub!(oros_exception_5, 5);
+exception_stub!(oros_exception_6, 6);
+exception_stub!(oros_exception_7, 7);
+exception_stub!(oros_exception_8, 8, error_code);
+exception_stub!(oros_exception_9, 9);
+exception_stub!(oros_exception_10, 10, error_code);
+exception_stub!(oros_exception_11, 11, error_code);
+exception_stub!(oros_exception_12, 12, error_code);
+exception_stub!(oros_exception_13, 13, error_code);
+exception_stub!(oros_exception_14, 14, error_code);
+exception_stub!(oros_exception_16, 16);
+exception_stub!(oros_exception_17, 17, error_code);
+exception_stub!(oros_exception_18, 18);
+exception_stub!(oros_exception_19, 19);
+exception_stub!(oros_exception_20, 20);
+exception_stub!(oros_exception_21, 21, error_code);
+exception_stub!(oros_exception_28, 28);
+exception_stub!(oros_exception_29, 29, error_code);
+exception_stub!(oros_exception_30, 30, error_code);
+
+/// Address of the entry stub of exception `vector`
+///
+/// Panics for reserved vectors, they have no stub
+pub fn stub_addr(vector: u8) -> VirtAddr {
+    let stub: unsafe extern "C" fn() = match vector {
+        0 => oros_exception_0,
+        1 => oros_exception_1,
+        2 => oros_exception_2,
+        3 => oros_exception_3,
+        4 => oros_exception_4,
+        5 => oros_exception_5,
+        6 => oros_exception_6,
+        7 => oros_exception_7,
+        8 => oros_exception_8,
+        9 => oros_exception_9,
+        10 => oros_exception_10,
+        11 => oros_exception_11,
+        12 => oros_exception_12,
+        13 => oros_exception_13,
+        14 => oros_exception_14,
+        16 => oros_exception_16,
+        17 => oros_exception_17,
+        18 => oros_exception_18,
+        19 => oros_exception_19,
+        20 => oros_exception_20,
+        21 => oros_exception_21,
+        28 => oros_exception_28,
+        29 => oros_exception_29,
+        30 => oros_exception_30,
+        _ => panic!("no exception stub for vector {}", vector),
+    };
+    VirtAddr::new(stub as usize as u64)
+}
@@ -1,6 +1,9 @@
 //! The Global Descriptor Table (GDT) is a relic that was used for memory segmentation before paging became the de facto standard. However, it is still needed in 64-bit mode for various things, such as kernel/user mode configuration or TSS loading.
 
+use core::ptr::addr_of;
+
 use lazy_static::lazy_static;
+use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable};
 use x86_64::{
     instructions::{
@@ -11,12 +14,16 @@ use x86_64::{
 };
 use x86_64::{structures::tss::TaskStateSegment, VirtAddr};
 
+use crate::memory::stack;
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
 /// selector struct used to set CS (Code Segment) segment and TSS (Task State Segment) segment on  CPU
 struct Selectors {
     cs_selector: SegmentSelector,
     tss_selector: SegmentSelector,
+    user_cs_selector: SegmentSelector,
+    user_ss_selector: SegmentSelector,
 }
 
 lazy_static! {
@@ -24,34 +31,35 @@ lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let cs_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (gdt, Selectors{cs_selector,tss_selector})
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*addr_of!(TSS) }));
+        let user_ss_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_cs_selector = gdt.add_entry(Descriptor::user_code_segment());
+        (gdt, Selectors{cs_selector,tss_selector,user_cs_selector,user_ss_selector})
     };
 }
 
-lazy_static! {
-    /// The Interrupt Stack Table (IST) is part of an old legacy structure called Task State Segment (TSS). The TSS used to hold various pieces of information (e.g., processor register state) about a task in 32-bit mode and was, for example, used for hardware context switching. However, hardware context switching is no longer supported in 64-bit mode and the format of the TSS has changed completely.
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-
-        // create new stack table for interupt stack
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            // return the address at the end of the stack
-            // stacks grow from higher address to lower address
-            VirtAddr::from_ptr(unsafe { &STACK }) + STACK_SIZE
-        };
-        tss
-    };
-}
+/// The Interrupt Stack Table (IST) is part of an old legacy structure called Task State Segment (TSS). The TSS used to hold various pieces of information (e.g., processor register state) about a task in 32-bit mode and was, for example, used for hardware context switching. However, hardware context switching is no longer supported in 64-bit mode and the format of the TSS has changed completely.
+///
+/// The CPU reads IST entries from the loaded TSS on every interrupt, so they
+/// are replaced in place by `init_stacks` once memory is initialized
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+/// Double fault stack used until `init_stacks` allocates a guarded one
+const BOOT_STACK_SIZE: usize = 4096 * 5;
+static mut BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
 
 /// The Interrupt Stack Table (IST)
 /// Loads TSS (Task State Segment) and
 /// CS (Code Segment regsiter) using the
 /// GDT (Global Descriptor Table)
 pub fn init() {
+    // point IST at the end of the boot stack
+    // stacks grow from higher address to lower address
+    unsafe {
+        TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            VirtAddr::from_ptr(addr_of!(BOOT_STACK)) + BOOT_STACK_SIZE;
+    }
+
     GDT.0.load();
 
     unsafe {
@@ -59,3 +67,28 @@ pub fn init() {
         CS::set_reg(GDT.1.cs_selector);
     }
 }
+
+/// Selectors of the ring 3 code and stack segments, loaded by
+/// `usermode::run`
+pub fn user_selectors() -> (SegmentSelector, SegmentSelector) {
+    (GDT.1.user_cs_selector, GDT.1.user_ss_selector)
+}
+
+/// Replace the boot interrupt stacks with stacks which have an unmapped
+/// guard page below, so an overflowing interrupt stack faults
+///
+/// Also sets the stack the CPU switches to when an interrupt enters ring 0
+/// from ring 3. Called once memory and the VMM are initialized
+pub fn init_stacks() {
+    let top = stack::allocate_stack()
+        .expect("failed to allocate double fault stack")
+        .leak();
+    let privilege_top = stack::allocate_stack()
+        .expect("failed to allocate ring 0 stack")
+        .leak();
+
+    without_interrupts(|| unsafe {
+        TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = top;
+        TSS.privilege_stack_table[0] = privilege_top;
+    });
+}
@@ -3,39 +3,21 @@ use pc_keyboard::{layouts, Keyboard, ScancodeSet1};
 use spin::Mutex;
 use x86_64::{
     instructions::port::{PortGeneric, ReadWriteAccess},
-    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
 };
 
 use super::{
-    handlers,
+    controller, handlers,
     pic::{InterruptIndex, PICS},
 };
 
-use crate::hlt_loop;
 use crate::{port::num::PortNumber, print, println};
 
-/// Breakpoint interrupt handler
-pub extern "x86-interrupt" fn breakpiont_handler(stack_frame: InterruptStackFrame) {
-    println!("BREAKPOINT EXCEPTION:");
-    println!("{stack_frame:#?}");
-}
-
-/// Double fault interrupt handler
-pub extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _err_code: u64,
-) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{stack_frame:#?}")
-}
-
 /// Timer interrupt handler
 pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // print!(".");
+    crate::time::tick();
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.into());
-    }
+    controller::end_of_interrupt(InterruptIndex::Timer);
 }
 
 /// Keyboard interrupt handler
@@ -49,22 +31,28 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: Interrupt
     // used for concurrency
     crate::task::keyboard::add_scancode(scancode);
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.into());
-    }
+    controller::end_of_interrupt(InterruptIndex::Keyboard);
 }
 
-/// memory paging interupt handler
-pub extern "x86-interrupt" fn paging_fault_handler(
-    stack_frame: InterruptStackFrame,
-    err_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
+/// Serial interrupt handler, raised for received bytes
+pub extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // nothing reads serial input yet, drop a pending byte to clear the
+    // interrupt, reading the line status clears line status interrupts
+    let _ = crate::port::serial::try_receive();
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {err_code:?}");
-    println!("{stack_frame:#?}");
-    hlt_loop();
+    controller::end_of_interrupt(InterruptIndex::Serial);
 }
+
+/// Real-time clock interrupt handler, raised periodically once enabled
+pub extern "x86-interrupt" fn rtc_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // the RTC raises no further interrupt until status C is read
+    crate::time::rtc::acknowledge();
+    if crate::time::tick_source() == crate::time::TickSource::Rtc {
+        crate::time::tick();
+    }
+
+    controller::end_of_interrupt(InterruptIndex::Rtc);
+}
+
+/// Spurious APIC interrupt handler, spurious interrupts take no EOI
+pub extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
@@ -1,37 +1,94 @@
+use core::{
+    mem,
+    ops::{Index, IndexMut},
+};
+
 use lazy_static::lazy_static;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::{
+    instructions::tables::lidt,
+    structures::{
+        idt::{Entry, HandlerFunc},
+        DescriptorTablePointer,
+    },
+    VirtAddr,
+};
 
-use super::{gdt, handlers, pic::InterruptIndex};
+use super::{apic, exceptions, gdt, handlers, pic::InterruptIndex};
 use crate::println;
 
+/// Vector of the double fault exception, handled on its own stack
+const DOUBLE_FAULT_VECTOR: u8 = 8;
+
+/// Interrupt descriptor table with an entry for every vector
+///
+/// The `InterruptDescriptorTable` of the `x86_64` crate has no fields for
+/// #CP (21) and #HV (28), gates are the same for all vectors
+#[repr(C, align(16))]
+pub struct Idt {
+    entries: [Entry<HandlerFunc>; 256],
+}
+
+impl Idt {
+    fn new() -> Self {
+        Self {
+            entries: [Entry::missing(); 256],
+        }
+    }
+
+    /// Load the table with `lidt`
+    pub fn load(&'static self) {
+        let pointer = DescriptorTablePointer {
+            base: VirtAddr::from_ptr(self),
+            limit: (mem::size_of::<Self>() - 1) as u16,
+        };
+        unsafe { lidt(&pointer) };
+    }
+}
+
+impl Index<usize> for Idt {
+    type Output = Entry<HandlerFunc>;
+
+    fn index(&self, vector: usize) -> &Self::Output {
+        &self.entries[vector]
+    }
+}
+
+impl IndexMut<usize> for Idt {
+    fn index_mut(&mut self, vector: usize) -> &mut Self::Output {
+        &mut self.entries[vector]
+    }
+}
+
 lazy_static! {
     /// Interrupt descriptor table
     /// used to create index of interrupt codes and register handlers
-    static ref IDT: InterruptDescriptorTable = {
+    static ref IDT: Idt = {
         // create new table
-        let mut idt = InterruptDescriptorTable::new();
-
-        // main interrupt handlers
+        let mut idt = Idt::new();
 
-        // breakpoint handler
-        idt.breakpoint.set_handler_fn(handlers::breakpiont_handler);
-
-        // double fault handler
-        unsafe {
-            idt.double_fault
-            .set_handler_fn(handlers::double_fault_handler)
-            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        // exceptions enter through stubs saving all registers
+        for vector in (0..32).filter(|&vector| !exceptions::is_reserved(vector as u64)) {
+            let options = unsafe { idt[vector as usize].set_handler_addr(exceptions::stub_addr(vector)) };
+            if vector == DOUBLE_FAULT_VECTOR {
+                unsafe { options.set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX) };
+            }
         }
 
-        // memory page fault
-        idt.page_fault.set_handler_fn(handlers::paging_fault_handler);
-
         // timer interrupt
         idt[InterruptIndex::Timer.into()].set_handler_fn(handlers::timer_interrupt_handler);
 
         // keyboard interrupt
         idt[InterruptIndex::Keyboard.into()].set_handler_fn(handlers::keyboard_interrupt_handler);
 
+        // serial interrupt
+        idt[InterruptIndex::Serial.into()].set_handler_fn(handlers::serial_interrupt_handler);
+
+        // real-time clock interrupt
+        idt[InterruptIndex::Rtc.into()].set_handler_fn(handlers::rtc_interrupt_handler);
+
+        // spurious APIC interrupt
+        idt[apic::SPURIOUS_VECTOR.into()].set_handler_fn(handlers::spurious_interrupt_handler);
+
         idt
     };
 }
@@ -1,4 +1,8 @@
+pub mod apic;
+pub mod controller;
+pub mod exceptions;
 pub mod gdt;
 pub mod handlers;
 pub mod idt;
 pub mod pic;
+pub mod usermode;
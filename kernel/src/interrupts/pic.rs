@@ -16,6 +16,10 @@ pub static PICS: spin::Mutex<ChainedPics> =
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    /// COM1, ISA IRQ 4
+    Serial = PIC_1_OFFSET + 4,
+    /// Real-time clock, ISA IRQ 8
+    Rtc = PIC_1_OFFSET + 8,
 }
 
 impl From<InterruptIndex> for usize {
@@ -0,0 +1,90 @@
+//! Running code in ring 3
+//!
+//! There are no system calls yet, user code runs until it raises an
+//! exception. An exception hook ends the run with `exit`, which returns to
+//! the kernel right after the `run` call that entered ring 3. Exceptions
+//! from ring 3 enter the kernel on the ring 0 stack set up by
+//! `gdt::init_stacks`
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::{
+    instructions::segmentation::{Segment, CS},
+    registers::rflags::RFlags,
+    VirtAddr,
+};
+
+use super::{exceptions::ExceptionFrame, gdt};
+
+/// Kernel state saved by `run` and restored by `exit`
+static KERNEL_RIP: AtomicU64 = AtomicU64::new(0);
+static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+static KERNEL_RFLAGS: AtomicU64 = AtomicU64::new(0);
+
+/// Run code at `entry` in ring 3 with the stack pointer at `stack`
+///
+/// Runs in the active address space with the given `rflags`, returns once
+/// an exception hook ends the run with `exit`. Only one run can be active
+///
+/// # Safety
+///
+/// The caller must ensure `entry` and `stack` are mapped user accessible
+/// in the active address space and an exception hook ends the run
+pub unsafe fn run(entry: VirtAddr, stack: VirtAddr, rflags: RFlags) {
+    let (cs, ss) = gdt::user_selectors();
+
+    asm!(
+        // callee saved registers hold user values once the run ends
+        "push rbx",
+        "push rbp",
+        "lea rax, [rip + 2f]",
+        "mov [rip + {kernel_rip}], rax",
+        "pushfq",
+        "pop rax",
+        "mov [rip + {kernel_rflags}], rax",
+        "mov [rip + {kernel_rsp}], rsp",
+        "push {ss}",
+        "push {stack}",
+        "push {rflags}",
+        "push {cs}",
+        "push {entry}",
+        "iretq",
+        // `exit` resumes here
+        "2:",
+        "pop rbp",
+        "pop rbx",
+        kernel_rip = sym KERNEL_RIP,
+        kernel_rsp = sym KERNEL_RSP,
+        kernel_rflags = sym KERNEL_RFLAGS,
+        ss = in(reg) ss.0 as u64,
+        stack = in(reg) stack.as_u64(),
+        rflags = in(reg) rflags.bits(),
+        cs = in(reg) cs.0 as u64,
+        entry = in(reg) entry.as_u64(),
+        out("rax") _,
+        out("r12") _,
+        out("r13") _,
+        out("r14") _,
+        out("r15") _,
+        clobber_abi("C"),
+    );
+}
+
+/// Whether the exception in `frame` was raised by code in ring 3
+pub fn is_user(frame: &ExceptionFrame) -> bool {
+    frame.stack_frame.code_segment & 3 == 3
+}
+
+/// End the run of user code which raised the exception in `frame`
+///
+/// Returning from the exception continues in the kernel after the `run`
+/// call, with the kernel stack and flags it was entered with
+pub fn exit(frame: &mut ExceptionFrame) {
+    let stack_frame = &mut frame.stack_frame;
+    stack_frame.instruction_pointer = VirtAddr::new(KERNEL_RIP.load(Ordering::SeqCst));
+    stack_frame.code_segment = CS::get_reg().0 as u64;
+    stack_frame.cpu_flags = KERNEL_RFLAGS.load(Ordering::SeqCst);
+    stack_frame.stack_pointer = VirtAddr::new(KERNEL_RSP.load(Ordering::SeqCst));
+    stack_frame.stack_segment = 0;
+}
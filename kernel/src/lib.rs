@@ -31,13 +31,18 @@ use bootloader_api::config::{BootloaderConfig, Mapping};
 use bootloader_api::{entry_point, BootInfo};
 
 // import kernel modules
+pub mod acpi;
+pub mod backtrace;
+pub mod cmdline;
 pub mod init;
 pub mod interrupts;
 pub mod memory;
 pub mod port;
+pub mod power;
 pub mod screen;
 pub mod task;
 pub mod test_utils;
+pub mod time;
 
 // main entry point used when cargo test
 #[cfg(test)]
@@ -46,6 +51,8 @@ entry_point!(test_kernel_main, &BOOTLOADER_CONFIG);
 pub static BOOTLOADER_CONFIG: BootloaderConfig = {
     let mut config = BootloaderConfig::new_default();
     config.mappings.physical_memory = Some(Mapping::Dynamic);
+    // keep bootloader mappings in the kernel half, shared by all address spaces
+    config.mappings.dynamic_range_start = Some(crate::memory::address_space::KERNEL_SPACE_START);
     config
 };
 
@@ -21,19 +21,21 @@
 // extern alloc crate to be compiled with binary
 extern crate alloc;
 
-use core::panic::PanicInfo;
+use core::{panic::PanicInfo, time::Duration};
 
 use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::{Page, PageTable, Size4KiB, Translate};
-use x86_64::VirtAddr;
+use x86_64::{PhysAddr, VirtAddr};
 
 use bootloader_api::config::{BootloaderConfig, Mapping};
 use bootloader_api::{entry_point, BootInfo};
+use futures_util::StreamExt;
 
-use oros_kernel::memory::{self, allocator, frame};
+use oros_kernel::backtrace::Backtrace;
+use oros_kernel::memory::{self, allocator};
 use oros_kernel::task::{executor::Executor, keyboard, Task};
-use oros_kernel::{hlt_loop, init, println, test_utils};
+use oros_kernel::{hlt_loop, init, println, serial_println, test_utils, time};
 
 #[cfg(test)]
 #[panic_handler]
@@ -46,6 +48,7 @@ fn panic(info: &PanicInfo) -> ! {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("{info}");
+    println!("{}", Backtrace::capture());
     hlt_loop();
 }
 
@@ -54,6 +57,9 @@ entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
 pub static BOOTLOADER_CONFIG: BootloaderConfig = {
     let mut config = BootloaderConfig::new_default();
     config.mappings.physical_memory = Some(Mapping::Dynamic);
+    // keep bootloader mappings in the kernel half, shared by all address spaces
+    config.mappings.dynamic_range_start =
+        Some(oros_kernel::memory::address_space::KERNEL_SPACE_START);
     config
 };
 
@@ -67,20 +73,29 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     // print the os is working
     println!("The NEWEST OS there is {}", "!");
 
+    // report memory usage after boot
+    serial_println!("{}", memory::stats());
+    serial_println!(
+        "clock: {} at {} Hz",
+        time::clocksource(),
+        time::clock_frequency()
+    );
+    println!("{}", time::wall_clock());
+
     // TODO:
     // Move allocator init logic into
     // main init method
 
-    // create page to test VGA buffer
-    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(0xb8000));
-    let page_ptr: *mut u64 = page.start_address().as_mut_ptr();
-    unsafe { page_ptr.offset(400).write_volatile(0xf021_f077_f065_f04e) };
+    // write to VGA buffer through an uncached mapping
+    let vga_buffer =
+        memory::map_mmio(PhysAddr::new(0xb8000), 4096).expect("failed to map VGA buffer");
+    vga_buffer.write64(400 * 8, 0xf021_f077_f065_f04e);
 
     println!("It did not crash!");
 
     let mut executor = Executor::new();
-    executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(keyboard::print_key_presses()));
+    executor.spawn(Task::named("example", example_task()));
+    executor.spawn(Task::named("keyboard", keyboard::print_key_presses()));
     executor.run();
 
     // run tests if 'cargo test'
@@ -98,4 +113,12 @@ async fn example_number() -> u32 {
 async fn example_task() {
     let number = example_number().await;
     println!("{number}");
+
+    // count the seconds since the executor started
+    let mut seconds = time::interval(Duration::from_secs(1));
+    let mut counter = 0;
+    while seconds.next().await.is_some() {
+        counter += 1;
+        println!("{counter}");
+    }
 }
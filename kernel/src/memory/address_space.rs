@@ -0,0 +1,243 @@
+//! Separate address spaces, each with its own level 4 page table
+//!
+//! The lower half of every address space belongs to user programs, the
+//! higher half is the kernel and shared by all of them. Level 4 entries
+//! of the higher half are created once at boot, so their tables are the
+//! same in every address space and kernel mappings made later show up in
+//! all of them
+
+use conquer_once::spin::OnceCell;
+use x86_64::{
+    registers::control::{Cr3, Cr3Flags},
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page,
+        PageTable, PageTableFlags, PhysFrame, Size4KiB,
+    },
+    VirtAddr,
+};
+
+use super::{bitmap::BitmapFrameAllocator, cow};
+
+/// Start of the kernel half, the bootloader places its mappings above it
+pub const KERNEL_SPACE_START: u64 = 0xffff_8000_0000_0000;
+
+/// End of the user half of every address space
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// First level 4 entry of the kernel half
+const KERNEL_ENTRY_START: usize = 256;
+
+/// Level 4 table of the kernel, active after boot
+static KERNEL_TABLE: OnceCell<PhysFrame> = OnceCell::uninit();
+
+/// Errors returned when mapping into an address space
+#[derive(Debug)]
+pub enum AddressSpaceError {
+    /// Address is in the kernel half, shared by every address space
+    KernelAddress,
+    Map(MapToError<Size4KiB>),
+}
+
+/// Keep the kernel level 4 table and create every level 4 entry of the
+/// kernel half, so the entries can be shared by all address spaces
+pub fn init(mapper: &mut OffsetPageTable, frame_allocator: &mut BitmapFrameAllocator) {
+    let (frame, _) = Cr3::read();
+    KERNEL_TABLE
+        .try_init_once(|| frame)
+        .expect("address_space::init should only be called once");
+
+    let phys_mem_offset = mapper.phys_offset();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    for entry in mapper.level_4_table().iter_mut().skip(KERNEL_ENTRY_START) {
+        if !entry.is_unused() {
+            continue;
+        }
+        let table = allocate_zeroed(frame_allocator, phys_mem_offset)
+            .expect("no frames for kernel page tables");
+        entry.set_frame(table, flags);
+    }
+}
+
+/// Level 4 table of the kernel address space
+pub fn kernel_table() -> PhysFrame {
+    *KERNEL_TABLE
+        .try_get()
+        .expect("address spaces not initialized")
+}
+
+/// Allocate zeroed frame, used for page tables and user pages
+fn allocate_zeroed(
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    phys_mem_offset: VirtAddr,
+) -> Option<PhysFrame> {
+    let frame = frame_allocator.allocate_frame()?;
+    let table_ptr: *mut PageTable = (phys_mem_offset + frame.start_address().as_u64()).as_mut_ptr();
+    unsafe { table_ptr.write(PageTable::new()) };
+    Some(frame)
+}
+
+/// Address space with its own level 4 page table
+///
+/// User pages are mapped into the lower half, the kernel half is shared.
+/// All user page tables and frames are freed on drop, frames shared
+/// copy-on-write only once their last user is gone
+pub struct AddressSpace {
+    lvl_4_frame: PhysFrame,
+}
+
+impl AddressSpace {
+    /// Create address space with an empty user half
+    pub fn new() -> Option<Self> {
+        let phys_mem_offset = super::phys_mem_offset();
+        let mut frame_allocator = super::frame_allocator();
+        let lvl_4_frame = allocate_zeroed(&mut *frame_allocator, phys_mem_offset)?;
+
+        let address_space = Self { lvl_4_frame };
+        let kernel_table = unsafe { &*table_ptr(kernel_table(), phys_mem_offset) };
+        let table = unsafe { &mut *table_ptr(lvl_4_frame, phys_mem_offset) };
+        for i in KERNEL_ENTRY_START..512 {
+            table[i] = kernel_table[i].clone();
+        }
+
+        Some(address_space)
+    }
+
+    /// Frame of the level 4 table, the value loaded into `Cr3`
+    pub fn lvl_4_frame(&self) -> PhysFrame {
+        self.lvl_4_frame
+    }
+
+    /// Mapper for the page tables of the address space
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let phys_mem_offset = super::phys_mem_offset();
+        unsafe {
+            OffsetPageTable::new(
+                &mut *table_ptr(self.lvl_4_frame, phys_mem_offset),
+                phys_mem_offset,
+            )
+        }
+    }
+
+    /// Back user page with a zeroed frame
+    ///
+    /// The page is mapped user accessible with the given flags
+    pub fn map_user(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<PhysFrame, AddressSpaceError> {
+        if page.start_address().as_u64() >= USER_SPACE_END {
+            return Err(AddressSpaceError::KernelAddress);
+        }
+
+        let phys_mem_offset = super::phys_mem_offset();
+        let mut frame_allocator = super::frame_allocator();
+        let frame = allocate_zeroed(&mut *frame_allocator, phys_mem_offset)
+            .ok_or(AddressSpaceError::Map(MapToError::FrameAllocationFailed))?;
+
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        let table_flags =
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+        let result = unsafe {
+            self.mapper().map_to_with_table_flags(
+                page,
+                frame,
+                flags,
+                table_flags,
+                &mut *frame_allocator,
+            )
+        };
+        match result {
+            // flush in case the address space is active
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                return Err(AddressSpaceError::Map(err));
+            }
+        }
+
+        Ok(frame)
+    }
+
+    /// Load the level 4 table into `Cr3`
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure nothing running after the switch uses user
+    /// pages of the previous address space
+    pub unsafe fn switch(&self) {
+        Cr3::write(self.lvl_4_frame, Cr3Flags::empty());
+    }
+
+    /// Switch back to the kernel address space
+    ///
+    /// # Safety
+    ///
+    /// Same as `switch`, user pages are no longer mapped afterwards
+    pub unsafe fn switch_to_kernel() {
+        Cr3::write(kernel_table(), Cr3Flags::empty());
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.lvl_4_frame
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        if self.is_active() {
+            unsafe { Self::switch_to_kernel() };
+        }
+
+        let phys_mem_offset = super::phys_mem_offset();
+        let mut frame_allocator = super::frame_allocator();
+        let table = unsafe { &mut *table_ptr(self.lvl_4_frame, phys_mem_offset) };
+
+        for entry in table.iter_mut().take(KERNEL_ENTRY_START) {
+            if entry.flags().contains(PageTableFlags::PRESENT) {
+                unsafe { free_table(entry.frame().unwrap(), 3, &mut frame_allocator) };
+            }
+        }
+
+        unsafe { frame_allocator.deallocate_frame(self.lvl_4_frame) };
+    }
+}
+
+/// Free page table of given level, its subtables and mapped frames
+///
+/// # Safety
+///
+/// The table must not be in use by any address space
+unsafe fn free_table(frame: PhysFrame, level: usize, frame_allocator: &mut BitmapFrameAllocator) {
+    let table = &*table_ptr(frame, super::phys_mem_offset());
+
+    for entry in table.iter() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+
+        if level == 1 {
+            let frame = PhysFrame::containing_address(entry.addr());
+            // shared frames are freed by their last user
+            if cow::release_frame(frame) {
+                frame_allocator.deallocate_frame(frame);
+            }
+        } else if !flags.contains(PageTableFlags::HUGE_PAGE) {
+            // huge pages are never mapped by `map_user`, only tables are freed
+            free_table(
+                PhysFrame::containing_address(entry.addr()),
+                level - 1,
+                frame_allocator,
+            );
+        }
+    }
+
+    frame_allocator.deallocate_frame(frame);
+}
+
+fn table_ptr(frame: PhysFrame, phys_mem_offset: VirtAddr) -> *mut PageTable {
+    (phys_mem_offset + frame.start_address().as_u64()).as_mut_ptr()
+}
@@ -8,17 +8,60 @@ use x86_64::{
     VirtAddr,
 };
 
-use super::{bump::BumpAllocator, fixed::FixedSizeAllocator, linked_list::LinkedListAllocator};
+use super::{
+    bump::BumpAllocator, external::ExternalLinkedListAllocator, fixed::FixedSizeAllocator,
+    linked_list::LinkedListAllocator, slab::SlabAllocator,
+};
+use crate::memory;
 
-#[global_allocator]
-// pub static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
-// pub static ALLOCATOR: Locked<BumpAllocator> = Locked::new(BumpAllocator::new());
+#[cfg(feature = "heap-debug")]
+use super::debug::DebugAllocator;
+
+// global allocator is selected with one of the `alloc-*` cargo features
+#[cfg(feature = "alloc-bump")]
+#[cfg_attr(not(feature = "heap-debug"), global_allocator)]
+pub static ALLOCATOR: Locked<BumpAllocator> = Locked::new(BumpAllocator::new());
+
+#[cfg(feature = "alloc-linked-list")]
+#[cfg_attr(not(feature = "heap-debug"), global_allocator)]
+pub static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+
+#[cfg(feature = "alloc-fixed")]
+#[cfg_attr(not(feature = "heap-debug"), global_allocator)]
 pub static ALLOCATOR: Locked<FixedSizeAllocator> = Locked::new(FixedSizeAllocator::new());
 
-// define heap memeory location
-pub const HEAP_START: usize = 0x_4444_4444_0000;
+#[cfg(feature = "alloc-slab")]
+#[cfg_attr(not(feature = "heap-debug"), global_allocator)]
+pub static ALLOCATOR: Locked<SlabAllocator> = Locked::new(SlabAllocator::new());
+
+#[cfg(feature = "alloc-external-linked-list")]
+#[cfg_attr(not(feature = "heap-debug"), global_allocator)]
+pub static ALLOCATOR: Locked<ExternalLinkedListAllocator> =
+    Locked::new(ExternalLinkedListAllocator::new());
+
+// with `heap-debug` the selected allocator is wrapped with corruption checks
+#[cfg(feature = "heap-debug")]
+#[global_allocator]
+static DEBUG_ALLOCATOR: DebugAllocator = DebugAllocator::new(&ALLOCATOR);
+
+const SELECTED_ALLOCATORS: usize = cfg!(feature = "alloc-bump") as usize
+    + cfg!(feature = "alloc-linked-list") as usize
+    + cfg!(feature = "alloc-fixed") as usize
+    + cfg!(feature = "alloc-slab") as usize
+    + cfg!(feature = "alloc-external-linked-list") as usize;
+
+const _: () = if SELECTED_ALLOCATORS != 1 {
+    panic!("exactly one `alloc-*` feature must be enabled to select the global allocator");
+};
+
+// define heap memeory location, in the kernel half of the address space
+pub const HEAP_START: usize = 0x_ffff_c444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024; // 100KiB
 
+// heap grows on demand, up to the ceiling
+pub const HEAP_GROW_SIZE: usize = 64 * 1024; // 64KiB
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64MiB
+
 pub struct Dummy;
 
 unsafe impl GlobalAlloc for Dummy {
@@ -49,6 +92,12 @@ impl<T> Locked<T> {
     pub fn lock(&self) -> spin::MutexGuard<T> {
         self.inner.lock()
     }
+
+    /// Lock if not already locked, used from interrupt handlers which
+    /// may have interrupted the lock holder
+    pub fn try_lock(&self) -> Option<spin::MutexGuard<T>> {
+        self.inner.try_lock()
+    }
 }
 
 /// Align `addr` upwards to alignment `align`
@@ -57,3 +106,30 @@ impl<T> Locked<T> {
 pub fn align_up(addr: usize, align: usize) -> usize {
     (addr + align - 1) & !(align - 1)
 }
+
+/// Heap allocator which can be extended with memory mapped after its
+/// current end
+pub trait GrowableHeap {
+    /// Current end address of the heap
+    fn heap_top(&self) -> usize;
+
+    /// Add `by` bytes after the heap top to the heap
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure the memory after the heap top is mapped
+    /// and unused
+    unsafe fn extend(&mut self, by: usize);
+
+    /// Map at least `min_size` more bytes after the heap top and add them
+    /// to the heap
+    ///
+    /// Returns false if the heap could not grow
+    fn grow(&mut self, min_size: usize) -> bool {
+        let grown = memory::grow_heap(self.heap_top(), min_size);
+        if grown > 0 {
+            unsafe { self.extend(grown) };
+        }
+        grown > 0
+    }
+}
@@ -0,0 +1,227 @@
+use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+use crate::serial_println;
+
+use super::allocator::align_up;
+
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// Physical frame allocator which tracks every 4KiB frame in a bitmap
+///
+/// A set bit means the frame is in use (or not usable at all), a clear
+/// bit means the frame can be handed out. A second bitmap marks the frames
+/// of usable regions, only those can be freed. Both bitmaps live in the
+/// first usable region large enough to hold them and are accessed through
+/// the physical memory offset mapping
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    usable: &'static mut [u64],
+    total_frames: usize,
+    used_frames: usize,
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Create BitmapFrameAllocator from the passed memory map
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the passed memory map is valid, that all
+    /// physical memory is mapped at `phys_mem_offset` and that the method is
+    /// only called once
+    pub unsafe fn init(mem_map: &MemoryRegions, phys_mem_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            mem_map
+                .iter()
+                .filter(|r| r.kind == MemoryRegionKind::Usable)
+        };
+
+        // size the bitmap to cover every frame up to the highest usable address
+        let max_addr = usable_regions().map(|r| r.end).max().unwrap_or(0);
+        let frame_count = (max_addr / FRAME_SIZE) as usize;
+        let word_count = (frame_count + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        let bitmap_size = align_up(2 * word_count * 8, FRAME_SIZE as usize) as u64;
+
+        // place the bitmaps at the start of the first region that can hold them
+        let bitmap_start = usable_regions()
+            .map(|r| {
+                (
+                    align_up(r.start as usize, FRAME_SIZE as usize) as u64,
+                    r.end,
+                )
+            })
+            .find(|&(start, end)| start + bitmap_size <= end)
+            .map(|(start, _)| start)
+            .expect("no usable region large enough for frame bitmap");
+
+        let bitmap_ptr: *mut u64 = (phys_mem_offset + bitmap_start).as_mut_ptr();
+        let bitmap = core::slice::from_raw_parts_mut(bitmap_ptr, word_count);
+        let usable = core::slice::from_raw_parts_mut(bitmap_ptr.add(word_count), word_count);
+
+        // every frame starts out used, usable frames are released below
+        bitmap.fill(u64::MAX);
+        usable.fill(0);
+
+        let mut allocator = Self {
+            bitmap,
+            usable,
+            total_frames: 0,
+            used_frames: 0,
+            next: 0,
+        };
+
+        for region in usable_regions() {
+            let start = align_up(region.start as usize, FRAME_SIZE as usize) as u64;
+            let end = region.end & !(FRAME_SIZE - 1);
+
+            for addr in (start..end).step_by(FRAME_SIZE as usize) {
+                let index = Self::frame_index(addr);
+                allocator.clear_bit(index);
+                allocator.usable[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+                allocator.total_frames += 1;
+            }
+        }
+
+        // reserve the frames holding the bitmaps, they are never freed
+        for addr in (bitmap_start..bitmap_start + bitmap_size).step_by(FRAME_SIZE as usize) {
+            let index = Self::frame_index(addr);
+            allocator.set_bit(index);
+            allocator.usable[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+            allocator.used_frames += 1;
+        }
+
+        serial_println!(
+            "Initializing BitmapFrameAllocator, {} usable frames",
+            allocator.total_frames
+        );
+
+        allocator
+    }
+
+    /// Number of usable frames managed by the allocator
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    /// Number of frames covered by the bitmap, every usable frame has a
+    /// lower frame number
+    pub fn frame_count(&self) -> usize {
+        self.bitmap.len() * BITS_PER_WORD
+    }
+
+    /// Number of frames currently handed out
+    pub fn used_frames(&self) -> usize {
+        self.used_frames
+    }
+
+    /// Number of frames still available for allocation
+    pub fn free_frames(&self) -> usize {
+        self.total_frames - self.used_frames
+    }
+
+    /// Returns true if the given frame is currently marked as used
+    pub fn is_used(&self, frame: PhysFrame) -> bool {
+        self.bit(Self::frame_index(frame.start_address().as_u64()))
+    }
+
+    /// Returns true if the given frame lies in a usable region and can be
+    /// handed out and freed
+    pub fn is_usable(&self, frame: PhysFrame) -> bool {
+        let index = Self::frame_index(frame.start_address().as_u64());
+        match self.usable.get(index / BITS_PER_WORD) {
+            Some(word) => word & (1 << (index % BITS_PER_WORD)) != 0,
+            None => false,
+        }
+    }
+
+    /// Allocate `count` physically contiguous frames, the first frame
+    /// aligned to `align` frames
+    ///
+    /// Scans the whole bitmap, meant for reserving larger pools rather
+    /// than for frequent allocations
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrame> {
+        let frame_count = self.bitmap.len() * BITS_PER_WORD;
+
+        let start = (0..frame_count)
+            .step_by(align.max(1))
+            .take_while(|start| start + count <= frame_count)
+            .find(|&start| (start..start + count).all(|i| !self.bit(i)))?;
+
+        for index in start..start + count {
+            self.set_bit(index);
+        }
+        self.used_frames += count;
+
+        let addr = PhysAddr::new(start as u64 * FRAME_SIZE);
+        Some(PhysFrame::containing_address(addr))
+    }
+
+    /// Find first word from `next` which has a free bit, wrapping around
+    /// to the start of the bitmap
+    ///
+    /// Returns index of the free frame
+    fn find_free(&self) -> Option<usize> {
+        let words = self.bitmap.len();
+
+        (0..words)
+            .map(|i| (self.next + i) % words)
+            .find(|&w| self.bitmap[w] != u64::MAX)
+            .map(|w| w * BITS_PER_WORD + self.bitmap[w].trailing_ones() as usize)
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        match self.bitmap.get(index / BITS_PER_WORD) {
+            Some(word) => word & (1 << (index % BITS_PER_WORD)) != 0,
+            None => true,
+        }
+    }
+
+    fn set_bit(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+
+    fn clear_bit(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+    }
+
+    fn frame_index(addr: u64) -> usize {
+        (addr / FRAME_SIZE) as usize
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let index = self.find_free()?;
+
+        self.set_bit(index);
+        self.used_frames += 1;
+        self.next = index / BITS_PER_WORD;
+
+        let addr = PhysAddr::new(index as u64 * FRAME_SIZE);
+        Some(PhysFrame::containing_address(addr))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        // also rejects frames beyond the bitmap
+        if !self.is_usable(frame) {
+            panic!("free of physical frame {frame:?} outside usable memory");
+        }
+        if !self.is_used(frame) {
+            panic!("double free of physical frame {frame:?}");
+        }
+
+        let index = Self::frame_index(frame.start_address().as_u64());
+        self.clear_bit(index);
+        self.used_frames -= 1;
+
+        // freed frame is the earliest known free frame
+        self.next = self.next.min(index / BITS_PER_WORD);
+    }
+}
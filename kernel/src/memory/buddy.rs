@@ -0,0 +1,268 @@
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use crate::serial_println;
+
+use super::allocator::Locked;
+
+pub static BUDDY_ALLOCATOR: Locked<BuddyFrameAllocator> = Locked::new(BuddyFrameAllocator::new());
+
+/// Largest block order handed out by the allocator
+///
+/// A block of order `n` is `2^n` contiguous 4KiB frames, so the
+/// largest block is 4MiB. Order 9 is a 2MiB huge page
+pub const MAX_ORDER: usize = 10;
+
+/// Size of the physical pool reserved for the buddy allocator at boot
+pub const POOL_SIZE: usize = 16 * 1024 * 1024; // 16MiB
+
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+
+/// Free block header, written into the first frame of every free block
+struct FreeBlock {
+    next: Option<&'static mut FreeBlock>,
+}
+
+/// Buddy system physical frame allocator
+///
+/// Keeps one free list per block order. Allocations split larger blocks
+/// in halves until the requested order is reached, deallocations merge
+/// a block with its buddy for as long as the buddy is free
+pub struct BuddyFrameAllocator {
+    free_lists: [Option<&'static mut FreeBlock>; MAX_ORDER + 1],
+    phys_mem_offset: VirtAddr,
+    total_frames: usize,
+    free_frames: usize,
+}
+
+impl BuddyFrameAllocator {
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut FreeBlock> = None;
+        Self {
+            free_lists: [EMPTY; MAX_ORDER + 1],
+            phys_mem_offset: VirtAddr::zero(),
+            total_frames: 0,
+            free_frames: 0,
+        }
+    }
+
+    /// Initialize allocator with the physical memory offset mapping
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that all physical memory is mapped at
+    /// `phys_mem_offset`. Method should only be called once.
+    pub unsafe fn init(&mut self, phys_mem_offset: VirtAddr) {
+        serial_println!("Initializing Buddy frame allocator");
+        self.phys_mem_offset = phys_mem_offset;
+    }
+
+    /// Hand a range of contiguous frames to the allocator
+    ///
+    /// The range is split into the largest naturally aligned blocks
+    /// that fit
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure the frames are unused and not owned by any
+    /// other frame allocator
+    pub unsafe fn add_region(&mut self, start: PhysFrame, frame_count: usize) {
+        let mut addr = start.start_address().as_u64();
+        let end = addr + frame_count as u64 * FRAME_SIZE;
+
+        while addr < end {
+            let mut order = MAX_ORDER;
+            while order > 0 && (addr % block_size(order) != 0 || addr + block_size(order) > end) {
+                order -= 1;
+            }
+
+            self.push(order, addr);
+            self.total_frames += 1 << order;
+            self.free_frames += 1 << order;
+            addr += block_size(order);
+        }
+    }
+
+    /// Allocate a block of `2^order` contiguous frames
+    ///
+    /// Returns the start address of the block, which is aligned to the
+    /// block size
+    pub fn allocate(&mut self, order: usize) -> Option<PhysAddr> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        // smallest order with a free block which is large enough
+        let found = (order..=MAX_ORDER).find(|&o| self.free_lists[o].is_some())?;
+        let addr = self.pop(found)?;
+
+        // split block, returning the upper halves to the free lists
+        for o in (order..found).rev() {
+            unsafe { self.push(o, addr + block_size(o)) };
+        }
+
+        self.free_frames -= 1 << order;
+        Some(PhysAddr::new(addr))
+    }
+
+    /// Allocate at least `frame_count` contiguous frames
+    pub fn allocate_contiguous(&mut self, frame_count: usize) -> Option<PhysAddr> {
+        self.allocate(Self::order_for(frame_count))
+    }
+
+    /// Return a block of `2^order` frames to the allocator, merging it
+    /// with its buddy while possible
+    ///
+    /// Panics if `order` is above `MAX_ORDER`, `addr` is not aligned to the
+    /// block size or part of the block is already free
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure the block was allocated with the same order
+    /// and is no longer used
+    pub unsafe fn deallocate(&mut self, addr: PhysAddr, order: usize) {
+        if order > MAX_ORDER {
+            panic!("free of physical block {addr:?} with order {order} above {MAX_ORDER}");
+        }
+        if !addr.is_aligned(block_size(order)) {
+            panic!("free of physical block {addr:?} not aligned to order {order}");
+        }
+        if self.is_free(addr, order) {
+            panic!("double free of physical block {addr:?} with order {order}");
+        }
+
+        let mut addr = addr.as_u64();
+        let mut order = order;
+
+        self.free_frames += 1 << order;
+
+        while order < MAX_ORDER {
+            let buddy = addr ^ block_size(order);
+            if !self.remove(order, buddy) {
+                break;
+            }
+            addr = addr.min(buddy);
+            order += 1;
+        }
+
+        self.push(order, addr);
+    }
+
+    /// Whether any frame of the `2^order` frames block at `addr` is on a
+    /// free list, either as a block of its own or as part of a larger one
+    pub fn is_free(&self, addr: PhysAddr, order: usize) -> bool {
+        let start = addr.as_u64();
+        let end = start + block_size(order);
+
+        (0..=MAX_ORDER).any(|o| {
+            let mut current = self.free_lists[o].as_deref();
+            while let Some(block) = current {
+                let block_start = block as *const FreeBlock as u64 - self.phys_mem_offset.as_u64();
+                if block_start < end && start < block_start + block_size(o) {
+                    return true;
+                }
+                current = block.next.as_deref();
+            }
+            false
+        })
+    }
+
+    /// Smallest order of a block holding `frame_count` frames
+    pub fn order_for(frame_count: usize) -> usize {
+        frame_count.max(1).next_power_of_two().trailing_zeros() as usize
+    }
+
+    /// Number of frames managed by the allocator
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    /// Number of frames still available for allocation
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Number of free blocks of the given order
+    pub fn free_blocks(&self, order: usize) -> usize {
+        let mut count = 0;
+        let mut current = self.free_lists[order].as_deref();
+        while let Some(block) = current {
+            count += 1;
+            current = block.next.as_deref();
+        }
+        count
+    }
+
+    /// Add free block at physical address `addr` to front of list
+    unsafe fn push(&mut self, order: usize, addr: u64) {
+        let block_ptr: *mut FreeBlock = (self.phys_mem_offset + addr).as_mut_ptr();
+        block_ptr.write(FreeBlock {
+            next: self.free_lists[order].take(),
+        });
+        self.free_lists[order] = Some(&mut *block_ptr);
+    }
+
+    /// Take first free block of list
+    ///
+    /// Returns physical address of the block
+    fn pop(&mut self, order: usize) -> Option<u64> {
+        let block = self.free_lists[order].take()?;
+        self.free_lists[order] = block.next.take();
+        Some(block as *mut FreeBlock as u64 - self.phys_mem_offset.as_u64())
+    }
+
+    /// Remove block at physical address `addr` from the list of given order
+    ///
+    /// Returns false if the block is not free
+    fn remove(&mut self, order: usize, addr: u64) -> bool {
+        let target = self.phys_mem_offset.as_u64() + addr;
+        let mut current = &mut self.free_lists[order];
+
+        while current.is_some() {
+            let block_addr = current.as_deref().map(|b| b as *const FreeBlock as u64);
+            if block_addr == Some(target) {
+                let block = current.take().unwrap();
+                *current = block.next.take();
+                return true;
+            }
+            current = &mut current.as_mut().unwrap().next;
+        }
+
+        false
+    }
+}
+
+/// Size in bytes of block with given order
+const fn block_size(order: usize) -> u64 {
+    FRAME_SIZE << order
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate(0).map(PhysFrame::containing_address)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.deallocate(frame.start_address(), 0)
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let order = Self::order_for((Size2MiB::SIZE / FRAME_SIZE) as usize);
+        self.allocate(order).map(PhysFrame::containing_address)
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let order = Self::order_for((Size2MiB::SIZE / FRAME_SIZE) as usize);
+        self.deallocate(frame.start_address(), order)
+    }
+}
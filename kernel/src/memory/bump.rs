@@ -1,7 +1,8 @@
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr;
 
-use super::allocator::{align_up, Locked};
+use super::allocator::{align_up, GrowableHeap, Locked};
+use super::stats::{AllocCounters, HeapStatistics, HeapStats};
 use crate::serial_println;
 
 unsafe impl GlobalAlloc for Locked<BumpAllocator> {
@@ -17,11 +18,18 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
             None => return ptr::null_mut(),
         };
 
+        // heap exhausted, map more pages
+        if alloc_end > allocator.heap_end {
+            let missing = alloc_end - allocator.heap_end;
+            allocator.grow(missing);
+        }
+
         if alloc_end > allocator.heap_end {
             ptr::null_mut()
         } else {
             allocator.next = alloc_end;
             allocator.allocations += 1;
+            allocator.counters.record_alloc(layout.size());
             alloc_start as *mut u8
         }
     }
@@ -29,6 +37,7 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
 
+        allocator.counters.record_free(layout.size());
         allocator.allocations -= 1;
         if allocator.allocations == 0 {
             allocator.next = allocator.heap_start;
@@ -40,6 +49,7 @@ pub struct BumpAllocator {
     heap_end: usize,
     next: usize,
     allocations: usize,
+    counters: AllocCounters,
 }
 
 impl BumpAllocator {
@@ -49,6 +59,7 @@ impl BumpAllocator {
             heap_end: 0,
             next: 0,
             allocations: 0,
+            counters: AllocCounters::new(),
         }
     }
 
@@ -66,3 +77,29 @@ impl BumpAllocator {
         self.next = heap_start;
     }
 }
+
+impl GrowableHeap for BumpAllocator {
+    fn heap_top(&self) -> usize {
+        self.heap_end
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        self.heap_end += by;
+    }
+}
+
+impl HeapStatistics for BumpAllocator {
+    fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new(
+            "BumpAllocator",
+            self.heap_end - self.heap_start,
+            self.counters,
+        );
+
+        // everything after next is one free region
+        stats.free_bytes = self.heap_end - self.next;
+        stats.free_regions = Some(1);
+        stats.largest_free_region = Some(stats.free_bytes);
+        stats
+    }
+}
@@ -0,0 +1,276 @@
+//! Copy-on-write sharing of physical frames between page tables
+//!
+//! A shared writable page is mapped read-only with the `COPY_ON_WRITE`
+//! flag into every page table using it. The first write faults, the page
+//! fault handler gives the writer a private copy and drops its reference
+//! to the shared frame. The last remaining user gets the frame back
+//! writable without a copy
+//!
+//! Reference counts are kept per frame in an array taken from the frame
+//! allocator at boot, so they can be updated from the page fault handler
+//! without allocating on the heap
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use conquer_once::spin::OnceCell;
+use x86_64::{
+    structures::paging::{
+        mapper::{FlagUpdateError, MapToError, MappedFrame, TranslateResult},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTableFlags,
+        PhysFrame, Size4KiB, Translate,
+    },
+    VirtAddr,
+};
+
+use super::bitmap::BitmapFrameAllocator;
+
+/// Page table flag marking a page as copy-on-write, one of the bits
+/// available to the OS
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Number of page table mappings of every frame, indexed by frame number
+///
+/// 0 means the frame is not shared and owned by a single mapping
+static REF_COUNTS: OnceCell<&'static [AtomicU16]> = OnceCell::uninit();
+
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+
+/// Errors returned when sharing pages
+#[derive(Debug)]
+pub enum CowError {
+    /// Page is not mapped in the source page table
+    NotMapped,
+    /// Page is mapped with a huge page
+    HugePage,
+    /// Frame is outside of the reference count table
+    UntrackedFrame,
+    /// Frame is already mapped by the maximum number of page tables
+    TooManyReferences,
+    /// Page of the source table couldn't be made copy-on-write
+    UpdateFlags(FlagUpdateError),
+    Map(MapToError<Size4KiB>),
+}
+
+/// Reserve the reference count table, one counter per frame of the frame
+/// allocator
+pub fn init(frame_allocator: &mut BitmapFrameAllocator, phys_mem_offset: VirtAddr) {
+    let frame_count = frame_allocator.frame_count();
+    let table_size = frame_count * core::mem::size_of::<AtomicU16>();
+    let table_frames = (table_size as u64 + FRAME_SIZE - 1) / FRAME_SIZE;
+
+    let start = frame_allocator
+        .allocate_contiguous(table_frames as usize, 1)
+        .expect("no contiguous memory for frame reference counts");
+
+    let table_ptr: *mut AtomicU16 = (phys_mem_offset + start.start_address().as_u64()).as_mut_ptr();
+    let table = unsafe {
+        table_ptr.write_bytes(0, frame_count);
+        core::slice::from_raw_parts(table_ptr, frame_count)
+    };
+
+    REF_COUNTS
+        .try_init_once(|| table)
+        .expect("cow::init should only be called once");
+}
+
+fn ref_counter(frame: PhysFrame) -> Option<&'static AtomicU16> {
+    let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+    REF_COUNTS.try_get().ok()?.get(index)
+}
+
+/// Number of page tables mapping the frame, 0 if it is not shared
+pub fn ref_count(frame: PhysFrame) -> u16 {
+    ref_counter(frame).map_or(0, |count| count.load(Ordering::SeqCst))
+}
+
+/// Count one more mapping of the frame
+fn acquire_frame(frame: PhysFrame) -> Result<(), CowError> {
+    let counter = ref_counter(frame).ok_or(CowError::UntrackedFrame)?;
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| match count {
+            // unshared frame has one implicit owner
+            0 => Some(2),
+            u16::MAX => None,
+            count => Some(count + 1),
+        })
+        .map(|_| ())
+        .map_err(|_| CowError::TooManyReferences)
+}
+
+/// Drop one mapping of the frame
+///
+/// Returns true if it was the last one and the caller should free the frame
+pub fn release_frame(frame: PhysFrame) -> bool {
+    let counter = match ref_counter(frame) {
+        Some(counter) => counter,
+        None => return true,
+    };
+
+    let previous = counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| match count {
+            0 => Some(0),
+            // remaining user owns the frame alone again
+            2 => Some(0),
+            count => Some(count - 1),
+        })
+        .unwrap_or(0);
+
+    previous == 0
+}
+
+/// Map `page` of `src` into `dst` at the same address, sharing its frame
+///
+/// Writable pages become read-only copy-on-write pages in both tables.
+/// Intermediate tables of `dst` are allocated from `frame_allocator`
+///
+/// # Safety
+///
+/// The caller must ensure both page tables are valid, `src` must be the
+/// active page table or its TLB entries flushed before it is used
+pub unsafe fn share_page(
+    src: &mut OffsetPageTable,
+    dst: &mut OffsetPageTable,
+    page: Page<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), CowError> {
+    let (frame, flags) = match src.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } => (frame, flags),
+        TranslateResult::Mapped { .. } => return Err(CowError::HugePage),
+        _ => return Err(CowError::NotMapped),
+    };
+
+    let flags = if flags.contains(PageTableFlags::WRITABLE) {
+        (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE
+    } else {
+        flags
+    };
+
+    acquire_frame(frame)?;
+
+    // a writable source would write through to the shared frame
+    match src.update_flags(page, flags) {
+        Ok(flush) => flush.flush(),
+        Err(err) => {
+            release_frame(frame);
+            return Err(CowError::UpdateFlags(err));
+        }
+    }
+
+    match dst.map_to_with_table_flags(page, frame, flags, table_flags(flags), frame_allocator) {
+        // dst isn't active, nothing to flush
+        Ok(flush) => flush.ignore(),
+        Err(err) => {
+            release_frame(frame);
+            return Err(CowError::Map(err));
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve write fault on a copy-on-write page of `mapper`
+///
+/// Copies the frame if it is still shared, otherwise makes the page
+/// writable again. Returns false if the page is not copy-on-write
+pub fn handle_write_fault(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut BitmapFrameAllocator,
+    addr: VirtAddr,
+) -> bool {
+    let page: Page<Size4KiB> = Page::containing_address(addr);
+
+    let (frame, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } => (frame, flags),
+        _ => return false,
+    };
+
+    if !flags.contains(COPY_ON_WRITE) {
+        return false;
+    }
+
+    let shared_flags = flags;
+    let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+
+    // last user keeps the frame
+    if ref_count(frame) == 0 {
+        return match unsafe { mapper.update_flags(page, flags) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    let copy = match frame_allocator.allocate_frame() {
+        Some(copy) => copy,
+        None => return false,
+    };
+
+    let phys_mem_offset = mapper.phys_offset();
+    unsafe {
+        let src: *const u8 = (phys_mem_offset + frame.start_address().as_u64()).as_ptr();
+        let dst: *mut u8 = (phys_mem_offset + copy.start_address().as_u64()).as_mut_ptr();
+        core::ptr::copy_nonoverlapping(src, dst, FRAME_SIZE as usize);
+    }
+
+    let remapped = unsafe {
+        match mapper.unmap(page) {
+            Ok((_, flush)) => flush.flush(),
+            Err(_) => {
+                frame_allocator.deallocate_frame(copy);
+                return false;
+            }
+        }
+
+        // tables of the page exist, mapping can only fail if they are corrupt
+        match mapper.map_to_with_table_flags(page, copy, flags, table_flags(flags), frame_allocator)
+        {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(_) => {
+                // map the shared frame again so the page isn't lost
+                let table_flags = table_flags(shared_flags);
+                if let Ok(flush) = mapper.map_to_with_table_flags(
+                    page,
+                    frame,
+                    shared_flags,
+                    table_flags,
+                    frame_allocator,
+                ) {
+                    flush.flush();
+                }
+                false
+            }
+        }
+    };
+
+    if !remapped {
+        unsafe { frame_allocator.deallocate_frame(copy) };
+        return false;
+    }
+
+    // another user may have dropped its reference in the meantime
+    if release_frame(frame) {
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+
+    true
+}
+
+/// Flags of tables created for a page, writable so the page can become
+/// writable after the copy
+fn table_flags(flags: PageTableFlags) -> PageTableFlags {
+    let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    table_flags | (flags & PageTableFlags::USER_ACCESSIBLE)
+}
@@ -0,0 +1,257 @@
+//! Heap corruption detection, enabled with the `heap-debug` feature
+//!
+//! Every allocation is wrapped in red zones and a header recording its
+//! layout, memory is poisoned when freed and checked to be still poisoned
+//! when the block is handed out again. Corruption found on free or reuse,
+//! or by the free list checks of the allocators, is passed to the
+//! corruption handler which panics by default.
+
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::{mem, ptr, slice};
+
+use spin::Mutex;
+
+/// Bytes of guard memory before and after every allocation
+pub const RED_ZONE: usize = 16;
+
+/// Pattern written into red zones
+pub const RED_ZONE_BYTE: u8 = 0xfd;
+
+/// Pattern written over freed memory
+pub const POISON_BYTE: u8 = 0xde;
+
+/// Bytes at the start of every block left to the wrapped allocator, which
+/// writes its free list node there once the block is freed
+const INNER_RESERVED: usize = 16;
+
+const HEADER_MAGIC: u64 = 0x4f52_4f53_4845_4150;
+const STATE_ALLOCATED: u64 = 0xa110_ca7e;
+const STATE_FREED: u64 = 0xf4ee_d000;
+
+/// Kind of heap corruption detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapError {
+    /// Pointer was already freed
+    DoubleFree,
+    /// Pointer freed with a layout other than the one it was allocated with
+    LayoutMismatch { allocated: Layout },
+    /// Write past the end of the allocation
+    Overflow,
+    /// Write before the start of the allocation
+    Underflow,
+    /// Pointer was never handed out by the allocator, or its header was
+    /// overwritten
+    InvalidPointer,
+    /// Free list node of an allocator points outside of the heap
+    CorruptedFreeList,
+    /// Freed memory was written before it was handed out again
+    UseAfterFree,
+}
+
+/// Detected heap corruption with the offending address and layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapCorruption {
+    pub error: HeapError,
+    pub addr: usize,
+    pub layout: Layout,
+}
+
+pub type CorruptionHandler = fn(&HeapCorruption);
+
+static CORRUPTION_HANDLER: Mutex<CorruptionHandler> = Mutex::new(panic_handler);
+
+fn panic_handler(corruption: &HeapCorruption) {
+    panic!(
+        "heap corruption: {:?} at {:#x}, {:?}",
+        corruption.error, corruption.addr, corruption.layout
+    );
+}
+
+/// Replace the function called on detected corruption
+///
+/// If the handler returns, the offending free is skipped and its memory
+/// leaked. Returns the previous handler
+pub fn set_corruption_handler(handler: CorruptionHandler) -> CorruptionHandler {
+    mem::replace(&mut *CORRUPTION_HANDLER.lock(), handler)
+}
+
+/// Pass detected corruption to the corruption handler
+pub fn report(error: HeapError, addr: usize, layout: Layout) {
+    let handler = *CORRUPTION_HANDLER.lock();
+    handler(&HeapCorruption {
+        error,
+        addr,
+        layout,
+    });
+}
+
+/// Bookkeeping written right before the front red zone of every allocation
+///
+/// Block layout is `[reserved][padding][header][red zone][data][red zone]`.
+/// On free a copy marked as freed is also written right after the reserved
+/// bytes, where it is found again when the block is reused
+#[repr(C)]
+struct Header {
+    magic: u64,
+    state: u64,
+    size: usize,
+    align: usize,
+    /// Offset from start of the inner block to the user pointer
+    front: usize,
+}
+
+const HEADER_SIZE: usize = mem::size_of::<Header>();
+
+/// Global allocator wrapper adding red zones, poisoning and checks
+pub struct DebugAllocator {
+    inner: &'static (dyn GlobalAlloc + Sync),
+}
+
+impl DebugAllocator {
+    pub const fn new(inner: &'static (dyn GlobalAlloc + Sync)) -> Self {
+        Self { inner }
+    }
+
+    /// Layout of the inner block holding the allocation, header and red zones
+    ///
+    /// Returns inner layout and offset of the user pointer
+    fn inner_layout(layout: Layout) -> Option<(Layout, usize)> {
+        let align = layout.align().max(mem::align_of::<Header>());
+        let front = (INNER_RESERVED + HEADER_SIZE + RED_ZONE + align - 1) & !(align - 1);
+        let size = front.checked_add(layout.size())?.checked_add(RED_ZONE)?;
+
+        Some((Layout::from_size_align(size, align).ok()?, front))
+    }
+
+    /// Check header and red zones of allocation
+    ///
+    /// Returns true if the allocation is safe to free
+    unsafe fn check(ptr: *mut u8, layout: Layout) -> bool {
+        let header = &*header(ptr);
+        let addr = ptr as usize;
+
+        if header.magic != HEADER_MAGIC {
+            report(HeapError::InvalidPointer, addr, layout);
+            return false;
+        }
+
+        if header.state == STATE_FREED {
+            report(HeapError::DoubleFree, addr, layout);
+            return false;
+        }
+
+        if header.state != STATE_ALLOCATED {
+            report(HeapError::InvalidPointer, addr, layout);
+            return false;
+        }
+
+        if header.size != layout.size() || header.align != layout.align() {
+            let allocated = Layout::from_size_align_unchecked(header.size, header.align);
+            report(HeapError::LayoutMismatch { allocated }, addr, layout);
+            return false;
+        }
+
+        // red zones are reported but the allocation is still freed
+        let front_zone = slice::from_raw_parts(ptr.sub(RED_ZONE), RED_ZONE);
+        if front_zone.iter().any(|&b| b != RED_ZONE_BYTE) {
+            report(HeapError::Underflow, addr, layout);
+        }
+
+        let back_zone = slice::from_raw_parts(ptr.add(layout.size()), RED_ZONE);
+        if back_zone.iter().any(|&b| b != RED_ZONE_BYTE) {
+            report(HeapError::Overflow, addr, layout);
+        }
+
+        true
+    }
+
+    /// Check the memory freed from an inner block, now handed out again,
+    /// is still poisoned
+    ///
+    /// Only the part of the old allocation within the new block of `size`
+    /// bytes is checked, the rest may belong to other allocations
+    unsafe fn check_reuse(block: *mut u8, size: usize) {
+        let freed = &mut *(block.add(INNER_RESERVED) as *mut Header);
+        if freed.magic != HEADER_MAGIC || freed.state != STATE_FREED {
+            return;
+        }
+        // stale once checked
+        freed.magic = 0;
+
+        let end = (freed.front + freed.size).min(size);
+        if freed.front >= end {
+            return;
+        }
+
+        let old = slice::from_raw_parts(block.add(freed.front), end - freed.front);
+        if old.iter().any(|&b| b != POISON_BYTE) {
+            let layout = Layout::from_size_align_unchecked(freed.size, freed.align);
+            report(
+                HeapError::UseAfterFree,
+                block.add(freed.front) as usize,
+                layout,
+            );
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for DebugAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (inner_layout, front) = match Self::inner_layout(layout) {
+            Some(inner) => inner,
+            None => return ptr::null_mut(),
+        };
+
+        let block = self.inner.alloc(inner_layout);
+        if block.is_null() {
+            return block;
+        }
+        Self::check_reuse(block, inner_layout.size());
+
+        let ptr = block.add(front);
+        ptr::write_bytes(ptr.sub(RED_ZONE), RED_ZONE_BYTE, RED_ZONE);
+        ptr::write_bytes(ptr.add(layout.size()), RED_ZONE_BYTE, RED_ZONE);
+        header(ptr).write(Header {
+            magic: HEADER_MAGIC,
+            state: STATE_ALLOCATED,
+            size: layout.size(),
+            align: layout.align(),
+            front,
+        });
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !Self::check(ptr, layout) {
+            return;
+        }
+
+        let (inner_layout, front) = Self::inner_layout(layout).unwrap();
+
+        // header stays behind marked as freed to catch double frees
+        ptr::write_bytes(ptr, POISON_BYTE, layout.size());
+        (*header(ptr)).state = STATE_FREED;
+
+        // copy at a fixed offset, the header moves with the alignment
+        let block = ptr.sub(front);
+        (block.add(INNER_RESERVED) as *mut Header).write(header(ptr).read());
+
+        self.inner.dealloc(block, inner_layout)
+    }
+}
+
+/// Start of the inner block of an allocation made by `DebugAllocator`,
+/// where the wrapped allocator keeps its own bookkeeping
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `DebugAllocator`
+pub unsafe fn block_start(ptr: *mut u8) -> *mut u8 {
+    ptr.sub((*header(ptr)).front)
+}
+
+/// Header of the allocation at `ptr`
+fn header(ptr: *mut u8) -> *mut Header {
+    ptr.wrapping_sub(RED_ZONE + HEADER_SIZE) as *mut Header
+}
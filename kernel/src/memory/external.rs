@@ -0,0 +1,89 @@
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use linked_list_allocator::Heap;
+
+use super::allocator::{GrowableHeap, Locked};
+use super::stats::{AllocCounters, HeapStatistics, HeapStats};
+use crate::serial_println;
+
+unsafe impl GlobalAlloc for Locked<ExternalLinkedListAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+
+        let ptr = match allocator.heap.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            // heap exhausted, map more pages and retry
+            Err(_) if allocator.grow(layout.size() + layout.align()) => allocator
+                .heap
+                .allocate_first_fit(layout)
+                .map_or(ptr::null_mut(), |ptr| ptr.as_ptr()),
+            Err(_) => ptr::null_mut(),
+        };
+
+        if !ptr.is_null() {
+            allocator.counters.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.lock();
+
+        allocator.counters.record_free(layout.size());
+        allocator
+            .heap
+            .deallocate(NonNull::new(ptr).unwrap(), layout)
+    }
+}
+
+/// Heap allocator from the external `linked_list_allocator` crate
+///
+/// Wrapped to add heap growth and allocation counters
+pub struct ExternalLinkedListAllocator {
+    heap: Heap,
+    counters: AllocCounters,
+}
+
+impl ExternalLinkedListAllocator {
+    pub const fn new() -> Self {
+        Self {
+            heap: Heap::empty(),
+            counters: AllocCounters::new(),
+        }
+    }
+
+    /// Initialize allocator with given heap bounds
+    ///
+    /// # Safety
+    ///
+    /// Marked as unsafe because the caller must ensure the given
+    /// memory range is unused. Method should only be called once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        serial_println!("Initializing external LinkedList heap allocator");
+        self.heap.init(heap_start, heap_size)
+    }
+}
+
+impl GrowableHeap for ExternalLinkedListAllocator {
+    fn heap_top(&self) -> usize {
+        self.heap.top()
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        self.heap.extend(by)
+    }
+}
+
+impl HeapStatistics for ExternalLinkedListAllocator {
+    fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new(
+            "ExternalLinkedListAllocator",
+            self.heap.size(),
+            self.counters,
+        );
+        // the crate keeps its hole list private, only totals are known
+        stats.free_bytes = self.heap.free();
+        stats
+    }
+}
@@ -0,0 +1,164 @@
+//! Page faults resolved by the memory subsystem
+//!
+//! Lazily backed VMM regions get zeroed frames on first access, pages of
+//! the heap growth range get the zeroed frame reserved for them. Writes to
+//! copy-on-write pages get a private copy of the page.
+//!
+//! Called from the exception dispatcher before the fault is reported. Heap
+//! pages are made present without taking any lock. Otherwise locks are only
+//! tried, a fault taken while the VMM, mapper or frame allocator is locked
+//! can't be resolved and is reported instead
+
+use x86_64::{
+    structures::{
+        idt::PageFaultErrorCode,
+        paging::{
+            mapper::{MappedFrame, TranslateResult},
+            FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags,
+            PhysFrame, Size4KiB, Translate,
+        },
+    },
+    VirtAddr,
+};
+
+use super::{
+    active_lvl_4_table, cow,
+    vmm::{Backing, VMM},
+};
+
+/// Try to resolve page fault at `addr`
+///
+/// Maps a zeroed frame for faults on not yet touched pages of lazily
+/// backed regions. Returns false if the fault is not handled here and
+/// should be reported
+pub fn handle_page_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    // present pages fault on protection violations, only copy-on-write
+    // pages are resolved
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        return error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) && copy_on_write(addr);
+    }
+
+    // the heap grows while the VMM may be locked, its range is known
+    // without looking it up
+    if let Ok(heap) = super::HEAP_GROWTH.try_get() {
+        if heap.contains(addr) {
+            // past the end of the heap is a stray access, not growth
+            return addr.as_u64() < super::heap_top() as u64
+                && back_heap_page(Page::containing_address(addr));
+        }
+    }
+
+    let region = match VMM.try_lock() {
+        Some(vmm) => match vmm.find(addr) {
+            Some(region) => *region,
+            None => return false,
+        },
+        None => return false,
+    };
+
+    if region.backing != Backing::Lazy || region.is_guard(addr) {
+        return false;
+    }
+
+    let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    if write && !region.flags.contains(PageTableFlags::WRITABLE) {
+        return false;
+    }
+
+    map_zeroed(Page::containing_address(addr), region.flags)
+}
+
+/// Make heap `page` present, zeroing the frame `grow_heap` reserved for it
+///
+/// Fresh heap pages are touched while the mapper may be locked, so the
+/// active table is changed without it. `grow_heap` wrote the entry before
+/// moving the heap top past it, only its present flag changes here. The
+/// heap is in the kernel half, shared by all address spaces
+fn back_heap_page(page: Page<Size4KiB>) -> bool {
+    let phys_mem_offset = match super::try_phys_mem_offset() {
+        Some(phys_mem_offset) => phys_mem_offset,
+        None => return false,
+    };
+    let mut active =
+        unsafe { OffsetPageTable::new(active_lvl_4_table(phys_mem_offset), phys_mem_offset) };
+
+    let (frame, flags) = match active.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } if !flags.contains(PageTableFlags::PRESENT) => (frame, flags),
+        _ => return false,
+    };
+
+    zero_frame(frame);
+    match unsafe { active.update_flags(page, flags | PageTableFlags::PRESENT) } {
+        Ok(flush) => {
+            flush.flush();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Back `page` with a zeroed frame
+///
+/// Both the mapper and frame allocator are only tried, the fault may be
+/// taken while either is locked
+fn map_zeroed(page: Page<Size4KiB>, flags: PageTableFlags) -> bool {
+    let (mapper, frame_allocator) =
+        match (super::MAPPER.try_get(), super::FRAME_ALLOCATOR.try_get()) {
+            (Ok(mapper), Ok(frame_allocator)) => (mapper, frame_allocator),
+            _ => return false,
+        };
+    let (mut mapper, mut frame_allocator) = match (mapper.try_lock(), frame_allocator.try_lock()) {
+        (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+        _ => return false,
+    };
+
+    let frame = match frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+
+    zero_frame(frame);
+    let flags = flags | PageTableFlags::PRESENT;
+    match unsafe { mapper.map_to(page, frame, flags, &mut *frame_allocator) } {
+        Ok(flush) => {
+            flush.flush();
+            true
+        }
+        Err(_) => {
+            unsafe { frame_allocator.deallocate_frame(frame) };
+            false
+        }
+    }
+}
+
+/// Zero `frame` through the physical memory mapping, before it is visible
+/// at its page
+fn zero_frame(frame: PhysFrame) {
+    let frame_ptr: *mut u8 =
+        (super::phys_mem_offset() + frame.start_address().as_u64()).as_mut_ptr();
+    unsafe { frame_ptr.write_bytes(0, Page::<Size4KiB>::SIZE as usize) };
+}
+
+/// Give the writer of a copy-on-write page its own copy
+fn copy_on_write(addr: VirtAddr) -> bool {
+    let (mapper, frame_allocator) =
+        match (super::MAPPER.try_get(), super::FRAME_ALLOCATOR.try_get()) {
+            (Ok(mapper), Ok(frame_allocator)) => (mapper, frame_allocator),
+            _ => return false,
+        };
+    // global mapper lock is held while the active table is changed, it
+    // may be a page table of an other address space
+    let (_mapper, mut frame_allocator) = match (mapper.try_lock(), frame_allocator.try_lock()) {
+        (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+        _ => return false,
+    };
+
+    let phys_mem_offset = super::phys_mem_offset();
+    let mut active =
+        unsafe { OffsetPageTable::new(active_lvl_4_table(phys_mem_offset), phys_mem_offset) };
+    cow::handle_write_fault(&mut active, &mut frame_allocator, addr)
+}
@@ -1,45 +1,60 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::mem;
-use core::ptr::{self, NonNull};
 
 use crate::serial_println;
 
 use super::allocator::Locked;
+#[cfg(feature = "heap-debug")]
+use super::debug::{self, HeapError};
+use super::linked_list::LinkedListAllocator;
+use super::stats::{AllocCounters, HeapStatistics, HeapStats};
 
 unsafe impl GlobalAlloc for Locked<FixedSizeAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
 
-        match FixedSizeAllocator::list_index(&layout) {
+        let ptr = match FixedSizeAllocator::list_index(&layout) {
             Some(index) => {
                 match allocator.list_heads[index].take() {
-                    Some(node) => {
+                    Some(node) if allocator.check_node(node, index, &layout) => {
                         allocator.list_heads[index] = node.next.take();
                         node as *mut ListNode as *mut u8
                     }
-                    None => {
+                    _ => {
                         // no list exists for that size => allocate new ListNode to the list
+                        // a corrupted list is dropped the same way
                         let block_size = BLOCK_SIZES[index];
                         // only works if size is power of two
                         let block_align = block_size;
 
                         let layout = Layout::from_size_align(block_size, block_align).unwrap();
 
-                        allocator.fallback_alloc(layout)
+                        allocator.fallback_allocator.allocate(layout)
                     }
                 }
             }
-            None => allocator.fallback_alloc(layout),
+            None => allocator.fallback_allocator.allocate(layout),
+        };
+
+        if !ptr.is_null() {
+            allocator.counters.record_alloc(layout.size());
         }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
+        allocator.counters.record_free(layout.size());
 
         match FixedSizeAllocator::list_index(&layout) {
             Some(index) => {
+                if !allocator.check_block(ptr as usize, index, &layout) {
+                    return;
+                }
+
+                let head = allocator.list_heads[index].take();
                 let new_node = ListNode {
-                    next: allocator.list_heads[index].take(),
+                    next: head.filter(|head| allocator.check_node(head, index, &layout)),
                 };
 
                 // verify that block has size and alignment
@@ -51,10 +66,7 @@ unsafe impl GlobalAlloc for Locked<FixedSizeAllocator> {
                 new_node_ptr.write(new_node);
                 allocator.list_heads[index] = Some(&mut *new_node_ptr)
             }
-            None => {
-                let ptr = NonNull::new(ptr).unwrap();
-                allocator.fallback_allocator.deallocate(ptr, layout)
-            }
+            None => allocator.fallback_allocator.deallocate(ptr, layout),
         }
     }
 }
@@ -73,7 +85,8 @@ pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
 pub struct FixedSizeAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
-    fallback_allocator: linked_list_allocator::Heap,
+    fallback_allocator: LinkedListAllocator,
+    counters: AllocCounters,
 }
 
 impl FixedSizeAllocator {
@@ -81,7 +94,8 @@ impl FixedSizeAllocator {
         const EMPTY: Option<&'static mut ListNode> = None;
         Self {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
-            fallback_allocator: linked_list_allocator::Heap::empty(),
+            fallback_allocator: LinkedListAllocator::new(),
+            counters: AllocCounters::new(),
         }
     }
 
@@ -96,12 +110,53 @@ impl FixedSizeAllocator {
         self.fallback_allocator.init(heap_start, heap_size)
     }
 
-    /// Allocates using the fallback allocator
-    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.fallback_allocator.allocate_first_fit(layout) {
-            Ok(ptr) => ptr.as_ptr(),
-            Err(_) => ptr::null_mut(),
+    /// Check that free list node and its successor lie in the heap,
+    /// aligned to their block size
+    ///
+    /// Reports corrupted nodes, only checks with `heap-debug` enabled
+    #[cfg(feature = "heap-debug")]
+    fn check_node(&self, node: &ListNode, index: usize, layout: &Layout) -> bool {
+        let node_addr = node as *const ListNode as usize;
+        // read successor as plain address, it may be garbage
+        let next_addr = unsafe { *(node as *const ListNode as *const usize) };
+
+        if self.valid_block(node_addr, index)
+            && (next_addr == 0 || self.valid_block(next_addr, index))
+        {
+            return true;
+        }
+
+        debug::report(HeapError::CorruptedFreeList, node_addr, *layout);
+        false
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    fn check_node(&self, _node: &ListNode, _index: usize, _layout: &Layout) -> bool {
+        true
+    }
+
+    /// Check that a block being freed was handed out by the allocator
+    ///
+    /// Reports invalid blocks, only checks with `heap-debug` enabled
+    #[cfg(feature = "heap-debug")]
+    fn check_block(&self, addr: usize, index: usize, layout: &Layout) -> bool {
+        if self.valid_block(addr, index) {
+            return true;
         }
+
+        debug::report(HeapError::InvalidPointer, addr, *layout);
+        false
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    fn check_block(&self, _addr: usize, _index: usize, _layout: &Layout) -> bool {
+        true
+    }
+
+    #[cfg(feature = "heap-debug")]
+    fn valid_block(&self, addr: usize, index: usize) -> bool {
+        let heap = self.fallback_allocator.bottom()..self.fallback_allocator.top();
+        heap.contains(&addr) && addr % BLOCK_SIZES[index] == 0
     }
 
     /// Chose an appropriate block size given a layout
@@ -112,3 +167,26 @@ impl FixedSizeAllocator {
         BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
     }
 }
+
+impl HeapStatistics for FixedSizeAllocator {
+    fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new(
+            "FixedSizeAllocator",
+            self.fallback_allocator.size(),
+            self.counters,
+        );
+        self.fallback_allocator.walk_free_regions(&mut stats);
+
+        let mut free_blocks = [0; BLOCK_SIZES.len()];
+        for (count, head) in free_blocks.iter_mut().zip(self.list_heads.iter()) {
+            let mut current = head.as_deref();
+            while let Some(node) = current {
+                *count += 1;
+                current = node.next.as_deref();
+            }
+        }
+        stats.free_blocks = Some(free_blocks);
+
+        stats
+    }
+}
@@ -1,36 +1,32 @@
 use core::{
     alloc::{GlobalAlloc, Layout},
-    mem, ptr,
+    mem,
+    ops::Range,
+    ptr,
 };
 
-use crate::{println, serial_println};
+use crate::serial_println;
 
-use super::allocator::{align_up, Locked};
+use super::allocator::{align_up, GrowableHeap, Locked};
+#[cfg(feature = "heap-debug")]
+use super::debug::{self, HeapError};
+use super::stats::{AllocCounters, HeapStatistics, HeapStats};
 
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
-    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-        let (size, align) = LinkedListAllocator::size_align(layout);
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
 
-        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
-            let alloc_end = alloc_start.checked_add(size).expect("overflow");
-            let excess_size = region.end_addr() - alloc_end;
-
-            if excess_size > 0 {
-                allocator.add_free_region(alloc_end, excess_size);
-            }
-
-            alloc_start as *mut u8
-        } else {
-            ptr::null_mut()
+        let ptr = allocator.allocate(layout);
+        if !ptr.is_null() {
+            allocator.counters.record_alloc(layout.size());
         }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        // perform layout adjustments
-        let (size, _) = LinkedListAllocator::size_align(layout);
-
-        self.lock().add_free_region(ptr as usize, size)
+        let mut allocator = self.lock();
+        allocator.counters.record_free(layout.size());
+        allocator.deallocate(ptr, layout)
     }
 }
 
@@ -55,6 +51,9 @@ impl ListNode {
 
 pub struct LinkedListAllocator {
     head: ListNode,
+    heap_start: usize,
+    heap_end: usize,
+    counters: AllocCounters,
 }
 
 impl LinkedListAllocator {
@@ -62,6 +61,9 @@ impl LinkedListAllocator {
     pub const fn new() -> Self {
         Self {
             head: ListNode::new(0),
+            heap_start: 0,
+            heap_end: 0,
+            counters: AllocCounters::new(),
         }
     }
 
@@ -74,6 +76,87 @@ impl LinkedListAllocator {
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
         serial_println!("Initializing LinkedList heap allocator");
         self.add_free_region(heap_start, heap_size);
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
+    }
+
+    /// Allocate first free region fitting `layout`
+    ///
+    /// Grows the heap and searches again if no region is large enough,
+    /// returns null if the heap can't grow
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::size_align(layout);
+
+        let region = match self.find_region(size, align) {
+            Some(region) => Some(region),
+            // heap exhausted, map more pages and search again
+            None if self.grow(size + align) => self.find_region(size, align),
+            None => None,
+        };
+
+        match region {
+            Some((region, alloc_start)) => {
+                let alloc_end = alloc_start.checked_add(size).expect("overflow");
+                let excess_size = region.end_addr() - alloc_end;
+                let padding = alloc_start - region.start_addr();
+
+                if excess_size > 0 {
+                    unsafe { self.add_free_region(alloc_end, excess_size) };
+                }
+                // space skipped to align the allocation stays free
+                if padding > 0 {
+                    unsafe { self.add_free_region(region.start_addr(), padding) };
+                }
+
+                alloc_start as *mut u8
+            }
+            None => ptr::null_mut(),
+        }
+    }
+
+    /// Return block allocated with `layout` to the free list
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure `ptr` was returned by `allocate` with the
+    /// same layout and is not used anymore
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        // perform layout adjustments
+        let (size, _) = Self::size_align(layout);
+        self.add_free_region(ptr as usize, size)
+    }
+
+    /// Start address of the heap
+    pub fn bottom(&self) -> usize {
+        self.heap_start
+    }
+
+    /// End address of the heap
+    pub fn top(&self) -> usize {
+        self.heap_end
+    }
+
+    /// Bytes of memory handed to the allocator
+    pub fn size(&self) -> usize {
+        self.heap_end - self.heap_start
+    }
+
+    /// Fill free memory of `stats` by walking the free list
+    pub fn walk_free_regions(&self, stats: &mut HeapStats) {
+        let mut regions = 0;
+        let mut largest = 0;
+        let mut free = 0;
+        let mut current = self.head.next.as_deref();
+        while let Some(region) = current {
+            regions += 1;
+            largest = largest.max(region.size);
+            free += region.size;
+            current = region.next.as_deref();
+        }
+
+        stats.free_bytes = free;
+        stats.free_regions = Some(regions);
+        stats.largest_free_region = Some(largest);
     }
 
     /// Create new region of available ListNodes
@@ -99,11 +182,17 @@ impl LinkedListAllocator {
     ///
     /// returns tuple of list node and start address of the allocation
     fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
+        let heap = self.heap_start..self.heap_end;
         // get mut ref to current head
         let mut current = &mut self.head;
 
         // walk down list to find large enough space
         while let Some(ref mut region) = current.next {
+            // nodes after a corrupted one can't be trusted, stop the walk
+            if !Self::check_node(&heap, region, size, align) {
+                return None;
+            }
+
             // this is the check if region is good enough
             // if not Ok, ie. required size not found, move onto next ListNode
             if let Ok(alloc_start) = Self::alloc_from_region(region, size, align) {
@@ -121,12 +210,44 @@ impl LinkedListAllocator {
         None
     }
 
+    /// Check that a free list node lies in the heap, aligned and large
+    /// enough to hold a node
+    ///
+    /// Reports corrupted nodes, only checks with `heap-debug` enabled
+    #[cfg(feature = "heap-debug")]
+    fn check_node(heap: &Range<usize>, node: &ListNode, size: usize, align: usize) -> bool {
+        let addr = node.start_addr();
+        let valid = heap.contains(&addr)
+            && addr % mem::align_of::<ListNode>() == 0
+            // size is only read once the node is known to be in the heap
+            && node.size >= mem::size_of::<ListNode>()
+            && node.size <= heap.end - addr;
+
+        if !valid {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            debug::report(HeapError::CorruptedFreeList, addr, layout);
+        }
+        valid
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    fn check_node(_heap: &Range<usize>, _node: &ListNode, _size: usize, _align: usize) -> bool {
+        true
+    }
+
     /// Try to use given region for an allocation with given size
     ///
     /// Returns allocation start address on success
     /// ie. region is an unused ListNode
     fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+
+        // padding in front of the allocation is freed again, it must be
+        // able to hold a ListNode too
+        let padding = alloc_start - region.start_addr();
+        if padding > 0 && padding < mem::size_of::<ListNode>() {
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         // required allocation size too large for region
@@ -160,3 +281,22 @@ impl LinkedListAllocator {
         (size, layout.align())
     }
 }
+
+impl GrowableHeap for LinkedListAllocator {
+    fn heap_top(&self) -> usize {
+        self.top()
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        self.add_free_region(self.heap_end, by);
+        self.heap_end += by;
+    }
+}
+
+impl HeapStatistics for LinkedListAllocator {
+    fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new("LinkedListAllocator", self.size(), self.counters);
+        self.walk_free_regions(&mut stats);
+        stats
+    }
+}
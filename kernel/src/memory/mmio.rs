@@ -0,0 +1,130 @@
+//! Uncached mappings of physical device memory
+
+use core::ptr;
+
+use x86_64::{structures::paging::PageTableFlags, PhysAddr, VirtAddr};
+
+use super::vmm::{RegionKind, VmmError, VMM};
+
+/// Flags device memory is mapped with
+pub const MMIO_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::WRITE_THROUGH)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// Device memory mapped into the kernel address space, unmapped on drop
+///
+/// Registers are accessed with volatile reads and writes at a byte offset
+/// from the start of the region. Offsets outside the region panic
+#[derive(Debug)]
+pub struct MmioRegion {
+    /// Start of the VMM region, page aligned
+    region_start: VirtAddr,
+    /// Address of `phys`, which may be inside the first page
+    base: VirtAddr,
+    phys: PhysAddr,
+    len: usize,
+}
+
+impl MmioRegion {
+    /// Map `len` bytes of device memory starting at `phys`
+    pub fn map(phys: PhysAddr, len: usize) -> Result<Self, VmmError> {
+        let page_phys = phys.align_down(4096u64);
+        let page_offset = phys - page_phys;
+
+        let region = VMM.lock().allocate_physical(
+            page_phys,
+            page_offset + len as u64,
+            RegionKind::Mmio,
+            MMIO_FLAGS,
+        )?;
+
+        Ok(Self {
+            region_start: region.start,
+            base: region.start + page_offset,
+            phys,
+            len,
+        })
+    }
+
+    /// Virtual address of the first byte of the region
+    pub fn base(&self) -> VirtAddr {
+        self.base
+    }
+
+    pub fn phys(&self) -> PhysAddr {
+        self.phys
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn read8(&self, offset: usize) -> u8 {
+        unsafe { ptr::read_volatile(self.ptr(offset)) }
+    }
+
+    pub fn read16(&self, offset: usize) -> u16 {
+        unsafe { ptr::read_volatile(self.ptr(offset)) }
+    }
+
+    pub fn read32(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile(self.ptr(offset)) }
+    }
+
+    pub fn read64(&self, offset: usize) -> u64 {
+        unsafe { ptr::read_volatile(self.ptr(offset)) }
+    }
+
+    pub fn write8(&self, offset: usize, value: u8) {
+        unsafe { ptr::write_volatile(self.ptr(offset), value) }
+    }
+
+    pub fn write16(&self, offset: usize, value: u16) {
+        unsafe { ptr::write_volatile(self.ptr(offset), value) }
+    }
+
+    pub fn write32(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile(self.ptr(offset), value) }
+    }
+
+    pub fn write64(&self, offset: usize, value: u64) {
+        unsafe { ptr::write_volatile(self.ptr(offset), value) }
+    }
+
+    /// Pointer to register of type `T` at `offset`
+    ///
+    /// Panics if the register is not inside the region or not aligned
+    fn ptr<T>(&self, offset: usize) -> *mut T {
+        let size = core::mem::size_of::<T>();
+        assert!(
+            offset
+                .checked_add(size)
+                .map_or(false, |end| end <= self.len),
+            "MMIO access at offset {:#x} outside of region of {:#x} bytes",
+            offset,
+            self.len
+        );
+
+        let addr = self.base + offset;
+        assert!(
+            addr.is_aligned(size as u64),
+            "unaligned MMIO access at {:#x}",
+            addr.as_u64()
+        );
+        addr.as_mut_ptr()
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        VMM.lock()
+            .release(self.region_start)
+            .expect("failed to unmap MMIO region");
+    }
+}
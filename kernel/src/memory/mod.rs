@@ -1,11 +1,15 @@
 use alloc::alloc::{GlobalAlloc, Layout};
-use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
+use bootloader_api::info::{FrameBuffer, MemoryRegionKind, MemoryRegions};
 
+use conquer_once::spin::OnceCell;
 use core::ptr::null_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use linked_list_allocator::LockedHeap;
+use spin::{Mutex, MutexGuard};
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, Page, PageSize,
+        PageTableFlags, Size4KiB,
     },
     VirtAddr,
 };
@@ -14,13 +18,69 @@ use x86_64::{
     PhysAddr,
 };
 
+use crate::serial_println;
+
+pub mod address_space;
 pub mod allocator;
+pub mod bitmap;
+pub mod buddy;
 pub mod bump;
+pub mod cow;
+#[cfg(feature = "heap-debug")]
+pub mod debug;
+pub mod external;
+pub mod fault;
 pub mod fixed;
-pub mod frame;
 pub mod linked_list;
+pub mod mmio;
+pub mod paging;
+pub mod slab;
+pub mod stack;
+pub mod stats;
+pub mod vmm;
+
+// checked ahead of the `ALLOCATOR` import, which is missing without a feature
+#[cfg(not(any(
+    feature = "alloc-bump",
+    feature = "alloc-linked-list",
+    feature = "alloc-fixed",
+    feature = "alloc-slab",
+    feature = "alloc-external-linked-list"
+)))]
+compile_error!("exactly one `alloc-*` feature must be enabled to select the global allocator");
+
+use allocator::{align_up, ALLOCATOR, HEAP_GROW_SIZE, HEAP_MAX_SIZE, HEAP_SIZE, HEAP_START};
+use bitmap::BitmapFrameAllocator;
+use buddy::BUDDY_ALLOCATOR;
+use mmio::MmioRegion;
+use paging::PageTableWalker;
+use stats::{HeapStatistics, MemoryStats};
+use vmm::{Region, RegionKind, VmmError, VMM};
+
+/// Page mapper for the active level 4 table, kept after boot by `init_globals`
+///
+/// The heap grows through it, allocations needing more heap fail while
+/// the lock is held
+pub static MAPPER: OnceCell<Mutex<OffsetPageTable<'static>>> = OnceCell::uninit();
+
+/// Range the heap grows into, reserved by `init_vmm`
+///
+/// Pages below the heap top have a frame reserved by `grow_heap` and are
+/// made present on first touch. Kept outside of the VMM so faults on it are
+/// resolved while the VMM is locked, its map lives on the heap
+pub static HEAP_GROWTH: OnceCell<Region> = OnceCell::uninit();
+
+/// End of the memory handed to the heap allocator, pages of the growth
+/// range below it have a frame reserved
+static HEAP_TOP: AtomicUsize = AtomicUsize::new(HEAP_START);
+
+/// Physical frame allocator, kept after boot by `init_globals`
+///
+/// Same as `MAPPER`, allocations needing more heap fail while the lock is held
+pub static FRAME_ALLOCATOR: OnceCell<Mutex<BitmapFrameAllocator>> = OnceCell::uninit();
 
-use allocator::{ALLOCATOR, HEAP_SIZE, HEAP_START};
+/// Virtual address at which all of physical memory is mapped
+static PHYS_MEM_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
 
 /// Initialize new OffsetPageTable
 /// # Safety
@@ -35,7 +95,7 @@ pub unsafe fn init(phys_mem_offset: VirtAddr) -> OffsetPageTable<'static> {
 /// # Safety
 ///
 /// raw pointers need usafe actions
-unsafe fn active_lvl_4_table(physical_mem_offset: VirtAddr) -> &'static mut PageTable {
+pub unsafe fn active_lvl_4_table(physical_mem_offset: VirtAddr) -> &'static mut PageTable {
     use x86_64::registers::control::Cr3;
 
     let (lvl_4_table_frame, _) = Cr3::read();
@@ -47,7 +107,57 @@ unsafe fn active_lvl_4_table(physical_mem_offset: VirtAddr) -> &'static mut Page
     &mut *page_table_ptr //unsafe
 }
 
+/// Keep page mapper and frame allocator built during boot as globals
+/// so memory can be mapped after init
+pub fn init_globals(mapper: OffsetPageTable<'static>, frame_allocator: BitmapFrameAllocator) {
+    PHYS_MEM_OFFSET
+        .try_init_once(|| mapper.phys_offset())
+        .expect("memory::init_globals should only be called once");
+    MAPPER
+        .try_init_once(|| Mutex::new(mapper))
+        .expect("memory::init_globals should only be called once");
+    FRAME_ALLOCATOR
+        .try_init_once(|| Mutex::new(frame_allocator))
+        .expect("memory::init_globals should only be called once");
+}
+
+/// Virtual address at which all of physical memory is mapped
+pub fn phys_mem_offset() -> VirtAddr {
+    *PHYS_MEM_OFFSET
+        .try_get()
+        .expect("physical memory offset not initialized")
+}
+
+/// Virtual address of physical memory, None before `init_globals`
+pub fn try_phys_mem_offset() -> Option<VirtAddr> {
+    PHYS_MEM_OFFSET.try_get().ok().copied()
+}
+
+/// Lock the global page mapper
+pub fn mapper() -> MutexGuard<'static, OffsetPageTable<'static>> {
+    MAPPER.try_get().expect("mapper not initialized").lock()
+}
+
+/// Lock the global frame allocator
+pub fn frame_allocator() -> MutexGuard<'static, BitmapFrameAllocator> {
+    FRAME_ALLOCATOR
+        .try_get()
+        .expect("frame allocator not initialized")
+        .lock()
+}
+
+/// Map `len` bytes of physical device memory at `phys` uncached
+///
+/// Virtual space is taken from the VMM, the mapping is removed when the
+/// returned region is dropped
+pub fn map_mmio(phys: PhysAddr, len: usize) -> Result<MmioRegion, VmmError> {
+    MmioRegion::map(phys, len)
+}
+
 /// Create allocation frames for heap memory
+///
+/// Only the initial `HEAP_SIZE` bytes are mapped, the heap is used before
+/// page faults can be resolved. Growth past it is reserved by `grow_heap`
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
@@ -64,8 +174,6 @@ pub fn init_heap(
     // allocate frames to each page in page range, size of page is 4KiB
     for page in page_range {
         // create frame for addresses available in frame allocator
-        // BootInfoFrameAllocator is initial entry point for Frame allocations
-
         let frame = frame_allocator
             .allocate_frame()
             .ok_or(MapToError::FrameAllocationFailed)?;
@@ -80,6 +188,202 @@ pub fn init_heap(
     unsafe {
         ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
     }
+    HEAP_TOP.store(HEAP_START + HEAP_SIZE, Ordering::Relaxed);
 
     Ok(())
 }
+
+/// Collect heap allocator and physical frame usage
+///
+/// The result implements `Display`, printing a `meminfo` style report
+pub fn stats() -> MemoryStats {
+    let heap = ALLOCATOR.lock().heap_stats();
+
+    let (total_frames, used_frames) = match FRAME_ALLOCATOR.try_get() {
+        Ok(frame_allocator) => {
+            let frame_allocator = frame_allocator.lock();
+            (
+                frame_allocator.total_frames(),
+                frame_allocator.used_frames(),
+            )
+        }
+        Err(_) => (0, 0),
+    };
+
+    let (buddy_total_frames, buddy_free_frames) = {
+        let buddy = BUDDY_ALLOCATOR.lock();
+        (buddy.total_frames(), buddy.free_frames())
+    };
+
+    MemoryStats {
+        heap,
+        total_frames,
+        used_frames,
+        buddy_total_frames,
+        buddy_free_frames,
+    }
+}
+
+/// Extend the heap into the growth region
+///
+/// Called by the heap allocator once it runs out of memory, `heap_top` is
+/// the current end of the heap. Grows by at least `min_size` bytes, rounded
+/// up to `HEAP_GROW_SIZE`, without going past `HEAP_MAX_SIZE`. A frame is
+/// taken for every new page and recorded in its page table entry, which is
+/// left not present. The page fault handler makes the page present on
+/// first touch, so growing doesn't zero memory which is never used
+///
+/// Returns number of bytes the heap grew by, may be less than requested
+/// if there are not enough free frames
+pub fn grow_heap(heap_top: usize, min_size: usize) -> usize {
+    let (mapper, frame_allocator) = match (MAPPER.try_get(), FRAME_ALLOCATOR.try_get()) {
+        (Ok(mapper), Ok(frame_allocator)) if HEAP_GROWTH.is_initialized() => {
+            (mapper, frame_allocator)
+        }
+        _ => return 0,
+    };
+
+    // never grow outside of the heap region
+    let heap_limit = HEAP_START + HEAP_MAX_SIZE;
+    if !(HEAP_START..heap_limit).contains(&heap_top) {
+        return 0;
+    }
+
+    // allocating while the mapper or frame allocator is locked fails the
+    // allocation instead of deadlocking
+    let (mut mapper, mut frame_allocator) = match (mapper.try_lock(), frame_allocator.try_lock()) {
+        (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+        _ => return 0,
+    };
+
+    let size =
+        align_up(min_size.max(HEAP_GROW_SIZE), Size4KiB::SIZE as usize).min(heap_limit - heap_top);
+    let start = Page::<Size4KiB>::containing_address(VirtAddr::new(heap_top as u64));
+    let end = Page::containing_address(VirtAddr::new((heap_top + size) as u64));
+
+    let mut grown = 0;
+    for page in Page::range(start, end) {
+        let frame = match frame_allocator.allocate_frame() {
+            Some(frame) => frame,
+            None => break,
+        };
+
+        // the frame is only reserved, page tables above it are present
+        let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe {
+            mapper.map_to_with_table_flags(
+                page,
+                frame,
+                PageTableFlags::WRITABLE,
+                table_flags,
+                &mut *frame_allocator,
+            )
+        } {
+            // entries which are not present are never cached
+            Ok(flush) => flush.ignore(),
+            Err(_) => {
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                break;
+            }
+        }
+        grown += Size4KiB::SIZE as usize;
+    }
+
+    if grown > 0 {
+        HEAP_TOP.store(heap_top + grown, Ordering::Relaxed);
+        serial_println!("Heap grown by {} bytes", grown);
+    }
+
+    grown
+}
+
+/// End of the memory handed to the heap allocator
+pub fn heap_top() -> usize {
+    HEAP_TOP.load(Ordering::Relaxed)
+}
+
+/// Reserve contiguous physical pool for the buddy allocator
+///
+/// The pool is taken from the frame allocator aligned to the largest
+/// buddy block. If memory is too fragmented the pool is halved until
+/// it fits, down to a single block
+pub fn init_buddy(frame_allocator: &mut BitmapFrameAllocator, phys_mem_offset: VirtAddr) {
+    let block_frames = 1 << buddy::MAX_ORDER;
+    let mut frame_count = buddy::POOL_SIZE / Size4KiB::SIZE as usize;
+
+    let start = loop {
+        if let Some(start) = frame_allocator.allocate_contiguous(frame_count, block_frames) {
+            break Some(start);
+        }
+        if frame_count <= block_frames {
+            break None;
+        }
+        frame_count /= 2;
+    };
+
+    let mut buddy_allocator = BUDDY_ALLOCATOR.lock();
+    unsafe { buddy_allocator.init(phys_mem_offset) };
+
+    match start {
+        Some(start) => unsafe { buddy_allocator.add_region(start, frame_count) },
+        None => serial_println!("WARNING: no contiguous memory for buddy allocator pool"),
+    }
+}
+
+/// Reserve the virtual memory in use after boot with the VMM
+///
+/// Reserves the heap region, the physical memory mapping and the
+/// framebuffer, everything else mapped by the bootloader is reserved as
+/// `RegionKind::Boot`. Must be called before `init_globals`, heap growth
+/// is not possible while the boot mapper is walked
+pub fn init_vmm(
+    mapper: &mut OffsetPageTable<'static>,
+    memory_regions: &MemoryRegions,
+    framebuffer: Option<&FrameBuffer>,
+) {
+    let mut vmm = VMM.lock();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    // initial heap is mapped by `init_heap`, the rest as it grows
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    vmm.reserve(heap_start, HEAP_SIZE as u64, RegionKind::Heap, flags)
+        .expect("heap region already in use");
+    let growth = vmm
+        .reserve_lazy(
+            heap_start + HEAP_SIZE,
+            (HEAP_MAX_SIZE - HEAP_SIZE) as u64,
+            RegionKind::Heap,
+            PageTableFlags::WRITABLE,
+        )
+        .expect("heap region already in use");
+    HEAP_GROWTH
+        .try_init_once(|| growth)
+        .expect("memory::init_vmm should only be called once");
+
+    let phys_mem_size = memory_regions.iter().map(|r| r.end).max().unwrap_or(0);
+    vmm.reserve_unclaimed(
+        mapper.phys_offset(),
+        phys_mem_size,
+        RegionKind::PhysicalMemory,
+        flags,
+    )
+    .expect("failed to reserve physical memory mapping");
+
+    if let Some(framebuffer) = framebuffer {
+        let start = VirtAddr::from_ptr(framebuffer.buffer().as_ptr());
+        vmm.reserve_unclaimed(
+            start,
+            framebuffer.info().byte_len as u64,
+            RegionKind::Framebuffer,
+            flags,
+        )
+        .expect("failed to reserve framebuffer");
+    }
+
+    let phys_mem_offset = mapper.phys_offset();
+    let walker = unsafe { PageTableWalker::new(mapper.level_4_table(), phys_mem_offset) };
+    for range in walker.ranges() {
+        vmm.reserve_unclaimed(range.start, range.size, RegionKind::Boot, range.flags)
+            .expect("failed to reserve boot mapping");
+    }
+}
@@ -0,0 +1,236 @@
+//! Inspection of the live four level page tables
+//!
+//! `PageTableWalker` visits every present leaf entry in virtual address
+//! order, `ranges` coalesces them into contiguous runs. `dump_page_tables`
+//! prints the runs of the active tables over serial
+
+use core::fmt;
+
+use x86_64::{
+    structures::paging::{PageTable, PageTableFlags},
+    PhysAddr, VirtAddr,
+};
+
+use crate::serial_println;
+
+/// Entries in one page table
+const ENTRY_COUNT: usize = 512;
+
+/// Page table level of the walk, 0 is the level 4 table
+const LEVELS: usize = 4;
+
+/// Contiguous run of mapped virtual memory
+///
+/// Backed by contiguous physical memory, every page with the same flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRange {
+    pub start: VirtAddr,
+    pub phys: PhysAddr,
+    /// Size in bytes
+    pub size: u64,
+    /// Flags of the leaf entries, flags of the upper levels are not merged
+    pub flags: PageTableFlags,
+}
+
+impl MappedRange {
+    /// First virtual address after the range
+    pub fn end(&self) -> VirtAddr {
+        VirtAddr::new_truncate(self.start.as_u64().wrapping_add(self.size))
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr.as_u64() - self.start.as_u64() < self.size
+    }
+
+    /// Physical address backing `addr`, if it is part of the range
+    pub fn translate(&self, addr: VirtAddr) -> Option<PhysAddr> {
+        if !self.contains(addr) {
+            return None;
+        }
+        Some(self.phys + (addr.as_u64() - self.start.as_u64()))
+    }
+
+    /// Extend range with `next` if it directly follows it, in both virtual
+    /// and physical memory, with the same flags
+    ///
+    /// Returns false if the ranges can't be merged
+    fn merge(&mut self, next: &MappedRange) -> bool {
+        let follows = self.end() == next.start && self.phys + self.size == next.phys;
+        if !follows || self.flags != next.flags {
+            return false;
+        }
+        self.size += next.size;
+        true
+    }
+}
+
+/// Prints one row of the `dump_page_tables` table
+impl fmt::Display for MappedRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (size, unit) = human_size(self.size);
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#014x} {:>6} {:<3} {:?}",
+            self.start.as_u64(),
+            self.end().as_u64().wrapping_sub(1),
+            self.phys.as_u64(),
+            size,
+            unit,
+            self.flags
+        )
+    }
+}
+
+/// Byte count in the largest binary unit it is a multiple of
+fn human_size(bytes: u64) -> (u64, &'static str) {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes;
+    let mut unit = 0;
+    while unit < UNITS.len() - 1 && size >= 1024 && size % 1024 == 0 {
+        size /= 1024;
+        unit += 1;
+    }
+    (size, UNITS[unit])
+}
+
+/// Iterator over the present leaf entries of a four level page table
+///
+/// Yields one `MappedRange` per 4KiB page, 2MiB or 1GiB huge page in
+/// ascending virtual address order. Walks the tables without allocating,
+/// so it can be used from the page fault handler
+pub struct PageTableWalker<'a> {
+    tables: [&'a PageTable; LEVELS],
+    indices: [usize; LEVELS],
+    level: usize,
+    phys_mem_offset: VirtAddr,
+}
+
+impl<'a> PageTableWalker<'a> {
+    /// Walk the tables below `lvl_4_table`
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that all physical memory is mapped at
+    /// `phys_mem_offset` and that the tables are not modified during the
+    /// walk
+    pub unsafe fn new(lvl_4_table: &'a PageTable, phys_mem_offset: VirtAddr) -> Self {
+        Self {
+            tables: [lvl_4_table; LEVELS],
+            indices: [0; LEVELS],
+            level: 0,
+            phys_mem_offset,
+        }
+    }
+
+    /// Coalesce the walked pages into contiguous ranges
+    pub fn ranges(self) -> MappedRanges<'a> {
+        MappedRanges {
+            walker: self,
+            pending: None,
+        }
+    }
+
+    /// Virtual address of the current entry
+    fn current_addr(&self) -> VirtAddr {
+        let addr = (0..=self.level).fold(0, |addr, level| {
+            addr | (self.indices[level] as u64) << level_shift(level)
+        });
+        VirtAddr::new_truncate(addr)
+    }
+}
+
+impl Iterator for PageTableWalker<'_> {
+    type Item = MappedRange;
+
+    fn next(&mut self) -> Option<MappedRange> {
+        loop {
+            let level = self.level;
+
+            // end of table, continue with the next entry of the parent
+            if self.indices[level] == ENTRY_COUNT {
+                if level == 0 {
+                    return None;
+                }
+                self.level -= 1;
+                self.indices[self.level] += 1;
+                continue;
+            }
+
+            let entry = &self.tables[level][self.indices[level]];
+            let flags = entry.flags();
+
+            if !flags.contains(PageTableFlags::PRESENT) {
+                self.indices[level] += 1;
+                continue;
+            }
+
+            // huge pages are leaves on level 3 and 2 tables
+            let is_leaf =
+                level == LEVELS - 1 || (level > 0 && flags.contains(PageTableFlags::HUGE_PAGE));
+
+            if is_leaf {
+                let range = MappedRange {
+                    start: self.current_addr(),
+                    phys: entry.addr(),
+                    size: 1 << level_shift(level),
+                    flags,
+                };
+                self.indices[level] += 1;
+                return Some(range);
+            }
+
+            let table_ptr: *const PageTable =
+                (self.phys_mem_offset + entry.addr().as_u64()).as_ptr();
+            self.level += 1;
+            self.tables[self.level] = unsafe { &*table_ptr };
+            self.indices[self.level] = 0;
+        }
+    }
+}
+
+/// Contiguous ranges of a `PageTableWalker`, built with `PageTableWalker::ranges`
+pub struct MappedRanges<'a> {
+    walker: PageTableWalker<'a>,
+    pending: Option<MappedRange>,
+}
+
+impl Iterator for MappedRanges<'_> {
+    type Item = MappedRange;
+
+    fn next(&mut self) -> Option<MappedRange> {
+        let mut current = self.pending.take().or_else(|| self.walker.next())?;
+
+        for next in self.walker.by_ref() {
+            if !current.merge(&next) {
+                self.pending = Some(next);
+                break;
+            }
+        }
+
+        Some(current)
+    }
+}
+
+/// Bits of a virtual address below the index into a table of given level
+const fn level_shift(level: usize) -> u64 {
+    39 - 9 * level as u64
+}
+
+/// Print the mapped ranges of the active page tables over serial
+pub fn dump_page_tables() {
+    let mut mapper = super::mapper();
+    let phys_mem_offset = mapper.phys_offset();
+    // mapper lock is held during the walk, tables can't change under it
+    let walker = unsafe { PageTableWalker::new(mapper.level_4_table(), phys_mem_offset) };
+
+    serial_println!(
+        "{:<37} -> {:<14} {:>10} Flags",
+        "Virtual range",
+        "Physical",
+        "Size"
+    );
+    for range in walker.ranges() {
+        serial_println!("{}", range);
+    }
+}
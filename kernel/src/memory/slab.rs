@@ -0,0 +1,488 @@
+//! Slab allocator, carving whole pages into objects of one size class
+//!
+//! Every slab is a single 4KiB page taken from the frame allocator and
+//! accessed through the physical memory offset mapping, so slabs never
+//! depend on the heap. The slab header lives at the start of the page,
+//! which lets a freed object find its slab by masking the address.
+
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::{
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size4KiB},
+    PhysAddr,
+};
+
+use super::allocator::Locked;
+#[cfg(feature = "heap-debug")]
+use super::debug::{self, HeapError};
+use super::linked_list::LinkedListAllocator;
+use super::stats::{AllocCounters, HeapStatistics, HeapStats};
+use crate::{memory, serial_println};
+
+const SLAB_SIZE: usize = Size4KiB::SIZE as usize;
+
+/// Empty slabs kept by each cache before pages are given back
+const MAX_EMPTY_SLABS: usize = 1;
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// Slab header, written to the start of every slab page
+struct Slab {
+    prev: *mut Slab,
+    next: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+/// Doubly linked list of slabs, so a slab can move between lists in O(1)
+struct SlabList {
+    head: *mut Slab,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.head;
+        if !self.head.is_null() {
+            (*self.head).prev = slab;
+        }
+        self.head = slab;
+        self.len += 1;
+    }
+
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.head = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+        self.len -= 1;
+    }
+
+    fn pop(&mut self) -> Option<*mut Slab> {
+        let slab = NonNull::new(self.head)?.as_ptr();
+        unsafe { self.remove(slab) };
+        Some(slab)
+    }
+}
+
+/// Snapshot of the state of a slab cache
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub objects_in_use: usize,
+    pub partial_slabs: usize,
+    pub full_slabs: usize,
+    pub empty_slabs: usize,
+}
+
+/// Cache of equally sized objects, backed by slabs
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    first_object: usize,
+    objects_per_slab: usize,
+    partial: SlabList,
+    full: SlabList,
+    empty: SlabList,
+    objects_in_use: usize,
+}
+
+// slab pointers are only touched while the cache is locked
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    /// Create an empty cache for objects with given size and alignment
+    ///
+    /// Alignment must be a power of two, objects must fit in a page
+    /// next to the slab header
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        let align = if align > mem::align_of::<FreeObject>() {
+            align
+        } else {
+            mem::align_of::<FreeObject>()
+        };
+        let size = if size > mem::size_of::<FreeObject>() {
+            size
+        } else {
+            mem::size_of::<FreeObject>()
+        };
+
+        let object_size = (size + align - 1) & !(align - 1);
+        let first_object = (mem::size_of::<Slab>() + align - 1) & !(align - 1);
+        assert!(
+            first_object + object_size <= SLAB_SIZE,
+            "object too large for slab"
+        );
+
+        Self {
+            name,
+            object_size,
+            first_object,
+            objects_per_slab: (SLAB_SIZE - first_object) / object_size,
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+            objects_in_use: 0,
+        }
+    }
+
+    /// Allocate one object
+    ///
+    /// Returns null pointer if no page could be allocated for a new slab,
+    /// or the free list of the slab is corrupted
+    pub fn alloc(&mut self) -> *mut u8 {
+        let slab = match NonNull::new(self.partial.head) {
+            Some(slab) => slab.as_ptr(),
+            None => match self.empty.pop().or_else(|| self.new_slab()) {
+                Some(slab) => {
+                    unsafe { self.partial.push(slab) };
+                    slab
+                }
+                None => return ptr::null_mut(),
+            },
+        };
+
+        unsafe {
+            let object = (*slab).free;
+            if !self.check_free(slab, object) {
+                return ptr::null_mut();
+            }
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+
+            if (*slab).in_use == self.objects_per_slab {
+                self.partial.remove(slab);
+                self.full.push(slab);
+            }
+
+            self.objects_in_use += 1;
+            object as *mut u8
+        }
+    }
+
+    /// Return object to its slab
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure `ptr` was allocated from this cache and is
+    /// no longer used
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8) {
+        let slab = (ptr as usize & !(SLAB_SIZE - 1)) as *mut Slab;
+        let was_full = (*slab).in_use == self.objects_per_slab;
+
+        let object = ptr as *mut FreeObject;
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+        self.objects_in_use -= 1;
+
+        if was_full {
+            self.full.remove(slab);
+            self.partial.push(slab);
+        }
+
+        if (*slab).in_use == 0 {
+            self.partial.remove(slab);
+            if self.empty.len < MAX_EMPTY_SLABS {
+                self.empty.push(slab);
+            } else {
+                free_page(slab as *mut u8);
+            }
+        }
+    }
+
+    /// Give all empty slabs back to the page allocator
+    pub fn shrink(&mut self) {
+        while let Some(slab) = self.empty.pop() {
+            unsafe { free_page(slab as *mut u8) };
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        SlabStats {
+            name: self.name,
+            object_size: self.object_size,
+            objects_per_slab: self.objects_per_slab,
+            objects_in_use: self.objects_in_use,
+            partial_slabs: self.partial.len,
+            full_slabs: self.full.len,
+            empty_slabs: self.empty.len,
+        }
+    }
+
+    /// Check that a free object and its successor lie in the slab on an
+    /// object boundary
+    ///
+    /// Reports corrupted free lists, only checks with `heap-debug` enabled
+    #[cfg(feature = "heap-debug")]
+    unsafe fn check_free(&self, slab: *mut Slab, object: *mut FreeObject) -> bool {
+        // successor is only read once the object is known to be in the slab
+        if self.valid_object(slab, object as usize) {
+            let next = (*object).next as usize;
+            if next == 0 || self.valid_object(slab, next) {
+                return true;
+            }
+        }
+
+        let layout = Layout::from_size_align(self.object_size, mem::align_of::<FreeObject>());
+        debug::report(
+            HeapError::CorruptedFreeList,
+            object as usize,
+            layout.unwrap(),
+        );
+        false
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    unsafe fn check_free(&self, _slab: *mut Slab, _object: *mut FreeObject) -> bool {
+        true
+    }
+
+    #[cfg(feature = "heap-debug")]
+    fn valid_object(&self, slab: *mut Slab, addr: usize) -> bool {
+        let objects = slab as usize + self.first_object;
+        let end = objects + self.objects_per_slab * self.object_size;
+        (objects..end).contains(&addr) && (addr - objects) % self.object_size == 0
+    }
+
+    /// Take a fresh page and thread the free list through its objects
+    fn new_slab(&mut self) -> Option<*mut Slab> {
+        let page = alloc_page()?;
+        let slab = page as *mut Slab;
+
+        unsafe {
+            let mut free = ptr::null_mut();
+            for i in (0..self.objects_per_slab).rev() {
+                let object = page.add(self.first_object + i * self.object_size) as *mut FreeObject;
+                object.write(FreeObject { next: free });
+                free = object;
+            }
+
+            slab.write(Slab {
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                free,
+                in_use: 0,
+            });
+        }
+
+        Some(slab)
+    }
+}
+
+/// Take a frame from the global frame allocator
+///
+/// Returns pointer to the page through the physical memory offset mapping
+fn alloc_page() -> Option<*mut u8> {
+    let frame_allocator = memory::FRAME_ALLOCATOR.try_get().ok()?;
+    let frame = frame_allocator.lock().allocate_frame()?;
+    Some((memory::phys_mem_offset() + frame.start_address().as_u64()).as_mut_ptr())
+}
+
+/// Give page taken with `alloc_page` back to the frame allocator
+unsafe fn free_page(page: *mut u8) {
+    let phys = PhysAddr::new(page as u64 - memory::phys_mem_offset().as_u64());
+    memory::frame_allocator().deallocate_frame(PhysFrame::containing_address(phys));
+}
+
+/// Named cache of objects of type `T`
+///
+/// Subsystems keep one as static, e.g.
+/// `static TASK_CACHE: ObjectCache<Task> = ObjectCache::new("task");`
+pub struct ObjectCache<T> {
+    cache: Locked<SlabCache>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> ObjectCache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            cache: Locked::new(SlabCache::new(
+                name,
+                mem::size_of::<T>(),
+                mem::align_of::<T>(),
+            )),
+            _type: PhantomData,
+        }
+    }
+
+    /// Move `value` into an object of the cache
+    ///
+    /// Returns `None` if the cache is out of memory
+    pub fn alloc(&'static self, value: T) -> Option<SlabBox<T>> {
+        let ptr = NonNull::new(self.cache.lock().alloc() as *mut T)?;
+        unsafe { ptr.as_ptr().write(value) };
+        Some(SlabBox { ptr, cache: self })
+    }
+
+    /// Give all empty slabs back to the page allocator
+    pub fn shrink(&self) {
+        self.cache.lock().shrink()
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        self.cache.lock().stats()
+    }
+}
+
+/// Owned pointer to an object in an `ObjectCache`, freed on drop
+pub struct SlabBox<T: 'static> {
+    ptr: NonNull<T>,
+    cache: &'static ObjectCache<T>,
+}
+
+unsafe impl<T: Send> Send for SlabBox<T> {}
+unsafe impl<T: Sync> Sync for SlabBox<T> {}
+
+impl<T> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache
+                .cache
+                .lock()
+                .dealloc(self.ptr.as_ptr() as *mut u8);
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<SlabAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+
+        let ptr = match SlabAllocator::cache_index(&layout) {
+            Some(index) => match allocator.caches[index].alloc() {
+                // no page for a new slab, try the fallback heap instead
+                ptr if ptr.is_null() => allocator.fallback_allocator.allocate(layout),
+                ptr => ptr,
+            },
+            None => allocator.fallback_allocator.allocate(layout),
+        };
+
+        if !ptr.is_null() {
+            allocator.counters.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.lock();
+        allocator.counters.record_free(layout.size());
+
+        let fallback = allocator.fallback_allocator.bottom()..allocator.fallback_allocator.top();
+        match SlabAllocator::cache_index(&layout) {
+            Some(index) if !fallback.contains(&(ptr as usize)) => {
+                allocator.caches[index].dealloc(ptr)
+            }
+            _ => allocator.fallback_allocator.deallocate(ptr, layout),
+        }
+    }
+}
+
+/// Object sizes served by slab caches in bytes
+///
+/// Larger allocations go to the fallback heap. Sizes must be power of
+/// two because they are also used as object alignment
+pub const SLAB_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024];
+
+/// Global allocator serving small allocations from slab caches
+pub struct SlabAllocator {
+    caches: [SlabCache; SLAB_SIZES.len()],
+    fallback_allocator: LinkedListAllocator,
+    counters: AllocCounters,
+}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        Self {
+            caches: [
+                SlabCache::new("kmalloc-8", 8, 8),
+                SlabCache::new("kmalloc-16", 16, 16),
+                SlabCache::new("kmalloc-32", 32, 32),
+                SlabCache::new("kmalloc-64", 64, 64),
+                SlabCache::new("kmalloc-128", 128, 128),
+                SlabCache::new("kmalloc-256", 256, 256),
+                SlabCache::new("kmalloc-512", 512, 512),
+                SlabCache::new("kmalloc-1024", 1024, 1024),
+            ],
+            fallback_allocator: LinkedListAllocator::new(),
+            counters: AllocCounters::new(),
+        }
+    }
+
+    /// Initialize fallback heap with given heap bounds
+    ///
+    /// # Safety
+    ///
+    /// Marked as unsafe because the caller must ensure the given
+    /// memory range is unused. Method should only be called once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        serial_println!("Initializing Slab heap allocator");
+        self.fallback_allocator.init(heap_start, heap_size)
+    }
+
+    /// State of every size class cache
+    pub fn cache_stats(&self) -> [SlabStats; SLAB_SIZES.len()] {
+        let mut stats = [self.caches[0].stats(); SLAB_SIZES.len()];
+        for (stats, cache) in stats.iter_mut().zip(self.caches.iter()) {
+            *stats = cache.stats();
+        }
+        stats
+    }
+
+    /// Chose slab cache for a layout
+    ///
+    /// Returns the index of the size class in `SLAB_SIZES`
+    fn cache_index(layout: &Layout) -> Option<usize> {
+        let required_size = layout.size().max(layout.align());
+        SLAB_SIZES.iter().position(|&s| s >= required_size)
+    }
+}
+
+impl HeapStatistics for SlabAllocator {
+    fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats::new(
+            "SlabAllocator",
+            self.fallback_allocator.size(),
+            self.counters,
+        );
+        self.fallback_allocator.walk_free_regions(&mut stats);
+        stats
+    }
+}
@@ -0,0 +1,78 @@
+//! Kernel stacks with an unmapped guard page below, an overflowing stack
+//! page faults on the guard page instead of corrupting other memory
+
+use core::mem;
+
+use x86_64::{
+    structures::paging::{Page, PageTableFlags, Size4KiB},
+    VirtAddr,
+};
+
+use super::vmm::{Region, RegionKind, VmmError, VMM};
+
+/// Stack size used for interrupt stacks and kernel threads
+pub const DEFAULT_STACK_PAGES: u64 = 5;
+
+/// Unmapped pages below every stack
+pub const GUARD_PAGES: u64 = 1;
+
+/// Mapped kernel stack, unmapped and freed on drop
+#[derive(Debug)]
+pub struct KernelStack {
+    region: Region,
+}
+
+impl KernelStack {
+    /// Map stack of `pages` pages above an unmapped guard page
+    pub fn allocate(pages: u64) -> Result<Self, VmmError> {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let region = VMM.lock().allocate_guarded(
+            pages * Page::<Size4KiB>::SIZE,
+            GUARD_PAGES,
+            RegionKind::KernelStack,
+            flags,
+        )?;
+        Ok(Self { region })
+    }
+
+    /// Initial stack pointer, stacks grow from higher to lower addresses
+    pub fn top(&self) -> VirtAddr {
+        self.region.end()
+    }
+
+    /// Lowest mapped address of the stack
+    pub fn bottom(&self) -> VirtAddr {
+        self.region.mapped_start()
+    }
+
+    /// First guard page below the stack
+    pub fn guard_page(&self) -> Page<Size4KiB> {
+        Page::containing_address(self.region.start)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.top() - self.bottom()
+    }
+
+    /// Keep the stack mapped forever, for stacks used until shutdown
+    ///
+    /// Returns the top of the stack
+    pub fn leak(self) -> VirtAddr {
+        let top = self.top();
+        mem::forget(self);
+        top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        VMM.lock()
+            .release(self.region.start)
+            .expect("failed to unmap kernel stack");
+    }
+}
+
+/// Allocate stack of `DEFAULT_STACK_PAGES` pages
+pub fn allocate_stack() -> Result<KernelStack, VmmError> {
+    KernelStack::allocate(DEFAULT_STACK_PAGES)
+}
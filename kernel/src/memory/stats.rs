@@ -0,0 +1,148 @@
+//! Memory usage statistics, used to track down leaks and fragmentation
+
+use core::fmt;
+
+use super::fixed::BLOCK_SIZES;
+
+/// Counters kept by every heap allocator, updated on each alloc and free
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocCounters {
+    pub bytes_in_use: usize,
+    pub peak_bytes_in_use: usize,
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+impl AllocCounters {
+    pub const fn new() -> Self {
+        Self {
+            bytes_in_use: 0,
+            peak_bytes_in_use: 0,
+            allocations: 0,
+            frees: 0,
+        }
+    }
+
+    pub fn record_alloc(&mut self, size: usize) {
+        self.allocations += 1;
+        self.bytes_in_use += size;
+        self.peak_bytes_in_use = self.peak_bytes_in_use.max(self.bytes_in_use);
+    }
+
+    pub fn record_free(&mut self, size: usize) {
+        self.frees += 1;
+        self.bytes_in_use = self.bytes_in_use.saturating_sub(size);
+    }
+
+    /// Allocations which have not been freed yet
+    pub fn live_allocations(&self) -> usize {
+        self.allocations.saturating_sub(self.frees)
+    }
+}
+
+/// Heap allocator which can report its state
+pub trait HeapStatistics {
+    fn heap_stats(&self) -> HeapStats;
+}
+
+/// Snapshot of the state of a heap allocator
+#[derive(Debug, Clone)]
+pub struct HeapStats {
+    pub allocator: &'static str,
+    /// Bytes of heap memory handed to the allocator
+    pub heap_size: usize,
+    pub counters: AllocCounters,
+    /// Bytes not yet handed out from the underlying heap region
+    pub free_bytes: usize,
+    /// Number of free regions, for allocators which can walk them
+    pub free_regions: Option<usize>,
+    /// Largest free region, for allocators which can walk them
+    pub largest_free_region: Option<usize>,
+    /// Free list length of each `BLOCK_SIZES` class, fixed size allocator only
+    pub free_blocks: Option<[usize; BLOCK_SIZES.len()]>,
+}
+
+impl HeapStats {
+    pub fn new(allocator: &'static str, heap_size: usize, counters: AllocCounters) -> Self {
+        Self {
+            allocator,
+            heap_size,
+            counters,
+            free_bytes: 0,
+            free_regions: None,
+            largest_free_region: None,
+            free_blocks: None,
+        }
+    }
+
+    /// Percentage of free memory which is not part of the largest
+    /// free region, 0 means all free memory is contiguous
+    pub fn fragmentation(&self) -> Option<usize> {
+        let largest = self.largest_free_region?;
+        if self.free_bytes == 0 {
+            return Some(0);
+        }
+        Some(100 - largest * 100 / self.free_bytes)
+    }
+}
+
+/// Snapshot of heap and physical memory usage, returned by `memory::stats`
+#[derive(Debug, Clone)]
+pub struct MemoryStats {
+    pub heap: HeapStats,
+    pub total_frames: usize,
+    pub used_frames: usize,
+    pub buddy_total_frames: usize,
+    pub buddy_free_frames: usize,
+}
+
+/// Prints `meminfo` style report
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let heap = &self.heap;
+        let counters = &heap.counters;
+
+        writeln!(f, "Heap allocator:      {}", heap.allocator)?;
+        writeln!(f, "Heap size:           {} KiB", heap.heap_size / 1024)?;
+        writeln!(f, "Heap in use:         {} bytes", counters.bytes_in_use)?;
+        writeln!(
+            f,
+            "Heap peak:           {} bytes",
+            counters.peak_bytes_in_use
+        )?;
+        writeln!(f, "Heap free:           {} bytes", heap.free_bytes)?;
+        writeln!(
+            f,
+            "Allocations:         {} ({} freed, {} live)",
+            counters.allocations,
+            counters.frees,
+            counters.live_allocations()
+        )?;
+
+        if let (Some(regions), Some(fragmentation)) = (heap.free_regions, heap.fragmentation()) {
+            writeln!(
+                f,
+                "Free regions:        {regions} ({fragmentation}% fragmented)"
+            )?;
+        }
+
+        if let Some(free_blocks) = heap.free_blocks {
+            write!(f, "Free blocks:        ")?;
+            for (size, count) in BLOCK_SIZES.iter().zip(free_blocks) {
+                write!(f, " {size}:{count}")?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(
+            f,
+            "Physical frames:     {} used / {} total",
+            self.used_frames, self.total_frames
+        )?;
+        write!(
+            f,
+            "Buddy pool frames:   {} free / {} total",
+            self.buddy_free_frames, self.buddy_total_frames
+        )
+    }
+}
@@ -0,0 +1,486 @@
+//! Virtual memory manager, tracks which parts of the kernel virtual
+//! address space are in use
+//!
+//! Every user of virtual memory reserves a region here instead of picking
+//! its own address. Regions never overlap, reserving memory that is already
+//! in use is an error instead of a silent remap
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use x86_64::{
+    align_up,
+    structures::paging::{
+        mapper::{MapToError, UnmapError},
+        FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PageTableFlags, PhysFrame,
+        Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use super::allocator::Locked;
+
+pub static VMM: Locked<VirtualMemoryManager> = Locked::new(VirtualMemoryManager::new());
+
+/// Start of the window `VirtualMemoryManager::allocate` hands out ranges from
+pub const VMM_START: u64 = 0xffff_d000_0000_0000;
+
+/// End of the allocation window
+pub const VMM_END: u64 = 0xffff_e000_0000_0000;
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+/// What a region of virtual memory is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Mapped by the bootloader, kernel image, boot stack and boot info
+    Boot,
+    Heap,
+    /// Mapping of all physical memory at the physical memory offset
+    PhysicalMemory,
+    Framebuffer,
+    KernelStack,
+    Mmio,
+    General,
+}
+
+/// How the pages of a region are backed by physical memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    /// Address space only, mapped by the owner of the region
+    Reserved,
+    /// Frames taken from the frame allocator, freed on release
+    Allocated,
+    /// Fixed physical range starting at the address, never freed
+    Physical(PhysAddr),
+    /// Zeroed frames mapped by the page fault handler on first access,
+    /// freed on release
+    Lazy,
+}
+
+impl Backing {
+    /// Whether frames mapped into the region belong to it
+    pub fn owns_frames(&self) -> bool {
+        matches!(self, Backing::Allocated | Backing::Lazy)
+    }
+}
+
+/// Reserved range of virtual memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    /// Size in bytes, multiple of the page size
+    pub size: u64,
+    pub kind: RegionKind,
+    pub backing: Backing,
+    pub flags: PageTableFlags,
+    /// Pages at the bottom of the region which are never mapped, so stacks
+    /// overflowing into them fault
+    pub guard_pages: u64,
+}
+
+impl Region {
+    /// First virtual address after the region
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end()
+    }
+
+    pub fn overlaps(&self, start: VirtAddr, size: u64) -> bool {
+        self.start < start + size && start < self.end()
+    }
+
+    /// First address above the guard pages
+    pub fn mapped_start(&self) -> VirtAddr {
+        self.start + self.guard_pages * PAGE_SIZE
+    }
+
+    pub fn is_guard(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.mapped_start()
+    }
+
+    /// Pages of the region backed by memory, guard pages excluded
+    pub fn pages(&self) -> impl Iterator<Item = Page<Size4KiB>> {
+        Page::range(
+            Page::containing_address(self.mapped_start()),
+            Page::containing_address(self.end()),
+        )
+    }
+}
+
+/// Errors returned by the virtual memory manager
+#[derive(Debug)]
+pub enum VmmError {
+    /// Requested range overlaps an already reserved region
+    Overlap(Region),
+    /// No free range of the requested size left in the allocation window
+    OutOfVirtualMemory,
+    /// Size is zero or address isn't page aligned
+    InvalidRange,
+    /// No region starts at the given address
+    NotReserved,
+    Map(MapToError<Size4KiB>),
+    Unmap(UnmapError),
+}
+
+impl fmt::Display for VmmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmmError::Overlap(region) => write!(
+                f,
+                "range overlaps {:?} region at {:#x}",
+                region.kind,
+                region.start.as_u64()
+            ),
+            VmmError::OutOfVirtualMemory => write!(f, "out of virtual memory"),
+            VmmError::InvalidRange => write!(f, "invalid range"),
+            VmmError::NotReserved => write!(f, "region not reserved"),
+            VmmError::Map(err) => write!(f, "map failed: {:?}", err),
+            VmmError::Unmap(err) => write!(f, "unmap failed: {:?}", err),
+        }
+    }
+}
+
+/// Sorted set of reserved virtual regions
+///
+/// Regions are kept in a `BTreeMap` keyed by start address. The map lives
+/// on the heap, so the heap region is reserved up front and heap growth
+/// never goes through the manager. The growth range is lazily backed, its
+/// faults are resolved without locking the manager
+///
+/// Lock order is `VMM`, heap, `MAPPER`/`FRAME_ALLOCATOR`. Pages are mapped
+/// after the region is inserted, without allocating on the heap
+pub struct VirtualMemoryManager {
+    regions: BTreeMap<u64, Region>,
+}
+
+impl VirtualMemoryManager {
+    pub const fn new() -> Self {
+        Self {
+            regions: BTreeMap::new(),
+        }
+    }
+
+    /// Reserve region at fixed address whose pages are mapped on first
+    /// access
+    pub fn reserve_lazy(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        self.insert(Region {
+            start,
+            size: align_up(size, PAGE_SIZE),
+            kind,
+            backing: Backing::Lazy,
+            flags,
+            guard_pages: 0,
+        })
+    }
+
+    /// Reserve region at fixed address without mapping it
+    pub fn reserve(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        self.insert(Region {
+            start,
+            size: align_up(size, PAGE_SIZE),
+            kind,
+            backing: Backing::Reserved,
+            flags,
+            guard_pages: 0,
+        })
+    }
+
+    /// Reserve the parts of the range not covered by other regions yet
+    ///
+    /// Used at boot to claim memory mapped by the bootloader, which may
+    /// partly overlap regions reserved before
+    pub fn reserve_unclaimed(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<(), VmmError> {
+        let mut start = start.align_down(PAGE_SIZE);
+        let end = VirtAddr::new(align_up(start.as_u64() + size, PAGE_SIZE));
+
+        while start < end {
+            let gap_end = match self.overlapping(start, end - start) {
+                // overlapping region may start before the gap
+                Some(region) if region.start <= start => {
+                    start = region.end();
+                    continue;
+                }
+                Some(region) => region.start,
+                None => end,
+            };
+            self.reserve(start, gap_end - start, kind, flags)?;
+            start = gap_end;
+        }
+
+        Ok(())
+    }
+
+    /// Reserve free range from the allocation window without mapping it
+    pub fn allocate(
+        &mut self,
+        size: u64,
+        align: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        let size = align_up(size, PAGE_SIZE);
+        let start = self.find_free(size, align)?;
+        self.reserve(start, size, kind, flags)
+    }
+
+    /// Reserve free range and back it with newly allocated frames
+    pub fn allocate_mapped(
+        &mut self,
+        size: u64,
+        align: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        let size = align_up(size, PAGE_SIZE);
+        let start = self.find_free(size, align)?;
+        self.map_region(Region {
+            start,
+            size,
+            kind,
+            backing: Backing::Allocated,
+            flags,
+            guard_pages: 0,
+        })
+    }
+
+    /// Reserve free range of `size` bytes above `guard_pages` unmapped pages,
+    /// backed by newly allocated frames
+    pub fn allocate_guarded(
+        &mut self,
+        size: u64,
+        guard_pages: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        let size = align_up(size, PAGE_SIZE) + guard_pages * PAGE_SIZE;
+        let start = self.find_free(size, PAGE_SIZE)?;
+        self.map_region(Region {
+            start,
+            size,
+            kind,
+            backing: Backing::Allocated,
+            flags,
+            guard_pages,
+        })
+    }
+
+    /// Reserve free range whose pages are mapped on first access
+    ///
+    /// Nothing is mapped until the range is touched, the page fault handler
+    /// backs each faulting page with a zeroed frame
+    pub fn allocate_lazy(
+        &mut self,
+        size: u64,
+        align: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        let size = align_up(size, PAGE_SIZE);
+        let start = self.find_free(size, align)?;
+        self.reserve_lazy(start, size, kind, flags)
+    }
+
+    /// Reserve free range and map it to the physical range starting at `phys`
+    pub fn allocate_physical(
+        &mut self,
+        phys: PhysAddr,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmmError> {
+        if !phys.is_aligned(PAGE_SIZE) {
+            return Err(VmmError::InvalidRange);
+        }
+        let size = align_up(size, PAGE_SIZE);
+        let start = self.find_free(size, PAGE_SIZE)?;
+        self.map_region(Region {
+            start,
+            size,
+            kind,
+            backing: Backing::Physical(phys),
+            flags,
+            guard_pages: 0,
+        })
+    }
+
+    /// Remove reservation of the region starting at `start`
+    ///
+    /// Pages of `Allocated` and `Physical` regions are unmapped, allocated
+    /// frames are returned to the frame allocator
+    pub fn release(&mut self, start: VirtAddr) -> Result<Region, VmmError> {
+        let region = *self
+            .regions
+            .get(&start.as_u64())
+            .ok_or(VmmError::NotReserved)?;
+
+        if region.backing != Backing::Reserved {
+            unmap_pages(&region, region.pages().count() as u64)?;
+        }
+
+        self.regions.remove(&start.as_u64());
+        Ok(region)
+    }
+
+    /// Region containing `addr`
+    pub fn find(&self, addr: VirtAddr) -> Option<&Region> {
+        self.regions
+            .range(..=addr.as_u64())
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| region.contains(addr))
+    }
+
+    /// Reserved regions sorted by start address
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+
+    /// First region overlapping the range, the one with the lowest start
+    fn overlapping(&self, start: VirtAddr, size: u64) -> Option<&Region> {
+        let end = start.as_u64().checked_add(size)?;
+
+        // only the last region starting at or before `start` can contain it
+        let containing = self
+            .regions
+            .range(..=start.as_u64())
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| region.overlaps(start, size));
+
+        containing.or_else(|| {
+            self.regions
+                .range(start.as_u64()..end)
+                .next()
+                .map(|(_, region)| region)
+        })
+    }
+
+    fn insert(&mut self, region: Region) -> Result<Region, VmmError> {
+        if region.size == 0 || !region.start.is_aligned(PAGE_SIZE) {
+            return Err(VmmError::InvalidRange);
+        }
+
+        if let Some(existing) = self.overlapping(region.start, region.size) {
+            return Err(VmmError::Overlap(*existing));
+        }
+
+        self.regions.insert(region.start.as_u64(), region);
+        Ok(region)
+    }
+
+    /// Insert region and map its pages, the region is removed again if
+    /// mapping fails
+    fn map_region(&mut self, region: Region) -> Result<Region, VmmError> {
+        // insert before taking the mapper lock, inserting allocates
+        let region = self.insert(region)?;
+
+        if let Err((err, mapped)) = map_pages(&region) {
+            let _ = unmap_pages(&region, mapped);
+            self.regions.remove(&region.start.as_u64());
+            return Err(err);
+        }
+
+        Ok(region)
+    }
+
+    /// Lowest free range in the allocation window
+    fn find_free(&self, size: u64, align: u64) -> Result<VirtAddr, VmmError> {
+        if size == 0 {
+            return Err(VmmError::InvalidRange);
+        }
+        let align = align.max(PAGE_SIZE);
+
+        let mut candidate = align_up(VMM_START, align);
+        for region in self.regions.range(..VMM_END).map(|(_, region)| region) {
+            if region.end().as_u64() <= candidate {
+                continue;
+            }
+            if candidate + size <= region.start.as_u64() {
+                break;
+            }
+            candidate = align_up(region.end().as_u64(), align);
+        }
+
+        if candidate + size > VMM_END {
+            return Err(VmmError::OutOfVirtualMemory);
+        }
+        Ok(VirtAddr::new(candidate))
+    }
+}
+
+/// Map pages of region to their backing
+///
+/// Returns error and the number of pages mapped before it
+fn map_pages(region: &Region) -> Result<(), (VmmError, u64)> {
+    let mut mapper = super::mapper();
+    let mut frame_allocator = super::frame_allocator();
+    let flags = region.flags | PageTableFlags::PRESENT;
+
+    for (i, page) in region.pages().enumerate() {
+        let i = i as u64;
+        let frame = match region.backing {
+            Backing::Allocated => frame_allocator
+                .allocate_frame()
+                .ok_or((VmmError::Map(MapToError::FrameAllocationFailed), i))?,
+            Backing::Physical(phys) => PhysFrame::containing_address(phys + i * PAGE_SIZE),
+            Backing::Reserved | Backing::Lazy => return Ok(()),
+        };
+
+        let result = unsafe { mapper.map_to(page, frame, flags, &mut *frame_allocator) };
+        match result {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                if region.backing.owns_frames() {
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                }
+                return Err((VmmError::Map(err), i));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Unmap first `count` pages of region, freeing owned frames
+fn unmap_pages(region: &Region, count: u64) -> Result<(), VmmError> {
+    let mut mapper = super::mapper();
+    let mut frame_allocator = super::frame_allocator();
+
+    for page in region.pages().take(count as usize) {
+        let (frame, flush) = match mapper.unmap(page) {
+            Ok(unmapped) => unmapped,
+            // lazy pages which were never touched
+            Err(UnmapError::PageNotMapped) if region.backing == Backing::Lazy => continue,
+            Err(err) => return Err(VmmError::Unmap(err)),
+        };
+        flush.flush();
+
+        if region.backing.owns_frames() {
+            unsafe { frame_allocator.deallocate_frame(frame) };
+        }
+    }
+
+    Ok(())
+}
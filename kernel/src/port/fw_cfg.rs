@@ -0,0 +1,93 @@
+//! QEMU firmware configuration device
+//!
+//! QEMU passes files to the guest through two I/O ports, a selector port
+//! picking an item and a data port reading its bytes in order. Files given
+//! with `-fw_cfg name=<name>,string=<value>` are listed in the file
+//! directory item. Reads return `0xff` without the device, which fails the
+//! signature check
+
+use x86_64::instructions::port::Port;
+
+/// Port selecting the item to read
+const SELECTOR: u16 = 0x510;
+/// Port reading the selected item byte by byte
+const DATA: u16 = 0x511;
+
+/// Item holding `SIGNATURE`
+const SIGNATURE_ITEM: u16 = 0x0000;
+/// Item listing the files
+const FILE_DIR_ITEM: u16 = 0x0019;
+
+const SIGNATURE: [u8; 4] = *b"QEMU";
+/// Length of the nul padded file name in a directory entry
+const FILE_NAME_LEN: usize = 56;
+
+/// Whether the machine has the device
+pub fn is_present() -> bool {
+    let mut signature = [0; 4];
+    unsafe {
+        select(SIGNATURE_ITEM);
+        read(&mut signature);
+    }
+    signature == SIGNATURE
+}
+
+/// Read the file `name` into `buf`
+///
+/// Returns the number of bytes read, the file is cut to the length of
+/// `buf`. `None` if there is no device or no such file
+pub fn read_file(name: &str, buf: &mut [u8]) -> Option<usize> {
+    if !is_present() {
+        return None;
+    }
+
+    unsafe {
+        select(FILE_DIR_ITEM);
+        let count = read_u32();
+        for _ in 0..count {
+            let size = read_u32() as usize;
+            let item = read_u16();
+            // reserved
+            read_u16();
+            let mut file_name = [0; FILE_NAME_LEN];
+            read(&mut file_name);
+
+            let len = file_name
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(FILE_NAME_LEN);
+            if &file_name[..len] == name.as_bytes() {
+                let len = size.min(buf.len());
+                select(item);
+                read(&mut buf[..len]);
+                return Some(len);
+            }
+        }
+    }
+    None
+}
+
+unsafe fn select(item: u16) {
+    Port::<u16>::new(SELECTOR).write(item);
+}
+
+/// Read the next bytes of the selected item
+unsafe fn read(buf: &mut [u8]) {
+    let mut data = Port::<u8>::new(DATA);
+    for byte in buf {
+        *byte = data.read();
+    }
+}
+
+/// Directory fields are big endian
+unsafe fn read_u32() -> u32 {
+    let mut bytes = [0; 4];
+    read(&mut bytes);
+    u32::from_be_bytes(bytes)
+}
+
+unsafe fn read_u16() -> u16 {
+    let mut bytes = [0; 2];
+    read(&mut bytes);
+    u16::from_be_bytes(bytes)
+}
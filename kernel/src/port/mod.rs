@@ -1,3 +1,4 @@
+pub mod fw_cfg;
 pub mod num;
 pub mod serial;
 use self::num::PortNumber;
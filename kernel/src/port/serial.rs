@@ -1,17 +1,43 @@
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
+use x86_64::instructions::port::PortReadOnly;
 
 use super::{num::PortNumber, Port};
 
+/// Base I/O port of the first serial port
+const COM1: u16 = 0x3f8;
+/// Line status register, offset from the base port
+const LINE_STATUS: u16 = 5;
+/// Line status bit set while a received byte waits in the data register
+const DATA_READY: u8 = 1;
+
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3f8) };
+        let mut serial_port = unsafe { SerialPort::new(COM1) };
         serial_port.init();
         Mutex::new(serial_port)
     };
 }
 
+/// Take the received byte if one is pending, without waiting for one
+///
+/// `SerialPort::receive` spins until a byte arrives, which never happens
+/// for an interrupt raised without one
+pub fn try_receive() -> Option<u8> {
+    // held so no write goes to the port in between
+    let _serial = SERIAL1.lock();
+    let mut line_status = PortReadOnly::<u8>::new(COM1 + LINE_STATUS);
+    let mut data = PortReadOnly::<u8>::new(COM1);
+
+    unsafe {
+        if line_status.read() & DATA_READY == 0 {
+            return None;
+        }
+        Some(data.read())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum QemuExitCode {
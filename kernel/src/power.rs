@@ -0,0 +1,300 @@
+//! Powering off and rebooting the machine
+//!
+//! Shutdown enters the ACPI S5 sleep state by writing the sleep type found
+//! in the DSDT to the PM1 control registers of the FADT. Reboot writes the
+//! FADT reset register, then pulses the reset line of the 8042 keyboard
+//! controller and finally triple faults if the machine is still running
+
+use core::fmt;
+
+use x86_64::{
+    instructions::{interrupts, port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+    VirtAddr,
+};
+
+use crate::acpi::{self, Fadt, Sdt, Signature};
+use crate::{hlt_loop, println, serial_println};
+
+/// PM1 control bit enabling sleep, written with the sleep type
+const SLP_EN: u64 = 1 << 13;
+const SLP_TYP_SHIFT: u64 = 10;
+const SLP_TYP_MASK: u64 = 0b111 << SLP_TYP_SHIFT;
+/// PM1 control bit set by the firmware once ACPI mode is on
+const SCI_EN: u64 = 1;
+
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+/// Status bit set while the controller hasn't taken the last input
+const KBC_INPUT_FULL: u8 = 1 << 1;
+/// Command pulsing the CPU reset line
+const KBC_PULSE_RESET: u8 = 0xfe;
+
+/// Port writes used as delay, each takes about a microsecond
+const IO_DELAY_PORT: u16 = 0x80;
+/// Delays waited for the firmware or hardware to react, about 100ms
+const POWER_TIMEOUT: usize = 100_000;
+
+// AML encoding of the `\_S5` package in the DSDT
+const AML_NAME_OP: u8 = 0x08;
+const AML_ROOT_CHAR: u8 = b'\\';
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0a;
+const AML_ZERO_OP: u8 = 0x00;
+const AML_ONE_OP: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// Machine has no ACPI tables or no FADT
+    NoAcpi,
+    /// FADT has no PM1a control block
+    NoPm1Control,
+    /// DSDT is invalid or has no `\_S5` object
+    NoS5,
+    /// Firmware didn't switch to ACPI mode
+    AcpiModeTimeout,
+    /// FADT has no usable reset register
+    NoResetRegister,
+    /// Machine has no 8042 keyboard controller
+    No8042,
+    /// Machine is still running after the request
+    Timeout,
+}
+
+impl fmt::Display for PowerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerError::NoAcpi => write!(f, "no ACPI FADT"),
+            PowerError::NoPm1Control => write!(f, "no PM1 control register"),
+            PowerError::NoS5 => write!(f, "no S5 sleep state in DSDT"),
+            PowerError::AcpiModeTimeout => write!(f, "firmware did not enable ACPI mode"),
+            PowerError::NoResetRegister => write!(f, "no ACPI reset register"),
+            PowerError::No8042 => write!(f, "no 8042 keyboard controller"),
+            PowerError::Timeout => write!(f, "machine did not react"),
+        }
+    }
+}
+
+fn fadt() -> Option<&'static Fadt> {
+    acpi::try_tables()?.fadt.as_ref()
+}
+
+fn io_delay(count: usize) {
+    let mut port = Port::<u8>::new(IO_DELAY_PORT);
+    for _ in 0..count {
+        unsafe { port.write(0) };
+    }
+}
+
+/// Power off the machine
+///
+/// Halts with interrupts disabled if ACPI shutdown fails
+pub fn shutdown() -> ! {
+    interrupts::disable();
+
+    let err = try_shutdown().unwrap_err();
+    serial_println!("shutdown failed: {}", err);
+    println!("shutdown failed: {}", err);
+    hlt_loop();
+}
+
+/// Enter the S5 sleep state through ACPI, only returns on failure
+pub fn try_shutdown() -> Result<(), PowerError> {
+    let fadt = fadt().ok_or(PowerError::NoAcpi)?;
+    let pm1a = fadt.pm1a_control.ok_or(PowerError::NoPm1Control)?;
+    let (slp_typ_a, slp_typ_b) = s5_sleep_type(fadt)?;
+
+    enable_acpi_mode(fadt)?;
+
+    unsafe {
+        // SLP_TYP of both blocks must be set before either SLP_EN is written
+        if let Some(pm1b) = fadt.pm1b_control {
+            write_sleep_type(&pm1b, slp_typ_b, false);
+        }
+        write_sleep_type(&pm1a, slp_typ_a, false);
+        if let Some(pm1b) = fadt.pm1b_control {
+            write_sleep_type(&pm1b, slp_typ_b, true);
+        }
+        write_sleep_type(&pm1a, slp_typ_a, true);
+    }
+
+    io_delay(POWER_TIMEOUT);
+    Err(PowerError::Timeout)
+}
+
+unsafe fn write_sleep_type(register: &acpi::GenericAddress, slp_typ: u8, enable: bool) {
+    let value = register.read().unwrap_or(0) & !(SLP_TYP_MASK | SLP_EN);
+    let mut value = value | ((slp_typ as u64) << SLP_TYP_SHIFT);
+    if enable {
+        value |= SLP_EN;
+    }
+    register.write(value);
+}
+
+/// Sleep types of PM1a and PM1b for S5 from the DSDT
+fn s5_sleep_type(fadt: &Fadt) -> Result<(u8, u8), PowerError> {
+    let dsdt = unsafe { Sdt::at(fadt.dsdt) }.map_err(|_| PowerError::NoS5)?;
+    dsdt.expect(Signature::DSDT).map_err(|_| PowerError::NoS5)?;
+    parse_s5(dsdt.body()).ok_or(PowerError::NoS5)
+}
+
+/// Find the `\_S5` package in AML code and return its first two elements,
+/// the sleep types written to PM1a and PM1b control
+///
+/// Only the usual encoding `Name(_S5, Package() { a, b, ... })` is
+/// understood, no AML is interpreted
+pub fn parse_s5(aml: &[u8]) -> Option<(u8, u8)> {
+    let name = aml.windows(4).enumerate().find_map(|(i, window)| {
+        let named = match i {
+            0 => false,
+            1 => aml[0] == AML_NAME_OP,
+            _ => {
+                aml[i - 1] == AML_NAME_OP
+                    || (aml[i - 1] == AML_ROOT_CHAR && aml[i - 2] == AML_NAME_OP)
+            }
+        };
+        (window == b"_S5_" && named).then_some(i)
+    })?;
+
+    let mut offset = name + 4;
+    if *aml.get(offset)? != AML_PACKAGE_OP {
+        return None;
+    }
+    offset += 1;
+
+    // package length, bits 6-7 of the lead byte count the following bytes
+    let extra_len_bytes = (aml.get(offset)? >> 6) as usize;
+    offset += 1 + extra_len_bytes;
+    // number of elements
+    offset += 1;
+
+    let slp_typ_a = aml_integer(aml, &mut offset)?;
+    let slp_typ_b = aml_integer(aml, &mut offset)?;
+    Some((slp_typ_a, slp_typ_b))
+}
+
+/// Small integer constant at `offset`, advances past it
+fn aml_integer(aml: &[u8], offset: &mut usize) -> Option<u8> {
+    let value = match *aml.get(*offset)? {
+        AML_BYTE_PREFIX => {
+            *offset += 1;
+            *aml.get(*offset)?
+        }
+        AML_ZERO_OP => 0,
+        AML_ONE_OP => 1,
+        _ => return None,
+    };
+    *offset += 1;
+    Some(value)
+}
+
+/// Check ACPI mode is on, the firmware hands power management to the OS
+pub fn acpi_mode_enabled() -> bool {
+    fadt()
+        .and_then(|fadt| fadt.pm1a_control)
+        .and_then(|pm1a| unsafe { pm1a.read() })
+        .map_or(false, |value| value & SCI_EN != 0)
+}
+
+/// Ask the firmware to switch to ACPI mode if it didn't yet
+fn enable_acpi_mode(fadt: &Fadt) -> Result<(), PowerError> {
+    // hardware reduced machines and those without SMI command are always
+    // in ACPI mode
+    if acpi_mode_enabled()
+        || fadt.hardware_reduced()
+        || fadt.smi_command == 0
+        || fadt.acpi_enable == 0
+    {
+        return Ok(());
+    }
+
+    unsafe { Port::<u8>::new(fadt.smi_command as u16).write(fadt.acpi_enable) };
+    for _ in 0..POWER_TIMEOUT {
+        if acpi_mode_enabled() {
+            return Ok(());
+        }
+        io_delay(1);
+    }
+    Err(PowerError::AcpiModeTimeout)
+}
+
+/// Ways to reset the machine, tried by `reboot` in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMethod {
+    /// FADT reset register
+    Acpi,
+    /// Reset line of the 8042 keyboard controller
+    Keyboard,
+    /// Triple fault, always resets
+    TripleFault,
+}
+
+/// Reset the machine
+///
+/// Tries the FADT reset register, the 8042 reset line and last a triple
+/// fault, which always resets
+pub fn reboot() -> ! {
+    interrupts::disable();
+
+    for method in [ResetMethod::Acpi, ResetMethod::Keyboard] {
+        let _ = try_reset(method);
+    }
+    triple_fault();
+}
+
+/// Reset the machine with `method` alone, only returns on failure
+///
+/// Lets the fallbacks of `reboot` be used on machines where an earlier
+/// method works
+pub fn try_reset(method: ResetMethod) -> Result<(), PowerError> {
+    let fadt = fadt();
+    match method {
+        ResetMethod::Acpi => {
+            let fadt = fadt
+                .filter(|fadt| fadt.reset_supported())
+                .ok_or(PowerError::NoResetRegister)?;
+            let reset = fadt.reset_register.unwrap();
+            if !unsafe { reset.write(fadt.reset_value as u64) } {
+                return Err(PowerError::NoResetRegister);
+            }
+        }
+        ResetMethod::Keyboard => {
+            if !fadt.map_or(true, Fadt::has_8042) {
+                return Err(PowerError::No8042);
+            }
+            pulse_reset_line();
+        }
+        ResetMethod::TripleFault => triple_fault(),
+    }
+
+    io_delay(POWER_TIMEOUT);
+    Err(PowerError::Timeout)
+}
+
+/// Reset through the 8042 keyboard controller
+fn pulse_reset_line() {
+    let mut status = Port::<u8>::new(KBC_STATUS);
+    let mut command = Port::<u8>::new(KBC_COMMAND);
+    unsafe {
+        for _ in 0..POWER_TIMEOUT {
+            if status.read() & KBC_INPUT_FULL == 0 {
+                break;
+            }
+            io_delay(1);
+        }
+        command.write(KBC_PULSE_RESET);
+    }
+}
+
+/// Load an empty IDT and raise an exception, the resulting double and
+/// triple fault reset the CPU
+fn triple_fault() -> ! {
+    let idt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    unsafe {
+        lidt(&idt);
+        core::arch::asm!("int3", options(noreturn));
+    }
+}
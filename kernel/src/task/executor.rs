@@ -1,24 +1,60 @@
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use core::task::{Context, Poll, Waker};
-use crossbeam_queue::ArrayQueue;
+use alloc::{collections::BTreeMap, string::String, sync::Arc, task::Wake, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::Ordering,
+    task::{Context, Poll, Waker},
+};
+use crossbeam_queue::SegQueue;
+use futures_util::task::noop_waker_ref;
+use spin::Mutex;
 
-use super::{Task, TaskId};
+use super::{
+    join::{join_pair, JoinState},
+    JoinHandle, Task, TaskHeader, TaskId, TaskInfo, TaskQueue, STATE_DONE, STATE_PENDING,
+    STATE_QUEUED, STATE_RUNNING,
+};
+
+/// Initial capacity of the task queue, it grows with the task count
+const TASK_QUEUE_CAPACITY: usize = 100;
+
+/// Headers of the live tasks, shared with the spawners for snapshots
+type TaskList = Arc<Mutex<BTreeMap<TaskId, Arc<TaskHeader>>>>;
 
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    task_queue: Arc<TaskQueue>,
     waker_cache: BTreeMap<TaskId, Waker>,
+    /// Tasks from spawners, added to `tasks` by the executor
+    spawn_queue: Arc<SegQueue<Task>>,
+    task_list: TaskList,
 }
 
 impl Executor {
     pub fn new() -> Self {
         Self {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
+            task_queue: Arc::new(TaskQueue::new(TASK_QUEUE_CAPACITY)),
             waker_cache: BTreeMap::new(),
+            spawn_queue: Arc::new(SegQueue::new()),
+            task_list: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Handle spawning tasks on this executor, also while it runs
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            spawn_queue: self.spawn_queue.clone(),
+            task_queue: self.task_queue.clone(),
+            task_list: self.task_list.clone(),
         }
     }
 
+    /// Live tasks, those spawned and not yet completed or aborted
+    pub fn tasks_snapshot(&self) -> Vec<TaskInfo> {
+        snapshot(&self.task_list)
+    }
+
     pub fn run(&mut self) -> ! {
         loop {
             self.run_ready_tasks();
@@ -26,11 +62,28 @@ impl Executor {
         }
     }
 
+    /// Run tasks until `future` completes and return its output
+    pub fn block_on<F>(&mut self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let mut handle = self.spawner().spawn(future);
+        let mut context = Context::from_waker(noop_waker_ref());
+        loop {
+            self.run_ready_tasks();
+            if let Poll::Ready(output) = Pin::new(&mut handle).poll(&mut context) {
+                return output.expect("block_on task has no handle to abort it");
+            }
+            self.sleep_if_idle();
+        }
+    }
+
     fn sleep_if_idle(&self) {
         use x86_64::instructions::interrupts::{self, enable_and_hlt};
 
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if self.task_queue.is_empty() && self.spawn_queue.is_empty() {
             enable_and_hlt();
         } else {
             interrupts::enable();
@@ -38,59 +91,161 @@ impl Executor {
     }
 
     pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
+        self.task_list.lock().insert(task.id(), task.header.clone());
+        self.add_task(task);
+    }
+
+    fn add_task(&mut self, task: Task) {
+        let task_id = task.id();
+        task.header.state.store(STATE_QUEUED, Ordering::Release);
         if self.tasks.insert(task_id, task).is_some() {
             panic!("task with same ID already in tasks");
         }
-        self.task_queue.push(task_id).expect("queue is full");
+        self.task_queue.reserve(self.tasks.len());
+        self.task_queue.push(task_id);
+    }
+
+    /// Move tasks from spawners into the executor
+    fn spawn_queued(&mut self) {
+        while let Ok(task) = self.spawn_queue.pop() {
+            self.add_task(task);
+        }
     }
 
     fn run_ready_tasks(&mut self) {
-        let Self {
-            tasks,
-            task_queue,
-            waker_cache,
-        } = self;
-
-        while let Ok(task_id) = task_queue.pop() {
-            let task = match tasks.get_mut(&task_id) {
+        self.spawn_queued();
+
+        while let Some(task_id) = self.task_queue.pop() {
+            let task = match self.tasks.get_mut(&task_id) {
                 Some(task) => task,
                 None => continue,
             };
-            let waker = waker_cache
-                .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
+            let header = task.header.clone();
+            // last id of a task completed while queued
+            if header.state.load(Ordering::Acquire) == STATE_DONE {
+                self.tasks.remove(&task_id);
+                continue;
+            }
 
+            // wakes from here on queue the task again
+            header.state.store(STATE_RUNNING, Ordering::Release);
+            if header.aborted.load(Ordering::Acquire) {
+                self.remove_task(task_id);
+                continue;
+            }
+
+            let task_queue = &self.task_queue;
+            let waker = self
+                .waker_cache
+                .entry(task_id)
+                .or_insert_with(|| TaskWaker::new(header.clone(), task_queue.clone()));
             let mut context = Context::from_waker(waker);
 
             match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    // task done -> remove it and its cached waker
-                    tasks.remove(&task_id);
-                    waker_cache.remove(&task_id);
+                // task done -> remove it and its cached waker
+                Poll::Ready(()) => self.remove_task(task_id),
+                // stays queued if it was woken while running
+                Poll::Pending => {
+                    let _ = header.state.compare_exchange(
+                        STATE_RUNNING,
+                        STATE_PENDING,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    );
                 }
-                Poll::Pending => {}
             }
         }
     }
+
+    /// Drop a completed or aborted task
+    ///
+    /// A task woken while it ran is still queued, it is kept until its id
+    /// is dequeued so the queue never holds more ids than there are tasks
+    fn remove_task(&mut self, task_id: TaskId) {
+        if let Some(task) = self.tasks.get(&task_id) {
+            let state = task.header.state.swap(STATE_DONE, Ordering::AcqRel);
+            if state != STATE_QUEUED {
+                // dropping the task drops its future
+                self.tasks.remove(&task_id);
+            }
+        }
+        self.waker_cache.remove(&task_id);
+        self.task_list.lock().remove(&task_id);
+    }
+}
+
+/// Cloneable handle spawning tasks on an executor
+///
+/// Tasks spawned while the executor runs start after the tasks ready at
+/// the time. Tasks are `Send`, so the spawner can be kept in a static and
+/// used outside of the executor's tasks. Spawning allocates, it must not
+/// be done from interrupt handlers
+#[derive(Clone)]
+pub struct Spawner {
+    spawn_queue: Arc<SegQueue<Task>>,
+    task_queue: Arc<TaskQueue>,
+    task_list: TaskList,
+}
+
+impl Spawner {
+    /// Spawn `future` as a task, the handle awaits its output
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (future, state) = join_pair(future);
+        self.spawn_with_handle(Task::new(future), state)
+    }
+
+    /// Spawn `future` as a task listed under `name`
+    pub fn spawn_named<F>(&self, name: impl Into<String>, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (future, state) = join_pair(future);
+        self.spawn_with_handle(Task::named(name, future), state)
+    }
+
+    fn spawn_with_handle<T>(&self, task: Task, state: Arc<JoinState<T>>) -> JoinHandle<T> {
+        let handle = JoinHandle::new(state, task.header.clone(), self.task_queue.clone());
+        self.spawn_task(task);
+        handle
+    }
+
+    pub fn spawn_task(&self, task: Task) {
+        // listed as queued until the executor takes it
+        task.header.state.store(STATE_QUEUED, Ordering::Release);
+        self.task_list.lock().insert(task.id(), task.header.clone());
+        self.spawn_queue.push(task);
+    }
+
+    /// Live tasks of the executor, also callable from its tasks
+    pub fn tasks_snapshot(&self) -> Vec<TaskInfo> {
+        snapshot(&self.task_list)
+    }
+}
+
+fn snapshot(task_list: &TaskList) -> Vec<TaskInfo> {
+    task_list
+        .lock()
+        .values()
+        .filter_map(|header| header.info())
+        .collect()
 }
 
 struct TaskWaker {
-    task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    header: Arc<TaskHeader>,
+    task_queue: Arc<TaskQueue>,
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(Self {
-            task_id,
-            task_queue,
-        }))
+    fn new(header: Arc<TaskHeader>, task_queue: Arc<TaskQueue>) -> Waker {
+        Waker::from(Arc::new(Self { header, task_queue }))
     }
     fn wake_task(&self) {
-        self.task_queue
-            .push(self.task_id)
-            .expect("task_queue is full");
+        self.header.schedule(&self.task_queue);
     }
 }
 
@@ -0,0 +1,142 @@
+//! Handles awaiting the output of spawned tasks
+
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+use super::{TaskFuture, TaskHeader, TaskId, TaskQueue};
+
+/// Error awaiting a task which didn't complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// Task was aborted through its handle
+    Cancelled,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+        }
+    }
+}
+
+/// Output of a task, shared by the task and its handle
+pub(crate) struct JoinState<T> {
+    output: Mutex<Option<T>>,
+    finished: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Handle of a spawned task, awaiting it gives the task's output
+///
+/// Dropping the handle detaches the task, it keeps running
+#[must_use = "dropping a JoinHandle detaches the task"]
+pub struct JoinHandle<T> {
+    state: Arc<JoinState<T>>,
+    header: Arc<TaskHeader>,
+    task_queue: Arc<TaskQueue>,
+}
+
+/// Wrap `future` into a task future storing its output for the handle
+pub(crate) fn join_pair<F>(future: F) -> (impl TaskFuture, Arc<JoinState<F::Output>>)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = Arc::new(JoinState {
+        output: Mutex::new(None),
+        finished: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+    });
+
+    let task_state = state.clone();
+    let task = async move {
+        let output = future.await;
+        *task_state.output.lock() = Some(output);
+        task_state.finished.store(true, Ordering::Release);
+        task_state.waker.wake();
+    };
+    (task, state)
+}
+
+impl<T> JoinHandle<T> {
+    pub(crate) fn new(
+        state: Arc<JoinState<T>>,
+        header: Arc<TaskHeader>,
+        task_queue: Arc<TaskQueue>,
+    ) -> Self {
+        Self {
+            state,
+            header,
+            task_queue,
+        }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.header.id
+    }
+
+    /// Check the task completed, its output may have been taken already
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        !self.is_finished() && self.header.aborted.load(Ordering::Acquire)
+    }
+
+    /// Stop the task, the executor drops its future instead of polling it
+    /// again
+    ///
+    /// Awaiting the handle then gives `JoinError::Cancelled`. Does nothing
+    /// if the task completed already
+    pub fn abort(&self) {
+        if self.is_finished() {
+            return;
+        }
+        self.header.aborted.store(true, Ordering::Release);
+        // the executor removes aborted tasks when it dequeues them
+        self.header.schedule(&self.task_queue);
+        self.state.waker.wake();
+    }
+
+    fn take_output(&self) -> T {
+        self.state
+            .output
+            .lock()
+            .take()
+            .expect("JoinHandle polled after completion")
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // fast path
+        if self.is_finished() {
+            return Poll::Ready(Ok(self.take_output()));
+        }
+        if self.is_aborted() {
+            return Poll::Ready(Err(JoinError::Cancelled));
+        }
+
+        self.state.waker.register(cx.waker());
+
+        if self.is_finished() {
+            self.state.waker.take();
+            Poll::Ready(Ok(self.take_output()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -1,18 +1,31 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::String, sync::Arc};
 use core::{
+    fmt,
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
     task::{Context, Poll},
+    time::Duration,
 };
 
+use crossbeam_queue::ArrayQueue;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::time::{self, Instant};
+
 pub mod executor;
+pub mod join;
 pub mod keyboard;
 
-pub trait TaskFuture = Future<Output = ()>;
+pub use executor::{Executor, Spawner};
+pub use join::{JoinError, JoinHandle};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+/// Future run as a task, `Send` so tasks can be queued from any context
+pub trait TaskFuture = Future<Output = ()> + Send;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
@@ -20,22 +33,222 @@ impl TaskId {
 
         Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
 }
 
-pub struct Task {
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a live task is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Woken, waiting in the queue to be polled
+    Queued,
+    /// Waiting to be woken
+    Pending,
+    /// Being polled
+    Running,
+}
+
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskState::Queued => write!(f, "queued"),
+            TaskState::Pending => write!(f, "pending"),
+            TaskState::Running => write!(f, "running"),
+        }
+    }
+}
+
+const STATE_QUEUED: u8 = 0;
+const STATE_PENDING: u8 = 1;
+const STATE_RUNNING: u8 = 2;
+/// Completed or aborted, wakes are ignored
+const STATE_DONE: u8 = 3;
+
+/// Task data shared with its waker, join handle and the task list
+#[derive(Debug)]
+pub(crate) struct TaskHeader {
     id: TaskId,
+    name: Option<String>,
+    spawned_at: Instant,
+    state: AtomicU8,
+    aborted: AtomicBool,
+    polls: AtomicU64,
+    cpu_nanos: AtomicU64,
+}
+
+impl TaskHeader {
+    fn new(name: Option<String>) -> Self {
+        Self {
+            id: TaskId::new(),
+            name,
+            spawned_at: time::now(),
+            state: AtomicU8::new(STATE_PENDING),
+            aborted: AtomicBool::new(false),
+            polls: AtomicU64::new(0),
+            cpu_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Queue the task to be polled unless it is queued or done already,
+    /// called from interrupt handlers too
+    fn schedule(&self, task_queue: &TaskQueue) {
+        let queued = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state != STATE_QUEUED && state != STATE_DONE).then_some(STATE_QUEUED)
+            })
+            .is_ok();
+        if queued {
+            task_queue.push(self.id);
+        }
+    }
+
+    fn state(&self) -> Option<TaskState> {
+        match self.state.load(Ordering::Acquire) {
+            STATE_QUEUED => Some(TaskState::Queued),
+            STATE_PENDING => Some(TaskState::Pending),
+            STATE_RUNNING => Some(TaskState::Running),
+            _ => None,
+        }
+    }
+
+    fn info(&self) -> Option<TaskInfo> {
+        Some(TaskInfo {
+            id: self.id,
+            name: self.name.clone(),
+            state: self.state()?,
+            spawned_at: self.spawned_at,
+            polls: self.polls.load(Ordering::Relaxed),
+            cpu_time: Duration::from_nanos(self.cpu_nanos.load(Ordering::Relaxed)),
+        })
+    }
+}
+
+/// Ids of the tasks ready to be polled, pushed to by wakers from interrupt
+/// handlers too
+///
+/// A task is queued at most once, so the executor keeps room for all of its
+/// tasks by growing the queue as tasks are added. The lock is only taken
+/// with interrupts disabled, an interrupt handler never finds it held
+pub(crate) struct TaskQueue {
+    ids: Mutex<ArrayQueue<TaskId>>,
+}
+
+impl TaskQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ids: Mutex::new(ArrayQueue::new(capacity)),
+        }
+    }
+
+    fn push(&self, id: TaskId) {
+        without_interrupts(|| {
+            self.ids
+                .lock()
+                .push(id)
+                .expect("task queue smaller than the task count");
+        })
+    }
+
+    fn pop(&self) -> Option<TaskId> {
+        without_interrupts(|| self.ids.lock().pop().ok())
+    }
+
+    fn is_empty(&self) -> bool {
+        without_interrupts(|| self.ids.lock().is_empty())
+    }
+
+    /// Make room for the ids of `tasks` tasks, doubling the capacity
+    fn reserve(&self, tasks: usize) {
+        without_interrupts(|| {
+            let mut ids = self.ids.lock();
+            if ids.capacity() >= tasks {
+                return;
+            }
+
+            let grown = ArrayQueue::new(tasks.max(ids.capacity() * 2));
+            while let Ok(id) = ids.pop() {
+                let _ = grown.push(id);
+            }
+            *ids = grown;
+        })
+    }
+}
+
+/// Live task as listed by `Executor::tasks_snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub state: TaskState,
+    pub spawned_at: Instant,
+    /// Times the task was polled
+    pub polls: u64,
+    /// Time spent polling the task
+    pub cpu_time: Duration,
+}
+
+impl fmt::Display for TaskInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>4} {:<16} {:<8} {:>8} {:>10}us  spawned at {}",
+            self.id,
+            self.name.as_deref().unwrap_or("-"),
+            self.state,
+            self.polls,
+            self.cpu_time.as_micros(),
+            self.spawned_at
+        )
+    }
+}
+
+pub struct Task {
+    header: Arc<TaskHeader>,
     future: Pin<Box<dyn TaskFuture>>,
 }
 
 impl Task {
     pub fn new(future: impl TaskFuture + 'static) -> Self {
+        Self::with_header(None, future)
+    }
+
+    /// Task listed under `name`
+    pub fn named(name: impl Into<String>, future: impl TaskFuture + 'static) -> Self {
+        Self::with_header(Some(name.into()), future)
+    }
+
+    fn with_header(name: Option<String>, future: impl TaskFuture + 'static) -> Self {
         Self {
-            id: TaskId::new(),
+            header: Arc::new(TaskHeader::new(name)),
             future: Box::pin(future),
         }
     }
 
+    pub fn id(&self) -> TaskId {
+        self.header.id
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.header.name.as_deref()
+    }
+
     fn poll(&mut self, ctx: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(ctx)
+        let start = time::now();
+        let poll = self.future.as_mut().poll(ctx);
+
+        let header = &self.header;
+        header.polls.fetch_add(1, Ordering::Relaxed);
+        let elapsed = start.elapsed().as_nanos() as u64;
+        header.cpu_nanos.fetch_add(elapsed, Ordering::Relaxed);
+        poll
     }
 }
@@ -1,5 +1,6 @@
 use core::panic::PanicInfo;
 
+use crate::backtrace::Backtrace;
 use crate::{hlt_loop, println, serial_print, serial_println};
 
 /// Testable trait used for all test cases
@@ -35,6 +36,7 @@ pub fn panic_handler(info: &PanicInfo) -> ! {
 
     serial_println!("[failed]\n");
     serial_println!("Error: {}", info);
+    serial_println!("{}", Backtrace::capture());
     println!("{info}");
     exit_qemu(QemuExitCode::Failed);
 
@@ -0,0 +1,137 @@
+//! Calendar dates of the proleptic Gregorian calendar in UTC
+
+use core::{fmt, time::Duration};
+
+const SECS_PER_DAY: u64 = 86_400;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Date and time of day, broken down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    /// 1970-01-01 00:00:00, time zero of Unix timestamps
+    pub const UNIX_EPOCH: Self = Self {
+        year: 1970,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        nanosecond: 0,
+    };
+
+    /// Date `since_epoch` after the Unix epoch
+    pub fn from_unix(since_epoch: Duration) -> Self {
+        let secs = since_epoch.as_secs();
+        let days = secs / SECS_PER_DAY;
+        let secs_of_day = secs % SECS_PER_DAY;
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            nanosecond: since_epoch.subsec_nanos(),
+        }
+    }
+
+    /// Time since the Unix epoch, zero for earlier dates
+    pub fn to_unix(&self) -> Duration {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64;
+        Duration::new(days * SECS_PER_DAY + secs, self.nanosecond)
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn timestamp(&self) -> u64 {
+        self.to_unix().as_secs()
+    }
+
+    /// Day of the week, 0 for Sunday to 6 for Saturday
+    pub fn weekday(&self) -> u8 {
+        // the epoch was a Thursday
+        ((days_from_civil(self.year, self.month, self.day) + 4) % 7) as u8
+    }
+
+    /// Check the fields name a real date and time
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && (self.nanosecond as u64) < NANOS_PER_SEC
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// conversions between days since the epoch and dates count years from
+// March, putting the leap day at the end of the year
+
+/// Days since 1970-01-01, saturating at zero
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // 719468 days from 0000-03-01 to 1970-01-01
+    (era * 146_097 + day_of_era - 719_468).max(0) as u64
+}
+
+/// Year, month and day `days` after 1970-01-01
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u8;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year as u16, month, day)
+}
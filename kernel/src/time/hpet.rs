@@ -0,0 +1,68 @@
+//! High Precision Event Timer, a free running counter found through ACPI
+//!
+//! Only the main counter is used, as clock. Its comparators are left
+//! disabled
+
+use x86_64::PhysAddr;
+
+use crate::acpi;
+use crate::memory::{self, mmio::MmioRegion, vmm::VmmError};
+
+const CAPABILITIES: usize = 0x000;
+const CONFIG: usize = 0x010;
+const MAIN_COUNTER: usize = 0x0f0;
+/// Registers of the timer block, with up to 32 comparators
+const REGISTERS_LEN: usize = 0x400;
+
+const CAP_COUNTER_64BIT: u64 = 1 << 13;
+const CONFIG_ENABLE: u64 = 1;
+const CONFIG_LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+/// Longest counter period allowed by the specification, 100ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+#[derive(Debug)]
+pub struct Hpet {
+    mmio: MmioRegion,
+    period_fs: u64,
+}
+
+impl Hpet {
+    /// Map the HPET described by `table` and start its main counter
+    ///
+    /// None if the counter is only 32 bits wide, it wraps within minutes,
+    /// or reports an invalid period
+    pub fn init(table: &acpi::Hpet) -> Result<Option<Self>, VmmError> {
+        let mmio = memory::map_mmio(table.base, REGISTERS_LEN)?;
+
+        let capabilities = mmio.read64(CAPABILITIES);
+        let period_fs = capabilities >> 32;
+        if capabilities & CAP_COUNTER_64BIT == 0 || period_fs == 0 || period_fs > MAX_PERIOD_FS {
+            return Ok(None);
+        }
+
+        // comparators keep their routing, the PIT stays the timer interrupt
+        let config = mmio.read64(CONFIG) & !CONFIG_LEGACY_REPLACEMENT;
+        mmio.write64(CONFIG, config | CONFIG_ENABLE);
+
+        Ok(Some(Self { mmio, period_fs }))
+    }
+
+    pub fn phys(&self) -> PhysAddr {
+        self.mmio.phys()
+    }
+
+    /// Femtoseconds per counter increment
+    pub fn period_fs(&self) -> u64 {
+        self.period_fs
+    }
+
+    /// Counter increments per second
+    pub fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period_fs
+    }
+
+    pub fn read(&self) -> u64 {
+        self.mmio.read64(MAIN_COUNTER)
+    }
+}
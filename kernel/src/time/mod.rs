@@ -0,0 +1,299 @@
+//! Timekeeping driven by the timer interrupt and a high resolution counter
+//!
+//! The PIT raises the timer interrupt `TICK_HZ` times a second. Each
+//! interrupt is a tick, counted since boot and adding the tick period to the
+//! tick clock. `init_clock` then picks the most precise clocksource, an
+//! invariant TSC or the HPET, which continues the tick clock from the time
+//! it was picked. From then on the tick clock is no longer advanced, ticks
+//! only count and drive the timers. Without either, `now` stays at tick
+//! resolution.
+//!
+//! The RTC periodic interrupt can replace the PIT as tick source. The RTC
+//! date read at boot, advanced by the monotonic clock, gives the wall clock.
+//!
+//! Tasks wait for time to pass with the futures of `timer`, woken by ticks
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use conquer_once::spin::OnceCell;
+
+use crate::{acpi, interrupts::controller};
+
+pub mod date;
+pub mod hpet;
+pub mod instant;
+pub mod pit;
+pub mod rtc;
+pub mod timer;
+pub mod tsc;
+
+pub use date::DateTime;
+pub use instant::Instant;
+pub use timer::{interval, sleep, sleep_until, timeout, Elapsed, Interval, Sleep, Timeout};
+
+/// Timer interrupts per second set up by `init`
+pub const TICK_HZ: u32 = 1000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const FEMTOS_PER_NANO: u64 = 1_000_000;
+/// ISA IRQ of the RTC
+const RTC_IRQ: u8 = 8;
+/// Fractional bits of the counter to nanoseconds scale
+const SCALE_SHIFT: u32 = 32;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// Monotonic clock until `init_clock` picks a clocksource
+static TICK_CLOCK_NANOS: AtomicU64 = AtomicU64::new(0);
+/// Nanoseconds between two ticks, the PIT reset rate until `init`
+static TICK_NANOS: AtomicU64 = AtomicU64::new(tick_nanos(pit::DEFAULT_DIVISOR));
+static RTC_TICKS: AtomicBool = AtomicBool::new(false);
+
+static CLOCK: OnceCell<Clock> = OnceCell::uninit();
+/// Unix time read from the RTC at boot and when it was read
+static WALL_CLOCK_BASE: OnceCell<(Duration, Instant)> = OnceCell::uninit();
+
+const fn tick_nanos(divisor: u32) -> u64 {
+    (divisor as u64 * NANOS_PER_SEC + pit::BASE_FREQUENCY as u64 / 2) / pit::BASE_FREQUENCY as u64
+}
+
+/// Program the PIT to tick at `TICK_HZ`
+pub fn init() {
+    set_tick_frequency(TICK_HZ);
+}
+
+/// Program the PIT to tick at about `frequency` Hz
+///
+/// Returns the frequency set, the nearest the PIT divides its clock to
+pub fn set_tick_frequency(frequency: u32) -> u32 {
+    set_tick_source(TickSource::Pit, frequency)
+}
+
+/// Interrupt counted as tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickSource {
+    /// PIT channel 0, the timer interrupt
+    Pit,
+    /// RTC periodic interrupt, a power of two from 2 to 8192 Hz
+    Rtc,
+}
+
+/// Tick from `source` at about `frequency` Hz and stop the other source
+///
+/// Returns the frequency set
+pub fn set_tick_source(source: TickSource, frequency: u32) -> u32 {
+    match source {
+        TickSource::Pit => {
+            RTC_TICKS.store(false, Ordering::Relaxed);
+            rtc::disable_periodic();
+
+            let divisor = pit::divisor(frequency);
+            TICK_NANOS.store(tick_nanos(divisor), Ordering::Relaxed);
+            pit::set_divisor(divisor);
+            pit::frequency(divisor)
+        }
+        TickSource::Rtc => {
+            pit::stop();
+
+            let rate = rtc::rate(frequency);
+            let frequency = rtc::periodic_frequency(rate);
+            let nanos = (NANOS_PER_SEC + frequency as u64 / 2) / frequency as u64;
+            TICK_NANOS.store(nanos, Ordering::Relaxed);
+            RTC_TICKS.store(true, Ordering::Relaxed);
+            controller::unmask_irq(RTC_IRQ);
+            rtc::enable_periodic(rate);
+            frequency
+        }
+    }
+}
+
+pub fn tick_source() -> TickSource {
+    if RTC_TICKS.load(Ordering::Relaxed) {
+        TickSource::Rtc
+    } else {
+        TickSource::Pit
+    }
+}
+
+/// Ticks per second
+pub fn tick_frequency() -> u32 {
+    let nanos = TICK_NANOS.load(Ordering::Relaxed);
+    ((NANOS_PER_SEC + nanos / 2) / nanos) as u32
+}
+
+/// Time between two ticks
+pub fn tick_period() -> Duration {
+    Duration::from_nanos(TICK_NANOS.load(Ordering::Relaxed))
+}
+
+/// Count a tick, called by the interrupt handler of the tick source
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    // the clocksource continued the tick clock, it isn't read any more
+    if !CLOCK.is_initialized() {
+        TICK_CLOCK_NANOS.fetch_add(TICK_NANOS.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+    timer::wake_expired();
+}
+
+/// Ticks since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Counter the monotonic clock is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clocksource {
+    /// Ticks counted by `tick`
+    Pit,
+    Hpet,
+    /// Invariant time stamp counter
+    Tsc,
+}
+
+impl fmt::Display for Clocksource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Clocksource::Pit => write!(f, "PIT"),
+            Clocksource::Hpet => write!(f, "HPET"),
+            Clocksource::Tsc => write!(f, "TSC"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Counter {
+    Tsc,
+    Hpet(hpet::Hpet),
+}
+
+/// High resolution counter continuing the tick clock
+#[derive(Debug)]
+struct Clock {
+    counter: Counter,
+    /// Counter increments per second
+    frequency: u64,
+    /// Nanoseconds per increment, with `SCALE_SHIFT` fractional bits
+    scale: u64,
+    /// Counter value when the clock was picked
+    start: u64,
+    /// Tick clock when the clock was picked
+    offset: u64,
+}
+
+impl Clock {
+    fn new(counter: Counter, frequency: u64, scale: u64) -> Self {
+        let mut clock = Self {
+            counter,
+            frequency,
+            scale,
+            start: 0,
+            offset: 0,
+        };
+        clock.start = clock.read();
+        clock.offset = TICK_CLOCK_NANOS.load(Ordering::Relaxed);
+        clock
+    }
+
+    fn source(&self) -> Clocksource {
+        match self.counter {
+            Counter::Tsc => Clocksource::Tsc,
+            Counter::Hpet(_) => Clocksource::Hpet,
+        }
+    }
+
+    fn read(&self) -> u64 {
+        match &self.counter {
+            Counter::Tsc => tsc::read(),
+            Counter::Hpet(hpet) => hpet.read(),
+        }
+    }
+
+    fn nanos(&self) -> u64 {
+        let elapsed = self.read().wrapping_sub(self.start) as u128;
+        self.offset + ((elapsed * self.scale as u128) >> SCALE_SHIFT) as u64
+    }
+}
+
+/// Pick the most precise clocksource, an invariant TSC, the HPET described
+/// by `hpet` or the ticks
+///
+/// Returns the clocksource picked
+pub fn init_clock(hpet: Option<&acpi::Hpet>) -> Clocksource {
+    let clock = if tsc::is_invariant() {
+        let frequency = tsc::calibrate();
+        let scale = (NANOS_PER_SEC << SCALE_SHIFT) / frequency;
+        Some(Clock::new(Counter::Tsc, frequency, scale))
+    } else {
+        hpet.and_then(|table| hpet::Hpet::init(table).ok().flatten())
+            .map(|hpet| {
+                let frequency = hpet.frequency();
+                let scale = (hpet.period_fs() << SCALE_SHIFT) / FEMTOS_PER_NANO;
+                Clock::new(Counter::Hpet(hpet), frequency, scale)
+            })
+    };
+
+    if let Some(clock) = clock {
+        CLOCK
+            .try_init_once(|| clock)
+            .expect("time::init_clock should only be called once");
+    }
+    clocksource()
+}
+
+/// Clocksource `now` reads
+pub fn clocksource() -> Clocksource {
+    CLOCK.try_get().map_or(Clocksource::Pit, Clock::source)
+}
+
+/// Increments per second of the clocksource
+pub fn clock_frequency() -> u64 {
+    match CLOCK.try_get() {
+        Ok(clock) => clock.frequency,
+        Err(_) => tick_frequency() as u64,
+    }
+}
+
+/// Current time on the monotonic clock
+pub fn now() -> Instant {
+    let nanos = match CLOCK.try_get() {
+        Ok(clock) => clock.nanos(),
+        Err(_) => TICK_CLOCK_NANOS.load(Ordering::Relaxed),
+    };
+    Instant::from_nanos(nanos)
+}
+
+/// Time since interrupts were enabled
+pub fn uptime() -> Duration {
+    now().since_boot()
+}
+
+/// Read the date from the RTC to start the wall clock
+///
+/// `century` is the CMOS index of the century from the FADT, 0 if there is
+/// none
+pub fn init_wall_clock(century: u8) -> DateTime {
+    rtc::set_century_register(century);
+    let date = rtc::read();
+    let read_at = now();
+    WALL_CLOCK_BASE
+        .try_init_once(|| (date.to_unix(), read_at))
+        .expect("time::init_wall_clock should only be called once");
+    date
+}
+
+/// Time since the Unix epoch, the RTC is read to the second
+pub fn unix_time() -> Duration {
+    match WALL_CLOCK_BASE.try_get() {
+        Ok((base, read_at)) => *base + read_at.elapsed(),
+        Err(_) => rtc::read().to_unix(),
+    }
+}
+
+/// Current date and time in UTC
+pub fn wall_clock() -> DateTime {
+    DateTime::from_unix(unix_time())
+}
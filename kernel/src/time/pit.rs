@@ -0,0 +1,109 @@
+//! 8253/8254 programmable interval timer
+//!
+//! Channel 0 is wired to ISA IRQ 0 and raises the timer interrupt each time
+//! its counter, loaded with a divisor of the input clock, reaches zero.
+//! Channel 2 drives the PC speaker and is used to busy wait a known time
+
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+/// Input clock of the PIT in Hz
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL0: u16 = 0x40;
+const CHANNEL2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+/// Port B of the 8255, gate and output of channel 2
+const PORT_B: u16 = 0x61;
+
+// port B bits
+const GATE2: u8 = 1 << 0;
+const SPEAKER_ENABLE: u8 = 1 << 1;
+const OUT2: u8 = 1 << 5;
+
+// command byte fields
+const SELECT_CHANNEL0: u8 = 0b00 << 6;
+const SELECT_CHANNEL2: u8 = 0b10 << 6;
+const ACCESS_LATCH: u8 = 0b00 << 4;
+const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+/// Mode 0, output goes high once the count reaches zero
+const MODE_ONE_SHOT: u8 = 0b000 << 1;
+/// Mode 2, rate generator, one interrupt every `divisor` input clocks
+const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+
+/// Divisor loaded on reset, 0 counts as 65536, about 18.2 Hz
+pub const DEFAULT_DIVISOR: u32 = 1 << 16;
+
+/// Divisor closest to `frequency`, clamped to what the 16 bit counter holds
+pub fn divisor(frequency: u32) -> u32 {
+    let frequency = frequency.max(1);
+    let divisor = (BASE_FREQUENCY + frequency / 2) / frequency;
+    divisor.clamp(1, DEFAULT_DIVISOR)
+}
+
+/// Frequency in Hz of interrupts raised every `divisor` input clocks
+pub fn frequency(divisor: u32) -> u32 {
+    (BASE_FREQUENCY + divisor / 2) / divisor
+}
+
+/// Raise interrupts on channel 0 every `divisor` input clocks
+///
+/// `divisor` must be between 1 and `DEFAULT_DIVISOR`
+pub fn set_divisor(divisor: u32) {
+    assert!(
+        (1..=DEFAULT_DIVISOR).contains(&divisor),
+        "PIT divisor {} out of range",
+        divisor
+    );
+
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut channel0 = Port::<u8>::new(CHANNEL0);
+    // 65536 is written as 0
+    let [low, high, ..] = divisor.to_le_bytes();
+    without_interrupts(|| unsafe {
+        command.write(SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_RATE_GENERATOR);
+        channel0.write(low);
+        channel0.write(high);
+    });
+}
+
+/// Stop the interrupts of channel 0, it waits for a new count
+pub fn stop() {
+    let mut command = Port::<u8>::new(COMMAND);
+    unsafe { command.write(SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_ONE_SHOT) };
+}
+
+/// Current count of channel 0, counting down to the next interrupt
+pub fn read_count() -> u16 {
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut channel0 = Port::<u8>::new(CHANNEL0);
+    without_interrupts(|| unsafe {
+        command.write(SELECT_CHANNEL0 | ACCESS_LATCH);
+        let low = channel0.read();
+        let high = channel0.read();
+        u16::from_le_bytes([low, high])
+    })
+}
+
+/// Busy wait `count` input clocks on channel 2, at most about 55ms
+///
+/// Channel 2 raises no interrupt, disable interrupts around the call to
+/// wait as exactly as possible
+pub fn wait(count: u16) {
+    let mut port_b = Port::<u8>::new(PORT_B);
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut channel2 = Port::<u8>::new(CHANNEL2);
+    let [low, high] = count.to_le_bytes();
+    unsafe {
+        // enable counting but keep the speaker off
+        let b = port_b.read();
+        port_b.write((b & !SPEAKER_ENABLE) | GATE2);
+
+        command.write(SELECT_CHANNEL2 | ACCESS_LOW_HIGH | MODE_ONE_SHOT);
+        channel2.write(low);
+        channel2.write(high);
+
+        while port_b.read() & OUT2 == 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
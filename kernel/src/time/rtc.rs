@@ -0,0 +1,208 @@
+//! CMOS real-time clock, keeping the date while the machine is off
+//!
+//! Its registers are read through the CMOS index and data ports, holding BCD
+//! or binary values and a 12 or 24 hour clock as set in status register B.
+//! The RTC can also raise a periodic interrupt on ISA IRQ 8
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+use super::date::DateTime;
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECOND: u8 = 0x00;
+const REG_MINUTE: u8 = 0x02;
+const REG_HOUR: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+const REG_STATUS_C: u8 = 0x0c;
+
+/// Status A bit set while the registers are being updated
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const RATE_MASK: u8 = 0x0f;
+/// Status B bits
+pub const HOURS_24: u8 = 1 << 1;
+pub const BINARY: u8 = 1 << 2;
+const PERIODIC_INTERRUPT: u8 = 1 << 6;
+/// Hour bit set for PM on a 12 hour clock
+const HOUR_PM: u8 = 1 << 7;
+
+/// Oscillator frequency divided down for the periodic interrupt
+pub const BASE_FREQUENCY: u32 = 32_768;
+/// Rates allowed for the periodic interrupt, 8192 Hz to 2 Hz
+pub const MIN_RATE: u8 = 3;
+pub const MAX_RATE: u8 = 15;
+
+/// Reads of status A waited for an update to finish, an update takes
+/// less than 2ms
+const UPDATE_TIMEOUT: usize = 100_000;
+
+/// CMOS index of the century, 0 if the RTC has none
+static CENTURY_REGISTER: AtomicU8 = AtomicU8::new(0);
+
+/// Use the century at CMOS index `register` as reported by the FADT, 0 for
+/// none
+pub fn set_century_register(register: u8) {
+    CENTURY_REGISTER.store(register, Ordering::Relaxed);
+}
+
+fn read_cmos(register: u8) -> u8 {
+    let mut index = Port::<u8>::new(CMOS_INDEX);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    // an interrupt handler selecting another register would break the pair
+    without_interrupts(|| unsafe {
+        index.write(register);
+        data.read()
+    })
+}
+
+fn write_cmos(register: u8, value: u8) {
+    let mut index = Port::<u8>::new(CMOS_INDEX);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    without_interrupts(|| unsafe {
+        index.write(register);
+        data.write(value);
+    })
+}
+
+/// Time registers as stored by the RTC, not yet decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+    /// None if the RTC has no century register
+    pub century: Option<u8>,
+    pub status_b: u8,
+}
+
+impl Registers {
+    fn read() -> Self {
+        let century = CENTURY_REGISTER.load(Ordering::Relaxed);
+        Self {
+            second: read_cmos(REG_SECOND),
+            minute: read_cmos(REG_MINUTE),
+            hour: read_cmos(REG_HOUR),
+            day: read_cmos(REG_DAY),
+            month: read_cmos(REG_MONTH),
+            year: read_cmos(REG_YEAR),
+            century: (century != 0).then(|| read_cmos(century)),
+            status_b: read_cmos(REG_STATUS_B),
+        }
+    }
+
+    /// Date held by the registers, years without century are taken to be
+    /// in the 2000s
+    pub fn decode(&self) -> DateTime {
+        let binary = self.status_b & BINARY != 0;
+        let value = |raw: u8| if binary { raw } else { from_bcd(raw) };
+
+        let pm = self.hour & HOUR_PM != 0;
+        let mut hour = value(self.hour & !HOUR_PM);
+        if self.status_b & HOURS_24 == 0 {
+            // 12 AM is midnight, 12 PM noon
+            hour = hour % 12 + if pm { 12 } else { 0 };
+        }
+
+        let century = self.century.map_or(20, value) as u16;
+        DateTime {
+            year: century * 100 + value(self.year) as u16,
+            month: value(self.month),
+            day: value(self.day),
+            hour,
+            minute: value(self.minute),
+            second: value(self.second),
+            nanosecond: 0,
+        }
+    }
+}
+
+pub fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+fn update_in_progress() -> bool {
+    read_cmos(REG_STATUS_A) & UPDATE_IN_PROGRESS != 0
+}
+
+fn wait_for_update() {
+    for _ in 0..UPDATE_TIMEOUT {
+        if !update_in_progress() {
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Current date and time of the RTC, to the second
+pub fn read() -> DateTime {
+    // an update may still start during the reads, read again until two
+    // reads agree
+    wait_for_update();
+    let mut registers = Registers::read();
+    loop {
+        wait_for_update();
+        let again = Registers::read();
+        if again == registers {
+            return registers.decode();
+        }
+        registers = again;
+    }
+}
+
+/// Periodic interrupt rate giving the frequency closest to `frequency`
+pub fn rate(frequency: u32) -> u8 {
+    (MIN_RATE..=MAX_RATE)
+        .min_by_key(|&rate| periodic_frequency(rate).abs_diff(frequency))
+        .unwrap()
+}
+
+/// Frequency in Hz of the periodic interrupt at `rate`
+pub fn periodic_frequency(rate: u8) -> u32 {
+    BASE_FREQUENCY >> (rate - 1)
+}
+
+/// Raise IRQ 8 `periodic_frequency(rate)` times a second
+///
+/// Every interrupt must be acknowledged for the next one to come
+pub fn enable_periodic(rate: u8) {
+    assert!(
+        (MIN_RATE..=MAX_RATE).contains(&rate),
+        "RTC rate {} out of range",
+        rate
+    );
+
+    without_interrupts(|| {
+        let status_a = read_cmos(REG_STATUS_A);
+        write_cmos(REG_STATUS_A, (status_a & !RATE_MASK) | rate);
+        let status_b = read_cmos(REG_STATUS_B);
+        write_cmos(REG_STATUS_B, status_b | PERIODIC_INTERRUPT);
+        acknowledge();
+    });
+}
+
+pub fn disable_periodic() {
+    without_interrupts(|| {
+        let status_b = read_cmos(REG_STATUS_B);
+        write_cmos(REG_STATUS_B, status_b & !PERIODIC_INTERRUPT);
+        acknowledge();
+    });
+}
+
+pub fn periodic_enabled() -> bool {
+    read_cmos(REG_STATUS_B) & PERIODIC_INTERRUPT != 0
+}
+
+/// Acknowledge the RTC interrupt by reading status C, which holds its cause
+pub fn acknowledge() -> u8 {
+    read_cmos(REG_STATUS_C)
+}
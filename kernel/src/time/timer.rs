@@ -0,0 +1,286 @@
+//! Timer futures woken by the tick interrupt
+//!
+//! Pending timers are kept ordered by deadline. Each tick the expired ones
+//! wake their task, the interrupt handler only wakes and marks them, so it
+//! never allocates or frees. Timers are removed by their future once it
+//! completes or is dropped
+
+use alloc::collections::BTreeMap;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_util::stream::Stream;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{now, Instant};
+
+/// Timers by deadline, ids keep equal deadlines apart
+static TIMERS: Mutex<BTreeMap<TimerKey, Timer>> = Mutex::new(BTreeMap::new());
+/// Earliest deadline not yet fired, `u64::MAX` if there is none
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TimerKey {
+    deadline: Instant,
+    id: u64,
+}
+
+impl TimerKey {
+    fn new(deadline: Instant) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        Self {
+            deadline,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Timer {
+    waker: Waker,
+    fired: bool,
+}
+
+/// Lock the timers, the tick interrupt must not come while they are held
+fn with_timers<R>(f: impl FnOnce(&mut BTreeMap<TimerKey, Timer>) -> R) -> R {
+    without_interrupts(|| f(&mut TIMERS.lock()))
+}
+
+/// Wake the tasks of expired timers, called on every tick
+pub(crate) fn wake_expired() {
+    let now = now();
+    if now.as_nanos() < NEXT_DEADLINE.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // task code holds the lock only with interrupts disabled
+    let mut timers = match TIMERS.try_lock() {
+        Some(timers) => timers,
+        None => return,
+    };
+    let mut next = u64::MAX;
+    for (key, timer) in timers.iter_mut().filter(|(_, timer)| !timer.fired) {
+        if key.deadline > now {
+            next = key.deadline.as_nanos();
+            break;
+        }
+        timer.waker.wake_by_ref();
+        timer.fired = true;
+    }
+    NEXT_DEADLINE.store(next, Ordering::Relaxed);
+}
+
+/// Number of timers waiting for their deadline
+pub fn pending() -> usize {
+    with_timers(|timers| timers.values().filter(|timer| !timer.fired).count())
+}
+
+/// Future completing once its deadline has passed
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Sleep {
+    deadline: Instant,
+    registered: Option<TimerKey>,
+}
+
+/// Wait until `duration` has passed
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(now().saturating_add(duration))
+}
+
+/// Wait until `deadline`
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        registered: None,
+    }
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        now() >= self.deadline
+    }
+
+    /// Wait until `deadline` instead
+    pub fn reset(&mut self, deadline: Instant) {
+        self.unregister();
+        self.deadline = deadline;
+    }
+
+    fn register(&mut self, waker: &Waker) {
+        with_timers(|timers| match self.registered {
+            Some(key) => {
+                let timer = timers.get_mut(&key).expect("sleep timer missing");
+                if !timer.waker.will_wake(waker) {
+                    timer.waker = waker.clone();
+                }
+            }
+            None => {
+                let key = TimerKey::new(self.deadline);
+                let timer = Timer {
+                    waker: waker.clone(),
+                    fired: false,
+                };
+                timers.insert(key, timer);
+                NEXT_DEADLINE.fetch_min(self.deadline.as_nanos(), Ordering::Relaxed);
+                self.registered = Some(key);
+            }
+        });
+    }
+
+    fn unregister(&mut self) {
+        if let Some(key) = self.registered.take() {
+            // the waker is dropped with interrupts enabled again
+            let timer = with_timers(|timers| timers.remove(&key));
+            drop(timer);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.is_elapsed() {
+            self.unregister();
+            return Poll::Ready(());
+        }
+
+        self.register(cx.waker());
+        // the deadline may have passed before the timer was seen by a tick
+        if self.is_elapsed() {
+            self.unregister();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+/// Stream yielding once every period
+///
+/// Yields the instant each period ended. Periods missed while the task
+/// didn't poll are skipped, not yielded in a burst
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+/// Yield every `period`, the first time one period from now
+///
+/// Panics if `period` is zero
+pub fn interval(period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "interval period must not be zero");
+
+    Interval {
+        period,
+        sleep: sleep(period),
+    }
+}
+
+impl Interval {
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// End of the next period
+    pub fn next_deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Instant>> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let deadline = self.sleep.deadline();
+        let mut next = deadline.saturating_add(self.period);
+        let now = now();
+        if next <= now {
+            let period = self.period.as_nanos() as u64;
+            let missed = (now.as_nanos() - deadline.as_nanos()) / period;
+            next = deadline.saturating_add(Duration::from_nanos((missed + 1) * period));
+        }
+        self.sleep.reset(next);
+        Poll::Ready(Some(deadline))
+    }
+}
+
+/// Error of a `timeout` whose deadline passed first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+
+/// Future running `future` until a deadline
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+/// Run `future`, giving up with `Elapsed` if it didn't complete within
+/// `duration`
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+impl<F> Timeout<F> {
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // the future is structurally pinned, the sleep is Unpin
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        // a future completing right at the deadline still counts
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
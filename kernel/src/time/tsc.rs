@@ -0,0 +1,41 @@
+//! Time stamp counter, counting CPU clock cycles
+//!
+//! Only an invariant TSC, running at a constant rate in every power state,
+//! is usable as clock. Its frequency isn't reported reliably and is measured
+//! against the PIT
+
+use core::arch::x86_64::{__cpuid, _rdtsc};
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::pit;
+
+/// PIT input clocks measured over, about 50ms
+const CALIBRATION_COUNT: u16 = 59_659;
+
+/// Check CPUID for a TSC running at a constant rate
+pub fn is_invariant() -> bool {
+    let max_extended = unsafe { __cpuid(0x8000_0000) }.eax;
+    if max_extended < 0x8000_0007 {
+        return false;
+    }
+    let power = unsafe { __cpuid(0x8000_0007) };
+    power.edx & (1 << 8) != 0
+}
+
+/// Current cycle count
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Frequency of the TSC in Hz, measured by waiting on the PIT
+pub fn calibrate() -> u64 {
+    let (start, end) = without_interrupts(|| {
+        let start = read();
+        pit::wait(CALIBRATION_COUNT);
+        (start, read())
+    });
+
+    let cycles = end.wrapping_sub(start) as u128;
+    (cycles * pit::BASE_FREQUENCY as u128 / CALIBRATION_COUNT as u128) as u64
+}
@@ -0,0 +1,211 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec, vec::Vec};
+
+    use oros_kernel::acpi::{
+        self,
+        madt::{Polarity, TriggerMode},
+        AcpiError, Madt, Mcfg, Rsdp, Sdt, Signature,
+    };
+    use oros_kernel::interrupts::apic::{ApicConfig, IrqOverride};
+    use x86_64::PhysAddr;
+
+    /// Table with a valid header and checksum around `body`
+    fn table(signature: &[u8; 4], body: &[u8]) -> &'static [u8] {
+        let mut data = Vec::new();
+        data.extend_from_slice(signature);
+        data.extend_from_slice(&((36 + body.len()) as u32).to_le_bytes());
+        data.push(1); // revision
+        data.push(0); // checksum
+        data.extend_from_slice(b"OROSOS");
+        data.extend_from_slice(b"OROSTEST");
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(body);
+
+        data[9] = 0u8.wrapping_sub(acpi::checksum(&data));
+        Box::leak(data.into_boxed_slice())
+    }
+
+    #[test_case]
+    fn tables_found() {
+        let tables = acpi::try_tables().expect("no ACPI tables");
+        assert!(tables.find(Signature::MADT).is_some());
+        assert!(tables.find(Signature::FADT).is_some());
+    }
+
+    #[test_case]
+    fn madt_parsed() {
+        let madt = acpi::tables().madt.as_ref().expect("no MADT");
+        assert!(madt.processors.iter().any(|cpu| cpu.enabled));
+        assert!(!madt.io_apics.is_empty());
+        // QEMU wires the PIT to GSI 2
+        assert!(madt.overrides.iter().any(|o| o.irq == 0 && o.gsi == 2));
+    }
+
+    #[test_case]
+    fn fadt_parsed() {
+        let fadt = acpi::tables().fadt.as_ref().expect("no FADT");
+        assert!(fadt.pm1a_control.is_some());
+        assert!(!fadt.dsdt.is_null());
+
+        let dsdt = unsafe { Sdt::at(fadt.dsdt) }.expect("invalid DSDT");
+        assert_eq!(dsdt.signature, Signature::DSDT);
+    }
+
+    #[test_case]
+    fn hpet_parsed() {
+        // QEMU provides an HPET unless disabled
+        if let Some(hpet) = acpi::tables().hpet {
+            assert!(!hpet.base.is_null());
+            assert!(hpet.comparators >= 3);
+        }
+    }
+
+    #[test_case]
+    fn mcfg_parsed() {
+        // only on PCIe machines like q35
+        if let Some(mcfg) = acpi::tables().mcfg.as_ref() {
+            assert!(!mcfg.entries.is_empty());
+        }
+    }
+
+    #[test_case]
+    fn checksum_mismatch_rejected() {
+        let data = table(b"TEST", &[1, 2, 3, 4]);
+        let mut corrupt = data.to_vec();
+        corrupt[36] ^= 0xff;
+        let corrupt: &'static [u8] = Box::leak(corrupt.into_boxed_slice());
+
+        assert!(Sdt::from_bytes(data).is_ok());
+        assert_eq!(
+            Sdt::from_bytes(corrupt).unwrap_err(),
+            AcpiError::InvalidChecksum(Signature(*b"TEST"))
+        );
+    }
+
+    #[test_case]
+    fn truncated_table_rejected() {
+        let data = table(b"TEST", &[0; 8]);
+        let truncated: &'static [u8] = &data[..40];
+        assert_eq!(
+            Sdt::from_bytes(truncated).unwrap_err(),
+            AcpiError::InvalidLength(Signature(*b"TEST"))
+        );
+    }
+
+    #[test_case]
+    fn rsdp_v1_parsed() {
+        let mut data = vec![0; 20];
+        data[..8].copy_from_slice(b"RSD PTR ");
+        data[9..15].copy_from_slice(b"OROSOS");
+        data[16..20].copy_from_slice(&0x000e_0000u32.to_le_bytes());
+        data[8] = 0u8.wrapping_sub(acpi::checksum(&data));
+
+        let rsdp = Rsdp::from_bytes(&data).unwrap();
+        assert_eq!(rsdp.revision, 0);
+        assert_eq!(rsdp.rsdt_addr, PhysAddr::new(0xe_0000));
+        assert_eq!(rsdp.xsdt_addr, None);
+
+        data[8] = data[8].wrapping_add(1);
+        assert!(Rsdp::from_bytes(&data).is_err());
+    }
+
+    fn synthesized_madt() -> Madt {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0xfee0_0000u32.to_le_bytes());
+        body.extend_from_slice(&1u32.to_le_bytes());
+        // processor 0 with APIC ID 0, enabled
+        body.extend_from_slice(&[0, 8, 0, 0, 1, 0, 0, 0]);
+        // I/O APIC 1 at 0xfec00000, GSI base 0
+        body.extend_from_slice(&[1, 12, 1, 0, 0x00, 0x00, 0xc0, 0xfe, 0, 0, 0, 0]);
+        // IRQ 9 to GSI 9, active low, level triggered
+        body.extend_from_slice(&[2, 10, 0, 9, 9, 0, 0, 0, 0b1111, 0]);
+        // NMI on LINT1 of all processors
+        body.extend_from_slice(&[4, 6, 0xff, 0, 0, 1]);
+
+        let sdt = Sdt::from_bytes(table(b"APIC", &body)).unwrap();
+        Madt::parse(&sdt).unwrap()
+    }
+
+    #[test_case]
+    fn madt_entries_decoded() {
+        let madt = synthesized_madt();
+        assert_eq!(madt.local_apic_address, PhysAddr::new(0xfee0_0000));
+        assert!(madt.pcat_compat);
+        assert_eq!(madt.processors.len(), 1);
+        assert!(madt.processors[0].enabled);
+        assert_eq!(madt.io_apics[0].address, PhysAddr::new(0xfec0_0000));
+
+        let irq9 = madt.overrides[0];
+        assert_eq!((irq9.irq, irq9.gsi), (9, 9));
+        assert_eq!(irq9.polarity, Polarity::ActiveLow);
+        assert_eq!(irq9.trigger, TriggerMode::Level);
+
+        assert_eq!(madt.nmis[0].processor_id, 0xff);
+        assert_eq!(madt.nmis[0].lint, 1);
+    }
+
+    #[test_case]
+    fn apic_config_from_madt() {
+        let config = ApicConfig::from_madt(&synthesized_madt()).unwrap();
+        assert_eq!(config.io_apic_base, PhysAddr::new(0xfec0_0000));
+        assert_eq!(
+            config.overrides,
+            [IrqOverride {
+                irq: 9,
+                gsi: 9,
+                active_low: true,
+                level_triggered: true,
+            }]
+        );
+    }
+
+    #[test_case]
+    fn mcfg_entries_decoded() {
+        let mut body = vec![0; 8];
+        body.extend_from_slice(&0xb000_0000u64.to_le_bytes());
+        body.extend_from_slice(&[0, 0, 0, 0xff, 0, 0, 0, 0]);
+
+        let sdt = Sdt::from_bytes(table(b"MCFG", &body)).unwrap();
+        let mcfg = Mcfg::parse(&sdt).unwrap();
+        let entry = mcfg.entries[0];
+        assert_eq!(entry.base, PhysAddr::new(0xb000_0000));
+        assert_eq!(entry.end_bus, 0xff);
+        assert_eq!(
+            entry.function_address(1, 2, 3),
+            Some(PhysAddr::new(
+                0xb000_0000 + (1 << 20) + (2 << 15) + (3 << 12)
+            ))
+        );
+        assert_eq!(entry.function_address(0, 32, 0), None);
+    }
+}
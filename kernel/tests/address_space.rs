@@ -0,0 +1,172 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use oros_kernel::interrupts::{
+        exceptions::{self, ExceptionFrame},
+        usermode,
+    };
+    use oros_kernel::memory::{
+        self,
+        address_space::{self, AddressSpace, AddressSpaceError, KERNEL_SPACE_START},
+    };
+    use x86_64::{
+        instructions::interrupts::without_interrupts,
+        registers::{control::Cr3, rflags::RFlags},
+        structures::paging::{Page, PageTableFlags, Translate},
+        VirtAddr,
+    };
+
+    const USER_PAGE: u64 = 0x40_0000;
+
+    #[test_case]
+    fn kernel_half_is_shared() {
+        let mut space = AddressSpace::new().unwrap();
+        let kernel_code = VirtAddr::new(super::test_kernel_main as usize as u64);
+        assert!(kernel_code.as_u64() >= KERNEL_SPACE_START);
+
+        let heap_value = Box::new(7u64);
+        let heap_addr = VirtAddr::from_ptr(&*heap_value);
+
+        let mapper = space.mapper();
+        let kernel_mapper = memory::mapper();
+        for addr in [kernel_code, heap_addr, memory::phys_mem_offset()] {
+            assert_eq!(
+                mapper.translate_addr(addr),
+                kernel_mapper.translate_addr(addr)
+            );
+        }
+    }
+
+    #[test_case]
+    fn user_pages_are_private() {
+        let page = Page::containing_address(VirtAddr::new(USER_PAGE));
+        let mut first = AddressSpace::new().unwrap();
+        let mut second = AddressSpace::new().unwrap();
+
+        first.map_user(page, PageTableFlags::WRITABLE).unwrap();
+        second.map_user(page, PageTableFlags::WRITABLE).unwrap();
+
+        let ptr: *mut u64 = page.start_address().as_mut_ptr();
+        unsafe {
+            first.switch();
+            assert_eq!(ptr.read_volatile(), 0);
+            ptr.write_volatile(1);
+
+            second.switch();
+            assert_eq!(ptr.read_volatile(), 0);
+            ptr.write_volatile(2);
+
+            first.switch();
+            assert_eq!(ptr.read_volatile(), 1);
+
+            AddressSpace::switch_to_kernel();
+        }
+
+        assert_eq!(Cr3::read().0, address_space::kernel_table());
+        assert!(memory::mapper()
+            .translate_addr(page.start_address())
+            .is_none());
+    }
+
+    #[test_case]
+    fn kernel_pages_are_rejected() {
+        let mut space = AddressSpace::new().unwrap();
+        let page = Page::containing_address(VirtAddr::new(KERNEL_SPACE_START));
+        assert!(matches!(
+            space.map_user(page, PageTableFlags::WRITABLE),
+            Err(AddressSpaceError::KernelAddress)
+        ));
+    }
+
+    #[test_case]
+    fn drop_frees_frames() {
+        let used = memory::frame_allocator().used_frames();
+
+        let mut space = AddressSpace::new().unwrap();
+        for i in 0..4 {
+            let page = Page::containing_address(VirtAddr::new(USER_PAGE + i * 0x20_0000));
+            space.map_user(page, PageTableFlags::WRITABLE).unwrap();
+        }
+        assert!(memory::frame_allocator().used_frames() > used);
+
+        unsafe { space.switch() };
+        drop(space);
+
+        assert_eq!(Cr3::read().0, address_space::kernel_table());
+        assert_eq!(memory::frame_allocator().used_frames(), used);
+    }
+
+    #[test_case]
+    fn user_code_runs_in_ring_3() {
+        static VECTOR: AtomicU64 = AtomicU64::new(u64::MAX);
+        static CODE_SEGMENT: AtomicU64 = AtomicU64::new(0);
+
+        fn exit_user(frame: &mut ExceptionFrame) -> bool {
+            if !usermode::is_user(frame) {
+                return false;
+            }
+            VECTOR.store(frame.vector, Ordering::SeqCst);
+            CODE_SEGMENT.store(frame.stack_frame.code_segment, Ordering::SeqCst);
+            usermode::exit(frame);
+            true
+        }
+
+        // ud2
+        const CODE: [u8; 2] = [0x0f, 0x0b];
+        let code_page = Page::containing_address(VirtAddr::new(USER_PAGE));
+        let stack_page = code_page + 1;
+
+        let mut space = AddressSpace::new().unwrap();
+        let code = space.map_user(code_page, PageTableFlags::empty()).unwrap();
+        space
+            .map_user(stack_page, PageTableFlags::WRITABLE)
+            .unwrap();
+        let code_ptr = (memory::phys_mem_offset() + code.start_address().as_u64()).as_mut_ptr();
+        unsafe { core::ptr::copy_nonoverlapping(CODE.as_ptr(), code_ptr, CODE.len()) };
+
+        without_interrupts(|| unsafe {
+            space.switch();
+            exceptions::set_exception_hook(Some(exit_user));
+            usermode::run(
+                code_page.start_address(),
+                stack_page.start_address() + 0x1000u64,
+                RFlags::empty(),
+            );
+            exceptions::set_exception_hook(None);
+            AddressSpace::switch_to_kernel();
+        });
+
+        assert_eq!(VECTOR.load(Ordering::SeqCst), 6);
+        assert_eq!(CODE_SEGMENT.load(Ordering::SeqCst) & 3, 3);
+    }
+}
@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::cmdline;
+    use oros_kernel::interrupts::{
+        apic::{self, ISA_IRQS},
+        controller::{self, Controller},
+        pic::InterruptIndex,
+    };
+    use oros_kernel::port::serial;
+
+    #[test_case]
+    fn apic_selected_when_supported() {
+        let expected = if apic::is_supported() && controller::preferred() == Controller::Apic {
+            Controller::Apic
+        } else {
+            Controller::Pic
+        };
+        assert_eq!(controller::active(), expected);
+    }
+
+    #[test_case]
+    fn controller_option_parsed() {
+        let cmdline = "quiet interrupt_controller=pic";
+        let name = cmdline::find_option(cmdline, controller::OPTION);
+        assert_eq!(name.and_then(Controller::from_name), Some(Controller::Pic));
+        assert_eq!(Controller::from_name("apic"), Some(Controller::Apic));
+        assert_eq!(Controller::from_name("8259"), None);
+        assert_eq!(cmdline::find_option("quiet", controller::OPTION), None);
+    }
+
+    #[test_case]
+    fn local_apic_enabled() {
+        let local_apic = match apic::local_apic() {
+            Some(local_apic) => local_apic,
+            None => return,
+        };
+        assert!(local_apic.is_enabled());
+        assert_ne!(local_apic.version(), 0);
+    }
+
+    #[test_case]
+    fn isa_irqs_routed() {
+        let io_apic = match apic::io_apic() {
+            Some(io_apic) => io_apic,
+            None => return,
+        };
+        // ISA IRQs need at least 16 inputs
+        assert!(io_apic.max_entries() >= 16);
+        assert!(io_apic.handles(io_apic.gsi_base()));
+        assert!(!io_apic.handles(io_apic.gsi_base() + io_apic.max_entries()));
+
+        let keyboard = io_apic.redirection(1);
+        assert_eq!(
+            apic::redirection_vector(keyboard),
+            u8::from(InterruptIndex::Keyboard)
+        );
+        assert!(!io_apic.is_masked(1));
+
+        // PIT is wired to input 2 on PCs
+        let timer = io_apic.redirection(2);
+        assert_eq!(
+            apic::redirection_vector(timer),
+            u8::from(InterruptIndex::Timer)
+        );
+
+        for (irq, _) in ISA_IRQS {
+            if irq != 0 {
+                assert!(!io_apic.is_masked(irq as u32));
+            }
+        }
+    }
+
+    #[test_case]
+    fn timer_interrupts_acknowledged() {
+        // every tick needs an EOI for the next one to arrive, hlt hangs
+        // otherwise
+        for _ in 0..3 {
+            x86_64::instructions::hlt();
+        }
+    }
+
+    #[test_case]
+    fn serial_receive_does_not_wait() {
+        // the serial interrupt handler reads like this, it must return
+        // once no byte is pending
+        while serial::try_receive().is_some() {}
+        assert_eq!(serial::try_receive(), None);
+    }
+}
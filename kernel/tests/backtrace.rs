@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use x86_64::VirtAddr;
+
+    use oros_kernel::backtrace::{symbols, Backtrace};
+    use oros_kernel::memory::address_space::KERNEL_SPACE_START;
+
+    /// Calls between a function and the return address into it
+    const MAX_CALL_OFFSET: u64 = 0x400;
+
+    #[inline(never)]
+    fn outer() -> Backtrace {
+        core::hint::black_box(inner())
+    }
+
+    #[inline(never)]
+    fn inner() -> Backtrace {
+        core::hint::black_box(Backtrace::capture())
+    }
+
+    fn returns_into(addr: u64, function: u64) -> bool {
+        addr > function && addr - function < MAX_CALL_OFFSET
+    }
+
+    #[test_case]
+    fn capture_follows_callers() {
+        let backtrace = outer();
+        let frames = backtrace.frames();
+
+        assert!(frames.len() >= 3);
+        assert!(returns_into(frames[0], inner as usize as u64));
+        assert!(returns_into(frames[1], outer as usize as u64));
+    }
+
+    #[test_case]
+    fn from_frame_starts_at_rip() {
+        let rip = VirtAddr::new(inner as usize as u64);
+        let backtrace = Backtrace::from_frame(rip, 0);
+        assert_eq!(backtrace.frames(), &[rip.as_u64()]);
+    }
+
+    #[test_case]
+    fn invalid_frame_pointer_stops_walk() {
+        let rip = VirtAddr::new(inner as usize as u64);
+        // user half, unaligned and wrapping pointers are never followed
+        assert_eq!(Backtrace::from_frame(rip, 0x1000).frames().len(), 1);
+        assert_eq!(Backtrace::from_frame(rip, u64::MAX - 7).frames().len(), 1);
+        assert_eq!(
+            Backtrace::from_frame(rip, KERNEL_SPACE_START + 4)
+                .frames()
+                .len(),
+            1
+        );
+    }
+
+    #[test_case]
+    fn display_lists_frames() {
+        let backtrace = outer();
+        let text = format!("{}", backtrace);
+        assert!(text.starts_with("Backtrace:"));
+        assert_eq!(text.lines().count(), backtrace.frames().len() + 1);
+    }
+
+    #[test_case]
+    fn resolve_anchor() {
+        // only with a symbol table of this build embedded by build.rs
+        if symbols::count() == 0 {
+            return;
+        }
+
+        let anchor = VirtAddr::new(symbols::oros_symbol_anchor as *const () as usize as u64);
+        let symbol = symbols::resolve(anchor).expect("anchor not in symbol table");
+        assert_eq!(
+            symbol.name.strip_prefix("oros_symbol_anchor_"),
+            Some(symbols::BUILD_ID)
+        );
+        assert_eq!(symbol.addr, anchor);
+    }
+}
@@ -0,0 +1,153 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::memory::{
+        self,
+        cow::{self, COPY_ON_WRITE},
+        vmm::{Region, RegionKind, VMM},
+    };
+    use x86_64::structures::paging::{
+        mapper::{MappedFrame, TranslateResult},
+        FrameAllocator, FrameDeallocator, OffsetPageTable, Page, PageTable, PageTableFlags,
+        PhysFrame, Size4KiB, Translate,
+    };
+
+    /// Empty level 4 table standing in for a second address space
+    fn new_page_table() -> OffsetPageTable<'static> {
+        let frame = memory::frame_allocator().allocate_frame().unwrap();
+        let phys_mem_offset = memory::phys_mem_offset();
+        let table_ptr: *mut PageTable =
+            (phys_mem_offset + frame.start_address().as_u64()).as_mut_ptr();
+        unsafe {
+            table_ptr.write(PageTable::new());
+            OffsetPageTable::new(&mut *table_ptr, phys_mem_offset)
+        }
+    }
+
+    fn translate(mapper: &OffsetPageTable, page: Page) -> (PhysFrame, PageTableFlags) {
+        match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } => (frame, flags),
+            _ => panic!("page not mapped"),
+        }
+    }
+
+    /// Page holding `value`, shared with a new page table
+    fn shared_page(value: u64) -> (Region, OffsetPageTable<'static>) {
+        let flags = PageTableFlags::WRITABLE;
+        let region = VMM
+            .lock()
+            .allocate_mapped(0x1000, 0x1000, RegionKind::General, flags)
+            .unwrap();
+        unsafe { region.start.as_mut_ptr::<u64>().write_volatile(value) };
+
+        let mut dst = new_page_table();
+        let page: Page<Size4KiB> = Page::containing_address(region.start);
+        unsafe {
+            cow::share_page(
+                &mut memory::mapper(),
+                &mut dst,
+                page,
+                &mut *memory::frame_allocator(),
+            )
+            .unwrap();
+        }
+
+        (region, dst)
+    }
+
+    #[test_case]
+    fn shared_page_is_read_only() {
+        let (region, dst) = shared_page(1);
+        let page = Page::containing_address(region.start);
+
+        let (src_frame, src_flags) = translate(&memory::mapper(), page);
+        let (dst_frame, dst_flags) = translate(&dst, page);
+
+        assert_eq!(src_frame, dst_frame);
+        assert_eq!(cow::ref_count(src_frame), 2);
+        for flags in [src_flags, dst_flags] {
+            assert!(flags.contains(COPY_ON_WRITE));
+            assert!(!flags.contains(PageTableFlags::WRITABLE));
+        }
+
+        // reads don't fault
+        assert_eq!(unsafe { region.start.as_ptr::<u64>().read_volatile() }, 1);
+    }
+
+    #[test_case]
+    fn write_copies_shared_page() {
+        let (region, dst) = shared_page(2);
+        let page = Page::containing_address(region.start);
+        let (shared_frame, _) = translate(&memory::mapper(), page);
+
+        unsafe { region.start.as_mut_ptr::<u64>().write_volatile(3) };
+
+        let (src_frame, src_flags) = translate(&memory::mapper(), page);
+        let (dst_frame, _) = translate(&dst, page);
+
+        assert_ne!(src_frame, shared_frame);
+        assert_eq!(dst_frame, shared_frame);
+        assert!(src_flags.contains(PageTableFlags::WRITABLE));
+        assert!(!src_flags.contains(COPY_ON_WRITE));
+        assert_eq!(cow::ref_count(shared_frame), 0);
+
+        // other page table still sees the old content
+        let shared_ptr: *const u64 =
+            (memory::phys_mem_offset() + shared_frame.start_address().as_u64()).as_ptr();
+        assert_eq!(unsafe { shared_ptr.read_volatile() }, 2);
+        assert_eq!(unsafe { region.start.as_ptr::<u64>().read_volatile() }, 3);
+    }
+
+    #[test_case]
+    fn last_user_keeps_frame() {
+        let (region, mut dst) = shared_page(4);
+        let page = Page::containing_address(region.start);
+
+        // copy for the active table, dst is the only user left
+        unsafe { region.start.as_mut_ptr::<u64>().write_volatile(5) };
+        let (shared_frame, _) = translate(&dst, page);
+
+        let handled =
+            cow::handle_write_fault(&mut dst, &mut memory::frame_allocator(), region.start);
+        assert!(handled);
+
+        let (frame, flags) = translate(&dst, page);
+        assert_eq!(frame, shared_frame);
+        assert!(flags.contains(PageTableFlags::WRITABLE));
+        assert!(!flags.contains(COPY_ON_WRITE));
+
+        unsafe { memory::frame_allocator().deallocate_frame(frame) };
+    }
+}
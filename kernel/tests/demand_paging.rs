@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::memory::{
+        self,
+        vmm::{Backing, RegionKind, VMM},
+    };
+    use x86_64::structures::paging::{PageTableFlags, Translate};
+
+    const FLAGS: PageTableFlags = PageTableFlags::WRITABLE;
+
+    #[test_case]
+    fn lazy_region_is_not_mapped() {
+        let used = memory::frame_allocator().used_frames();
+        let region = VMM
+            .lock()
+            .allocate_lazy(0x10_0000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+
+        assert_eq!(region.backing, Backing::Lazy);
+        assert!(memory::mapper().translate_addr(region.start).is_none());
+        assert_eq!(memory::frame_allocator().used_frames(), used);
+
+        VMM.lock().release(region.start).unwrap();
+    }
+
+    #[test_case]
+    fn touched_page_is_mapped_zeroed() {
+        let region = VMM
+            .lock()
+            .allocate_lazy(0x4000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+        let page_ptr = |page: u64| -> *mut u64 { (region.start + page * 0x1000).as_mut_ptr() };
+
+        // first fault may also allocate frames for page tables
+        unsafe { page_ptr(3).write_volatile(1) };
+        let used = memory::frame_allocator().used_frames();
+
+        unsafe {
+            let ptr = page_ptr(1).add(1);
+            assert_eq!(ptr.read_volatile(), 0);
+            ptr.write_volatile(42);
+            assert_eq!(ptr.read_volatile(), 42);
+        }
+
+        // only touched pages are backed
+        assert_eq!(memory::frame_allocator().used_frames(), used + 1);
+        let mapper = memory::mapper();
+        assert!(mapper.translate_addr(region.start).is_none());
+        assert!(mapper.translate_addr(region.start + 0x1000u64).is_some());
+        assert!(mapper.translate_addr(region.start + 0x2000u64).is_none());
+        drop(mapper);
+
+        VMM.lock().release(region.start).unwrap();
+        assert_eq!(memory::frame_allocator().used_frames(), used - 1);
+    }
+
+    #[test_case]
+    fn write_fault_maps_page() {
+        let region = VMM
+            .lock()
+            .allocate_lazy(0x2000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+
+        let ptr: *mut u8 = (region.start + 0x1fffu64).as_mut_ptr();
+        unsafe { ptr.write_volatile(7) };
+        assert_eq!(unsafe { ptr.read_volatile() }, 7);
+
+        VMM.lock().release(region.start).unwrap();
+        assert!(memory::mapper()
+            .translate_addr(region.start + 0x1000u64)
+            .is_none());
+    }
+}
@@ -0,0 +1,658 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+// Vectors raised for real: 0, 1, 2, 3, 6, 7, 8, 10, 11, 12, 13, 14, 16, 17
+// and 19. 2 is sent by the local APIC, 8 is also raised by the
+// stack_overflow and kernel_stack_overflow tests.
+//
+// Vectors that can't be raised in this kernel, their tests are marked
+// untriggerable and are no coverage of the exception itself:
+// - 4 (#OF), 5 (#BR): `into` and `bound` are invalid in 64-bit mode
+// - 9: not raised by CPUs since the 486
+// - 18 (#MC): raised by hardware errors
+// - 20 (#VE), 28 (#HV), 29 (#VC): raised in virtualized guests only
+// - 21 (#CP): needs CET shadow stacks
+// - 30 (#SX): raised by SVM security events
+//
+// Untriggerable vectors without an error code enter their stub with
+// `int n`. `int n` pushes no error code, so 21, 29 and 30 only pass a
+// synthesized frame to `dispatch`
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use core::arch::asm;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use oros_kernel::interrupts::{
+        apic,
+        exceptions::{self, ErrorCode, ExceptionFrame, Registers},
+        usermode,
+    };
+    use oros_kernel::memory::{self, address_space::AddressSpace, vmm::VMM_END};
+    use x86_64::{
+        instructions::{
+            interrupts::without_interrupts,
+            tables::{lgdt, load_tss, sgdt, sidt},
+        },
+        registers::{
+            control::{Cr0, Cr0Flags, Cr2, Cr4, Cr4Flags},
+            rflags::RFlags,
+        },
+        structures::{
+            gdt::SegmentSelector,
+            idt::{
+                DescriptorTable, InterruptStackFrameValue, PageFaultErrorCode, SelectorErrorCode,
+            },
+            paging::{Page, PageTableFlags},
+            DescriptorTablePointer,
+        },
+        VirtAddr,
+    };
+
+    const NO_EXCEPTION: u64 = u64::MAX;
+    const TRAP_FLAG: u64 = 1 << 8;
+    const NON_CANONICAL: u64 = 0x8000_0000_0000_0000;
+
+    static VECTOR: AtomicU64 = AtomicU64::new(NO_EXCEPTION);
+    static ERROR_CODE: AtomicU64 = AtomicU64::new(0);
+    static FAULT_ADDR: AtomicU64 = AtomicU64::new(0);
+
+    /// Record the exception and continue at the address in `r11`
+    fn resume(frame: &mut ExceptionFrame) -> bool {
+        VECTOR.store(frame.vector, Ordering::SeqCst);
+        ERROR_CODE.store(frame.error_code, Ordering::SeqCst);
+        if frame.vector == 14 {
+            FAULT_ADDR.store(Cr2::read().as_u64(), Ordering::SeqCst);
+        }
+
+        frame.stack_frame.instruction_pointer = VirtAddr::new(frame.registers.r11);
+        // stop single stepping after a debug exception
+        frame.stack_frame.cpu_flags &= !TRAP_FLAG;
+        true
+    }
+
+    /// Record the exception and return where it was raised, for
+    /// exceptions arriving at any instruction
+    fn record(frame: &mut ExceptionFrame) -> bool {
+        VECTOR.store(frame.vector, Ordering::SeqCst);
+        ERROR_CODE.store(frame.error_code, Ordering::SeqCst);
+        true
+    }
+
+    /// Record the exception raised in ring 3 and end the user mode run
+    fn exit_user(frame: &mut ExceptionFrame) -> bool {
+        if !usermode::is_user(frame) {
+            return false;
+        }
+        usermode::exit(frame);
+        record(frame)
+    }
+
+    /// Run `f` and check it raised exception `vector`, returns the error code
+    fn expect_exception(vector: u64, f: impl FnOnce()) -> u64 {
+        VECTOR.store(NO_EXCEPTION, Ordering::SeqCst);
+        exceptions::set_exception_hook(Some(resume));
+        f();
+        exceptions::set_exception_hook(None);
+
+        assert_eq!(VECTOR.load(Ordering::SeqCst), vector);
+        ERROR_CODE.load(Ordering::SeqCst)
+    }
+
+    /// Frame of an exception the CPU can't be made to raise, passed to
+    /// `dispatch` directly. Such tests check the dispatch only, or decode
+    /// the error code of an exception raised before
+    fn synthesized_frame(vector: u64, error_code: u64) -> ExceptionFrame {
+        ExceptionFrame {
+            registers: Registers::default(),
+            vector,
+            error_code,
+            stack_frame: InterruptStackFrameValue {
+                instruction_pointer: VirtAddr::new(0),
+                code_segment: 0x8,
+                cpu_flags: 0x2,
+                stack_pointer: VirtAddr::new(0),
+                stack_segment: 0,
+            },
+        }
+    }
+
+    /// Raise software interrupt `int n` for a vector without error code
+    ///
+    /// Used for untriggerable vectors only: `int n` enters the stub of the
+    /// vector, the exception condition itself is never raised
+    macro_rules! software_interrupt {
+        ($vector:literal) => {
+            expect_exception($vector, || unsafe {
+                asm!(
+                    "lea r11, [rip + 2f]",
+                    concat!("int ", stringify!($vector)),
+                    "2:",
+                    out("r11") _,
+                )
+            })
+        };
+    }
+
+    #[test_case]
+    fn divide_error() {
+        expect_exception(0, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "div ecx",
+                "2:",
+                inout("eax") 1 => _,
+                inout("edx") 0 => _,
+                in("ecx") 0,
+                out("r11") _,
+            )
+        });
+    }
+
+    #[test_case]
+    fn debug() {
+        expect_exception(1, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "pushfq",
+                "or qword ptr [rsp], 0x100",
+                "popfq",
+                // traps after this instruction
+                "nop",
+                "2:",
+                out("r11") _,
+            )
+        });
+    }
+
+    #[test_case]
+    fn non_maskable_interrupt() {
+        // NMI sent to this CPU by its own local APIC, taken at whatever
+        // instruction runs when it arrives
+        let local_apic = apic::local_apic().expect("no local APIC to send the NMI");
+
+        VECTOR.store(NO_EXCEPTION, Ordering::SeqCst);
+        exceptions::set_exception_hook(Some(record));
+        local_apic.send_nmi(local_apic.id());
+        for _ in 0..1_000_000 {
+            if VECTOR.load(Ordering::SeqCst) != NO_EXCEPTION {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        exceptions::set_exception_hook(None);
+
+        assert_eq!(VECTOR.load(Ordering::SeqCst), 2);
+    }
+
+    #[test_case]
+    fn breakpoint() {
+        expect_exception(3, || unsafe {
+            asm!("lea r11, [rip + 2f]", "int3", "2:", out("r11") _)
+        });
+    }
+
+    #[test_case]
+    fn breakpoint_without_hook() {
+        // reported and resumed
+        x86_64::instructions::interrupts::int3();
+    }
+
+    #[test_case]
+    fn overflow() {
+        // untriggerable: `into` is invalid in 64-bit mode, only the entry
+        // stub is checked
+        software_interrupt!(4);
+    }
+
+    #[test_case]
+    fn bound_range_exceeded() {
+        // untriggerable: `bound` is invalid in 64-bit mode, only the entry
+        // stub is checked
+        software_interrupt!(5);
+    }
+
+    #[test_case]
+    fn invalid_opcode() {
+        expect_exception(6, || unsafe {
+            asm!("lea r11, [rip + 2f]", "ud2", "2:", out("r11") _)
+        });
+    }
+
+    #[test_case]
+    fn device_not_available() {
+        // x87 instructions fault while CR0.TS is set
+        let cr0 = Cr0::read();
+        unsafe { Cr0::write((cr0 | Cr0Flags::TASK_SWITCHED) - Cr0Flags::EMULATE_COPROCESSOR) };
+        expect_exception(7, || unsafe {
+            asm!("lea r11, [rip + 2f]", "fnop", "2:", out("r11") _)
+        });
+        unsafe { Cr0::write(cr0) };
+    }
+
+    #[test_case]
+    fn double_fault() {
+        // same as a stack overflow: the push faults on an unmapped page and
+        // the page fault can't be pushed on that stack either. The double
+        // fault runs on its IST stack, the stack pointer is restored from
+        // `r12` after it
+        let stack = VMM_END - 4096 / 2;
+        let error_code = without_interrupts(|| {
+            expect_exception(8, || unsafe {
+                asm!(
+                    "mov r12, rsp",
+                    "lea r11, [rip + 2f]",
+                    "mov rsp, {stack}",
+                    "push rax",
+                    "2:",
+                    "mov rsp, r12",
+                    stack = in(reg) stack,
+                    out("r11") _,
+                    out("r12") _,
+                )
+            })
+        });
+        assert_eq!(error_code, 0);
+    }
+
+    #[test_case]
+    fn coprocessor_segment_overrun() {
+        // untriggerable: not raised by CPUs since the 486, only the entry
+        // stub is checked
+        software_interrupt!(9);
+    }
+
+    #[test_case]
+    fn invalid_tss() {
+        // copy of the GDT with a TSS too short for its IST entries appended
+        static mut GDT: [u64; 16] = [0; 16];
+        // IST entries start at byte 36
+        static SHORT_TSS: [u8; 36] = [0; 36];
+
+        let gdt = sgdt();
+        let entries = (gdt.limit as usize + 1) / 8;
+        assert!(entries + 2 <= 16);
+        let tss: u16;
+        unsafe {
+            asm!("str {0:x}", out(reg) tss);
+            let current = core::slice::from_raw_parts(gdt.base.as_ptr::<u64>(), entries);
+            GDT[..entries].copy_from_slice(current);
+            // available 64-bit TSS, limit 35
+            let base = SHORT_TSS.as_ptr() as u64;
+            GDT[entries] = 35 | (base & 0xff_ffff) << 16 | 0x89 << 40 | (base >> 24 & 0xff) << 56;
+            GDT[entries + 1] = base >> 32;
+        }
+        let test_gdt = DescriptorTablePointer {
+            limit: ((entries + 2) * 8 - 1) as u16,
+            base: VirtAddr::from_ptr(unsafe { core::ptr::addr_of!(GDT) }),
+        };
+
+        let error_code = without_interrupts(|| unsafe {
+            lgdt(&test_gdt);
+            load_tss(SegmentSelector((entries << 3) as u16));
+            // the double fault gate switches to an IST stack, read from
+            // the TSS
+            let error_code = expect_exception(
+                10,
+                || asm!("lea r11, [rip + 2f]", "int 8", "2:", out("r11") _),
+            );
+            lgdt(&gdt);
+            // ltr faults on a busy TSS, mark the kernel TSS available again
+            *gdt.base.as_mut_ptr::<u64>().add(tss as usize >> 3) &= !(1 << 41);
+            load_tss(SegmentSelector(tss));
+            error_code
+        });
+
+        let selector = SelectorErrorCode::new_truncate(error_code);
+        assert_eq!(selector.descriptor_table(), DescriptorTable::Gdt);
+        assert_eq!(selector.index(), entries as u64);
+    }
+
+    #[test_case]
+    fn invalid_tss_report() {
+        let mut frame = synthesized_frame(10, 0b110 | 1);
+        expect_exception(10, || exceptions::dispatch(&mut frame));
+
+        let selector = match frame.decode_error_code() {
+            ErrorCode::Selector(selector) => selector,
+            other => panic!("not a selector error code: {:?}", other),
+        };
+        assert!(selector.external());
+        assert_eq!(selector.descriptor_table(), DescriptorTable::Idt);
+        assert_eq!(selector.index(), 0);
+    }
+
+    #[test_case]
+    fn segment_not_present() {
+        // copy of the GDT with a not present data segment appended
+        static mut GDT: [u64; 16] = [0; 16];
+
+        let gdt = sgdt();
+        let entries = (gdt.limit as usize + 1) / 8;
+        assert!(entries < 16);
+        unsafe {
+            let current = core::slice::from_raw_parts(gdt.base.as_ptr::<u64>(), entries);
+            GDT[..entries].copy_from_slice(current);
+            // writable data segment with the present bit clear
+            GDT[entries] = 0x12 << 40;
+        }
+        let test_gdt = DescriptorTablePointer {
+            limit: ((entries + 1) * 8 - 1) as u16,
+            base: VirtAddr::from_ptr(unsafe { core::ptr::addr_of!(GDT) }),
+        };
+
+        let error_code = without_interrupts(|| unsafe {
+            lgdt(&test_gdt);
+            let error_code = expect_exception(11, || {
+                asm!(
+                    "lea r11, [rip + 2f]",
+                    "mov ds, {0:x}",
+                    "2:",
+                    in(reg) (entries << 3) as u16,
+                    out("r11") _,
+                )
+            });
+            lgdt(&gdt);
+            error_code
+        });
+
+        let selector = SelectorErrorCode::new_truncate(error_code);
+        assert!(!selector.external());
+        assert_eq!(selector.descriptor_table(), DescriptorTable::Gdt);
+        assert_eq!(selector.index(), entries as u64);
+    }
+
+    #[test_case]
+    fn stack_segment_fault() {
+        let error_code = expect_exception(12, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "push rbp",
+                "mov rbp, rax",
+                // non-canonical address relative to rbp
+                "mov rax, [rbp]",
+                "2:",
+                "pop rbp",
+                inout("rax") NON_CANONICAL => _,
+                out("r11") _,
+            )
+        });
+        assert_eq!(error_code, 0);
+    }
+
+    #[test_case]
+    fn general_protection_fault() {
+        let error_code = expect_exception(13, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "mov rax, [rcx]",
+                "2:",
+                in("rcx") NON_CANONICAL,
+                out("rax") _,
+                out("r11") _,
+            )
+        });
+        assert_eq!(error_code, 0);
+    }
+
+    #[test_case]
+    fn general_protection_fault_selector() {
+        // selector beyond the end of the GDT
+        let error_code = expect_exception(13, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "mov ds, {0:x}",
+                "2:",
+                in(reg) 0xfff8u16,
+                out("r11") _,
+            )
+        });
+
+        let frame = synthesized_frame(13, error_code);
+        assert_eq!(
+            frame.decode_error_code(),
+            ErrorCode::Selector(SelectorErrorCode::new_truncate(0xfff8))
+        );
+    }
+
+    #[test_case]
+    fn page_fault() {
+        // end of the VMM range is never reserved
+        let addr = VMM_END - 4096;
+        let error_code = expect_exception(14, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "mov rax, [rcx]",
+                "2:",
+                in("rcx") addr,
+                out("rax") _,
+                out("r11") _,
+            )
+        });
+
+        assert_eq!(FAULT_ADDR.load(Ordering::SeqCst), addr);
+        let frame = synthesized_frame(14, error_code);
+        assert_eq!(
+            frame.decode_error_code(),
+            ErrorCode::PageFault(PageFaultErrorCode::empty())
+        );
+    }
+
+    #[test_case]
+    fn x87_floating_point() {
+        // unmasked x87 exceptions raise #MF with CR0.NE set, on the next
+        // waiting instruction
+        let cr0 = Cr0::read();
+        let fpu = Cr0Flags::TASK_SWITCHED | Cr0Flags::EMULATE_COPROCESSOR;
+        unsafe { Cr0::write((cr0 | Cr0Flags::NUMERIC_ERROR) - fpu) };
+        // default control word with divide by zero unmasked
+        let control: u16 = 0x037b;
+        expect_exception(16, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "fninit",
+                "fldcw [{control}]",
+                "fldz",
+                "fld1",
+                "fdiv st, st(1)",
+                "fwait",
+                "2:",
+                "fninit",
+                control = in(reg) &control,
+                out("r11") _,
+            )
+        });
+        unsafe { Cr0::write(cr0) };
+    }
+
+    #[test_case]
+    fn alignment_check() {
+        // raised at CPL 3 only, an unaligned load runs in ring 3 with CR0.AM
+        // and RFLAGS.AC set
+        const USER_CODE: u64 = 0x40_0000;
+        const USER_STACK: u64 = 0x40_1000;
+        // mov rax, [rsp + 1]; ud2
+        const CODE: [u8; 7] = [0x48, 0x8b, 0x44, 0x24, 0x01, 0x0f, 0x0b];
+
+        let mut space = AddressSpace::new().unwrap();
+        let code = space
+            .map_user(
+                Page::containing_address(VirtAddr::new(USER_CODE)),
+                PageTableFlags::empty(),
+            )
+            .unwrap();
+        space
+            .map_user(
+                Page::containing_address(VirtAddr::new(USER_STACK)),
+                PageTableFlags::WRITABLE,
+            )
+            .unwrap();
+        let code_ptr = (memory::phys_mem_offset() + code.start_address().as_u64()).as_mut_ptr();
+        unsafe { core::ptr::copy_nonoverlapping(CODE.as_ptr(), code_ptr, CODE.len()) };
+
+        let cr0 = Cr0::read();
+
+        VECTOR.store(NO_EXCEPTION, Ordering::SeqCst);
+        without_interrupts(|| unsafe {
+            space.switch();
+            Cr0::write(cr0 | Cr0Flags::ALIGNMENT_MASK);
+            exceptions::set_exception_hook(Some(exit_user));
+            usermode::run(
+                VirtAddr::new(USER_CODE),
+                VirtAddr::new(USER_STACK + 0x800),
+                RFlags::ALIGNMENT_CHECK,
+            );
+            exceptions::set_exception_hook(None);
+            Cr0::write(cr0);
+            AddressSpace::switch_to_kernel();
+        });
+
+        assert_eq!(VECTOR.load(Ordering::SeqCst), 17);
+        assert_eq!(ERROR_CODE.load(Ordering::SeqCst), 0);
+    }
+
+    #[test_case]
+    fn machine_check() {
+        // untriggerable: raised by hardware errors, only the entry stub is
+        // checked
+        software_interrupt!(18);
+    }
+
+    #[test_case]
+    fn simd_floating_point() {
+        // unmasked SSE exceptions raise #XM with CR4.OSXMMEXCPT set
+        let cr4 = Cr4::read();
+        unsafe { Cr4::write(cr4 | Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE) };
+        // default MXCSR with divide by zero unmasked
+        let unmasked: u32 = 0x1f80 & !(1 << 9);
+        let default: u32 = 0x1f80;
+        expect_exception(19, || unsafe {
+            asm!(
+                "lea r11, [rip + 2f]",
+                "ldmxcsr [{unmasked}]",
+                "mov {tmp:e}, 0x3f800000",
+                "movd xmm1, {tmp:e}",
+                "xorps xmm0, xmm0",
+                "divss xmm1, xmm0",
+                "2:",
+                "ldmxcsr [{default}]",
+                unmasked = in(reg) &unmasked,
+                default = in(reg) &default,
+                tmp = out(reg) _,
+                out("r11") _,
+            )
+        });
+        unsafe { Cr4::write(cr4) };
+    }
+
+    #[test_case]
+    fn virtualization() {
+        // untriggerable: raised in guests using EPT violations, only the
+        // entry stub is checked
+        software_interrupt!(20);
+    }
+
+    #[test_case]
+    fn control_protection() {
+        // untriggerable: needs CET shadow stacks, only the IDT entry and
+        // the dispatch are checked
+        let idt = sidt();
+        let entry = unsafe { &*(idt.base.as_ptr::<[u64; 2]>()).add(21) };
+        let handler = (entry[0] & 0xffff)
+            | ((entry[0] >> 32) & 0xffff_0000)
+            | ((entry[1] & 0xffff_ffff) << 32);
+        assert_eq!(handler, exceptions::stub_addr(21).as_u64());
+
+        let mut frame = synthesized_frame(21, 3);
+        expect_exception(21, || exceptions::dispatch(&mut frame));
+        assert_eq!(frame.decode_error_code(), ErrorCode::Other(3));
+    }
+
+    #[test_case]
+    fn hypervisor_injection() {
+        // untriggerable: raised in SEV-SNP guests, only the entry stub is
+        // checked
+        software_interrupt!(28);
+    }
+
+    #[test_case]
+    fn vmm_communication() {
+        // untriggerable: raised in SEV-ES guests, only the dispatch is
+        // checked
+        let mut frame = synthesized_frame(29, 0x7b);
+        expect_exception(29, || exceptions::dispatch(&mut frame));
+        assert_eq!(frame.decode_error_code(), ErrorCode::Other(0x7b));
+    }
+
+    #[test_case]
+    fn security() {
+        // untriggerable: raised by SVM security events, only the dispatch
+        // is checked
+        let mut frame = synthesized_frame(30, 1);
+        expect_exception(30, || exceptions::dispatch(&mut frame));
+        assert_eq!(frame.decode_error_code(), ErrorCode::Other(1));
+    }
+
+    #[test_case]
+    fn registers_are_saved() {
+        static RBX: AtomicU64 = AtomicU64::new(0);
+
+        fn record_rbx(frame: &mut ExceptionFrame) -> bool {
+            RBX.store(frame.registers.rbx, Ordering::SeqCst);
+            resume(frame)
+        }
+
+        VECTOR.store(NO_EXCEPTION, Ordering::SeqCst);
+        exceptions::set_exception_hook(Some(record_rbx));
+        unsafe {
+            asm!(
+                "push rbx",
+                "mov rbx, {0}",
+                "lea r11, [rip + 2f]",
+                "ud2",
+                "2:",
+                "pop rbx",
+                in(reg) 0x1234_5678u64,
+                out("r11") _,
+            )
+        }
+        exceptions::set_exception_hook(None);
+
+        assert_eq!(RBX.load(Ordering::SeqCst), 0x1234_5678);
+    }
+
+    #[test_case]
+    fn report_names_exception() {
+        let frame = synthesized_frame(13, 3 << 3);
+        let report = format!("{}", frame);
+        assert!(report.contains("GENERAL PROTECTION FAULT"));
+        assert!(report.contains("Gdt selector index 3"));
+        assert!(report.contains("RAX="));
+    }
+}
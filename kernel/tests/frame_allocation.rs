@@ -0,0 +1,198 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use oros_kernel::memory::{bitmap::BitmapFrameAllocator, buddy::BuddyFrameAllocator};
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+static BUDDY_ALLOCATOR: Mutex<BuddyFrameAllocator> = Mutex::new(BuddyFrameAllocator::new());
+
+// frames handed to the buddy allocator, one max order block
+const BUDDY_FRAMES: usize = 1 << oros_kernel::memory::buddy::MAX_ORDER;
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+
+    // build allocator directly, without running init::init, so the test
+    // owns every usable frame
+    let mut allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_regions, phys_mem_offset) };
+
+    // give the buddy allocator a pool of its own
+    let pool = allocator
+        .allocate_contiguous(BUDDY_FRAMES, BUDDY_FRAMES)
+        .expect("no contiguous pool for buddy allocator");
+    unsafe {
+        let mut buddy = BUDDY_ALLOCATOR.lock();
+        buddy.init(phys_mem_offset);
+        buddy.add_region(pool, BUDDY_FRAMES);
+    }
+
+    FRAME_ALLOCATOR.lock().replace(allocator);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oros_kernel::memory::buddy::MAX_ORDER;
+    use x86_64::structures::paging::{
+        FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB,
+    };
+    use x86_64::PhysAddr;
+
+    #[test_case]
+    fn allocate_updates_counts() {
+        let mut guard = FRAME_ALLOCATOR.lock();
+        let allocator = guard.as_mut().unwrap();
+
+        let free = allocator.free_frames();
+        let frame = allocator.allocate_frame().expect("no free frame");
+
+        assert!(allocator.is_used(frame));
+        assert_eq!(allocator.free_frames(), free - 1);
+
+        unsafe { allocator.deallocate_frame(frame) };
+        assert!(!allocator.is_used(frame));
+        assert_eq!(allocator.free_frames(), free);
+    }
+
+    #[test_case]
+    fn freed_frame_is_reused() {
+        let mut guard = FRAME_ALLOCATOR.lock();
+        let allocator = guard.as_mut().unwrap();
+
+        let frame = allocator.allocate_frame().unwrap();
+        unsafe { allocator.deallocate_frame(frame) };
+
+        assert_eq!(allocator.allocate_frame(), Some(frame));
+        unsafe { allocator.deallocate_frame(frame) };
+    }
+
+    #[test_case]
+    fn frames_are_unique() {
+        let mut guard = FRAME_ALLOCATOR.lock();
+        let allocator = guard.as_mut().unwrap();
+
+        let first = allocator.allocate_frame().unwrap();
+        let second = allocator.allocate_frame().unwrap();
+        assert_ne!(first, second);
+
+        unsafe {
+            allocator.deallocate_frame(first);
+            allocator.deallocate_frame(second);
+        }
+    }
+
+    #[test_case]
+    fn only_usable_frames_can_be_freed() {
+        let mut guard = FRAME_ALLOCATOR.lock();
+        let allocator = guard.as_mut().unwrap();
+
+        let frame = allocator.allocate_frame().unwrap();
+        assert!(allocator.is_usable(frame));
+        unsafe { allocator.deallocate_frame(frame) };
+
+        // frames past the end of the bitmap are never usable
+        let beyond = PhysFrame::containing_address(PhysAddr::new(
+            allocator.frame_count() as u64 * Size4KiB::SIZE,
+        ));
+        assert!(!allocator.is_usable(beyond));
+    }
+
+    #[test_case]
+    fn contiguous_frames_are_free() {
+        let mut guard = FRAME_ALLOCATOR.lock();
+        let allocator = guard.as_mut().unwrap();
+
+        let used = allocator.used_frames();
+        let start = allocator.allocate_contiguous(8, 8).unwrap();
+
+        assert_eq!(start.start_address().as_u64() % (8 * 4096), 0);
+        assert_eq!(allocator.used_frames(), used + 8);
+
+        for frame in PhysFrame::range(start, start + 8) {
+            unsafe { allocator.deallocate_frame(frame) };
+        }
+        assert_eq!(allocator.used_frames(), used);
+    }
+
+    #[test_case]
+    fn buddy_split_and_coalesce() {
+        let mut buddy = BUDDY_ALLOCATOR.lock();
+
+        let first = buddy.allocate(0).unwrap();
+        assert_eq!(buddy.free_blocks(MAX_ORDER), 0);
+        assert_eq!(buddy.free_frames(), BUDDY_FRAMES - 1);
+
+        // every order below the max block holds one split off half
+        for order in 0..MAX_ORDER {
+            assert_eq!(buddy.free_blocks(order), 1);
+        }
+
+        unsafe { buddy.deallocate(first, 0) };
+        assert_eq!(buddy.free_blocks(MAX_ORDER), 1);
+        assert_eq!(buddy.free_frames(), BUDDY_FRAMES);
+    }
+
+    #[test_case]
+    fn buddy_blocks_are_aligned() {
+        let mut buddy = BUDDY_ALLOCATOR.lock();
+
+        let small = buddy.allocate(0).unwrap();
+        let block = buddy.allocate(3).unwrap();
+
+        assert_eq!(block.as_u64() % (8 * 4096), 0);
+        assert_ne!(small, block);
+
+        unsafe {
+            buddy.deallocate(block, 3);
+            buddy.deallocate(small, 0);
+        }
+        assert_eq!(buddy.free_blocks(MAX_ORDER), 1);
+    }
+
+    #[test_case]
+    fn buddy_free_blocks_are_known() {
+        let mut buddy = BUDDY_ALLOCATOR.lock();
+
+        let block = buddy.allocate(2).unwrap();
+        assert!(!buddy.is_free(block, 2));
+        assert!(!buddy.is_free(block + 4096u64, 0));
+
+        unsafe { buddy.deallocate(block, 2) };
+        // merged back into the max order block
+        assert!(buddy.is_free(block, 2));
+        assert!(buddy.is_free(block + 4096u64, 0));
+        assert!(buddy.is_free(block, MAX_ORDER));
+    }
+
+    #[test_case]
+    fn buddy_huge_page() {
+        let mut buddy = BUDDY_ALLOCATOR.lock();
+
+        let frame: PhysFrame<Size2MiB> = buddy.allocate_frame().unwrap();
+        assert_eq!(frame.start_address().as_u64() % Size2MiB::SIZE, 0);
+
+        unsafe { buddy.deallocate_frame(frame) };
+        assert_eq!(buddy.free_frames(), BUDDY_FRAMES);
+    }
+}
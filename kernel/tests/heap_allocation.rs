@@ -1,20 +1,20 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
-#![test_runner(oros::test_utils::test_runner)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 
-use bootloader::{entry_point, BootInfo};
+use bootloader_api::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 
-use oros::hlt_loop;
-use oros::init;
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
 
-entry_point!(test_kernel_main);
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
 
-fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
     // get the physical memory offset
 
     // initialize RAM
@@ -27,14 +27,14 @@ fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    oros::test_utils::panic_handler(info)
+    oros_kernel::test_utils::panic_handler(info)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloc::{boxed::Box, vec::Vec};
-    use oros::memory::allocator::HEAP_SIZE;
+    use alloc::{boxed::Box, vec, vec::Vec};
+    use oros_kernel::memory::allocator::HEAP_SIZE;
 
     #[test_case]
     fn large_vec() {
@@ -76,4 +76,326 @@ mod tests {
         assert_eq!(*heap_val_1, 41);
         assert_eq!(*heap_val_2, 7);
     }
+
+    #[test_case]
+    fn heap_grows_past_initial_size() {
+        // more than the initially mapped heap, all alive at once
+        let vec = vec![1u8; HEAP_SIZE * 2];
+        assert_eq!(
+            vec.iter().map(|&x| x as usize).sum::<usize>(),
+            HEAP_SIZE * 2
+        );
+    }
+
+    // heap-debug poisons every allocated byte, which touches all pages
+    #[cfg(not(feature = "heap-debug"))]
+    #[test_case]
+    fn heap_growth_is_backed_on_touch() {
+        use oros_kernel::memory;
+        use x86_64::{
+            structures::paging::{mapper::TranslateResult, PageTableFlags, Translate},
+            VirtAddr,
+        };
+
+        const SIZE: usize = 1024 * 1024;
+        let present = |addr: VirtAddr| match memory::mapper().translate(addr) {
+            TranslateResult::Mapped { flags, .. } => flags.contains(PageTableFlags::PRESENT),
+            _ => false,
+        };
+        let used = memory::frame_allocator().used_frames();
+
+        // far past the initial heap, the heap grows without being touched
+        let mut vec = Vec::<u8>::with_capacity(SIZE);
+        let middle = VirtAddr::from_ptr(vec.as_ptr()) + SIZE / 2;
+        assert!(memory::HEAP_GROWTH.try_get().unwrap().contains(middle));
+        assert!(!present(middle));
+
+        // frames of the growth are taken when growing
+        let grown = memory::frame_allocator().used_frames();
+        assert!(grown > used);
+
+        // and back the pages once touched
+        unsafe { vec.as_mut_ptr().add(SIZE / 2).write_volatile(1) };
+        assert!(present(middle));
+        assert_eq!(memory::frame_allocator().used_frames(), grown);
+    }
+
+    // heap-debug poisons every allocated byte, which touches all pages
+    #[cfg(not(feature = "heap-debug"))]
+    #[test_case]
+    fn heap_growth_is_backed_while_mapper_locked() {
+        use oros_kernel::memory;
+
+        const SIZE: usize = 1024 * 1024;
+        let mut vec = Vec::<u8>::with_capacity(SIZE);
+
+        // the fault on the fresh page takes neither lock
+        let mapper = memory::mapper();
+        let frame_allocator = memory::frame_allocator();
+        unsafe { vec.as_mut_ptr().add(SIZE - 1).write_volatile(1) };
+        drop((mapper, frame_allocator));
+
+        assert_eq!(unsafe { vec.as_ptr().add(SIZE - 1).read_volatile() }, 1);
+    }
+
+    // heap-debug adds red zones and a header to every allocation
+    #[cfg(not(feature = "heap-debug"))]
+    #[test_case]
+    fn stats_track_allocations() {
+        use oros_kernel::memory;
+
+        let before = memory::stats().heap.counters;
+
+        let x = Box::new([0u8; 64]);
+        let during = memory::stats().heap.counters;
+        assert_eq!(during.allocations, before.allocations + 1);
+        assert_eq!(during.bytes_in_use, before.bytes_in_use + 64);
+
+        drop(x);
+        let after = memory::stats().heap.counters;
+        assert_eq!(after.frees, before.frees + 1);
+        assert_eq!(after.bytes_in_use, before.bytes_in_use);
+        assert!(after.peak_bytes_in_use >= during.bytes_in_use);
+    }
+
+    #[test_case]
+    fn stats_report_free_memory() {
+        use oros_kernel::memory;
+
+        let heap = memory::stats().heap;
+        assert!(heap.free_bytes <= heap.heap_size);
+
+        // the external crate keeps its hole list private, only totals are known
+        if cfg!(feature = "alloc-external-linked-list") {
+            assert_eq!(heap.free_regions, None);
+        } else {
+            let regions = heap.free_regions.expect("free regions not walked");
+            let largest = heap.largest_free_region.unwrap();
+
+            assert!(regions > 0);
+            assert!(largest > 0 && largest <= heap.free_bytes);
+            assert!(heap.fragmentation().is_some());
+        }
+    }
+
+    // bump allocator frees nothing until the heap is empty
+    #[cfg(not(any(feature = "alloc-bump", feature = "alloc-external-linked-list")))]
+    #[test_case]
+    fn stats_report_fragmentation() {
+        use oros_kernel::memory;
+
+        // larger than any size class, served by the linked list heap
+        const BLOCK: usize = 4096;
+        let mut blocks: Vec<Option<Box<[u8]>>> = (0..8)
+            .map(|_| Some(vec![0u8; BLOCK].into_boxed_slice()))
+            .collect();
+        let before = memory::stats().heap;
+
+        // free every other block, leaving holes between the live ones
+        for block in blocks.iter_mut().step_by(2) {
+            block.take();
+        }
+        let after = memory::stats().heap;
+
+        assert!(after.free_bytes >= before.free_bytes + 4 * BLOCK);
+        assert!(after.free_regions.unwrap() >= before.free_regions.unwrap() + 4);
+        assert!(after.largest_free_region.unwrap() < after.free_bytes);
+        assert!(after.fragmentation().unwrap() > 0);
+    }
+}
+
+#[cfg(all(test, feature = "heap-debug"))]
+mod debug_tests {
+    use alloc::alloc::{alloc, dealloc, Layout};
+    use oros_kernel::memory::debug::{self, HeapCorruption, HeapError};
+    use spin::Mutex;
+
+    static LAST_CORRUPTION: Mutex<Option<HeapCorruption>> = Mutex::new(None);
+
+    fn record(corruption: &HeapCorruption) {
+        LAST_CORRUPTION.lock().replace(*corruption);
+    }
+
+    /// Run `f` with corruption recorded instead of panicking
+    ///
+    /// Returns the last corruption detected
+    fn detect(f: impl FnOnce()) -> Option<HeapCorruption> {
+        LAST_CORRUPTION.lock().take();
+        let previous = debug::set_corruption_handler(record);
+        f();
+        debug::set_corruption_handler(previous);
+        LAST_CORRUPTION.lock().take()
+    }
+
+    #[test_case]
+    fn valid_free_is_not_reported() {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let corruption = detect(|| unsafe {
+            let ptr = alloc(layout);
+            ptr.write_bytes(1, layout.size());
+            dealloc(ptr, layout);
+        });
+        assert_eq!(corruption, None);
+    }
+
+    #[test_case]
+    fn double_free() {
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        let corruption = detect(|| unsafe {
+            let ptr = alloc(layout);
+            dealloc(ptr, layout);
+            dealloc(ptr, layout);
+        });
+        assert_eq!(corruption.map(|c| c.error), Some(HeapError::DoubleFree));
+    }
+
+    #[test_case]
+    fn mismatched_layout() {
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        let wrong = Layout::from_size_align(48, 8).unwrap();
+        let mut ptr = core::ptr::null_mut();
+
+        let corruption = detect(|| unsafe {
+            ptr = alloc(layout);
+            dealloc(ptr, wrong);
+        });
+        assert_eq!(
+            corruption.map(|c| c.error),
+            Some(HeapError::LayoutMismatch { allocated: layout })
+        );
+
+        // mismatched free is skipped, free for real
+        unsafe { dealloc(ptr, layout) };
+    }
+
+    #[test_case]
+    fn overflow() {
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let corruption = detect(|| unsafe {
+            let ptr = alloc(layout);
+            ptr.add(layout.size()).write(0);
+            dealloc(ptr, layout);
+        });
+        assert_eq!(corruption.map(|c| c.error), Some(HeapError::Overflow));
+    }
+
+    #[test_case]
+    fn underflow() {
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let corruption = detect(|| unsafe {
+            let ptr = alloc(layout);
+            ptr.sub(1).write(0);
+            dealloc(ptr, layout);
+        });
+        assert_eq!(corruption.map(|c| c.error), Some(HeapError::Underflow));
+    }
+
+    #[test_case]
+    fn freed_memory_is_poisoned() {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        unsafe {
+            let ptr = alloc(layout);
+            ptr.write_bytes(1, layout.size());
+            dealloc(ptr, layout);
+
+            // block is back on a free list, user bytes are untouched by it
+            let freed = core::slice::from_raw_parts(ptr, layout.size());
+            assert!(freed.iter().all(|&b| b == debug::POISON_BYTE));
+        }
+    }
+
+    #[cfg(feature = "alloc-fixed")]
+    #[test_case]
+    fn use_after_free() {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let corruption = detect(|| unsafe {
+            let ptr = alloc(layout);
+            dealloc(ptr, layout);
+
+            // write through the dangling pointer, the block is reused next
+            ptr.add(8).write(1);
+            let reused = alloc(layout);
+            assert_eq!(reused, ptr);
+            dealloc(reused, layout);
+        });
+
+        let corruption = corruption.expect("use after free not detected");
+        assert_eq!(corruption.error, HeapError::UseAfterFree);
+        assert_eq!(corruption.layout, layout);
+    }
+
+    #[cfg(feature = "alloc-fixed")]
+    #[test_case]
+    fn corrupted_free_list() {
+        let layout = Layout::from_size_align(8, 8).unwrap();
+        let corruption = detect(|| unsafe {
+            let ptr = alloc(layout);
+            let block = debug::block_start(ptr);
+            dealloc(ptr, layout);
+
+            // overwrite next pointer of free list node with garbage
+            (block as *mut usize).write(0xdead_beef);
+            let ptr = alloc(layout);
+            assert!(!ptr.is_null());
+            dealloc(ptr, layout);
+        });
+        assert_eq!(
+            corruption.map(|c| c.error),
+            Some(HeapError::CorruptedFreeList)
+        );
+    }
+
+    #[cfg(feature = "alloc-linked-list")]
+    #[test_case]
+    fn corrupted_linked_list_node() {
+        let layout = Layout::from_size_align(8, 8).unwrap();
+        let node_size = unsafe {
+            let ptr = alloc(layout);
+            let block = debug::block_start(ptr);
+            dealloc(ptr, layout);
+            block as *mut usize
+        };
+
+        // freed block is the head of the free list, its size runs past the
+        // end of the heap
+        let corruption = detect(|| unsafe {
+            let size = node_size.read();
+            node_size.write(usize::MAX / 2);
+            let ptr = alloc(layout);
+            assert!(!ptr.is_null());
+            node_size.write(size);
+            dealloc(ptr, layout);
+        });
+        assert_eq!(
+            corruption.map(|c| c.error),
+            Some(HeapError::CorruptedFreeList)
+        );
+    }
+
+    #[test_case]
+    fn corrupted_slab_free_list() {
+        use oros_kernel::memory::slab::SlabCache;
+
+        let mut cache = SlabCache::new("test-corruption", 64, 8);
+        let object = cache.alloc();
+        unsafe { cache.dealloc(object) };
+
+        // freed object is the head of the free list of the only slab
+        let next = object as *mut usize;
+        let corruption = detect(|| unsafe {
+            let saved = next.read();
+            next.write(0xdead_beef);
+            assert!(cache.alloc().is_null());
+            next.write(saved);
+        });
+        assert_eq!(
+            corruption.map(|c| c.error),
+            Some(HeapError::CorruptedFreeList)
+        );
+
+        let object = cache.alloc();
+        assert!(!object.is_null());
+        unsafe { cache.dealloc(object) };
+        cache.shrink();
+    }
 }
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use bootloader_api::{entry_point, BootInfo};
+use core::{
+    arch::asm,
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+};
+
+use oros_kernel::{
+    hlt_loop, init,
+    memory::stack::KernelStack,
+    port::serial::{exit_qemu, QemuExitCode},
+    serial_print, serial_println, BOOTLOADER_CONFIG,
+};
+
+/// Start address of the guard page below the overflowing stack
+static GUARD_PAGE: AtomicU64 = AtomicU64::new(0);
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    serial_print!("kernel_stack_overflow::allocated_stack_overflow...\t");
+
+    // double fault stack is allocated with a guard page during init
+    init::init(boot_info);
+
+    // no hardware interrupts without their handlers in the test IDT
+    x86_64::instructions::interrupts::disable();
+    init_test_idt();
+
+    let stack = KernelStack::allocate(2).expect("failed to allocate stack");
+    GUARD_PAGE.store(
+        stack.guard_page().start_address().as_u64(),
+        Ordering::SeqCst,
+    );
+
+    // trigger a stack overflow on the allocated stack
+    unsafe {
+        asm!(
+            "mov rsp, {top}",
+            "call {entry}",
+            top = in(reg) stack.top().as_u64(),
+            entry = sym overflow_entry,
+            options(noreturn)
+        );
+    }
+}
+
+extern "C" fn overflow_entry() -> ! {
+    stack_overflow();
+    panic!("Execution continued after stack overflow");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow(); // for each recursion, the return address is pushed
+    volatile::Volatile::new(0).read(); // prevent tail recursion optimizations
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(oros_kernel::interrupts::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_double_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    // fault must come from the guard page, not from some other memory
+    let fault_addr = Cr2::read().as_u64();
+    let guard_page = GUARD_PAGE.load(Ordering::SeqCst);
+
+    if (guard_page..guard_page + 4096).contains(&fault_addr) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        serial_println!("Error: fault at {:#x} outside of guard page", fault_addr);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    hlt_loop()
+}
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::memory::{
+        self,
+        mmio::MMIO_FLAGS,
+        paging::PageTableWalker,
+        vmm::{RegionKind, VMM},
+    };
+    use x86_64::structures::paging::{
+        FrameAllocator, FrameDeallocator, PageTableFlags, PhysFrame, Translate,
+    };
+
+    /// Run `f` with a frame of RAM standing in for device memory
+    fn with_frame(f: impl FnOnce(PhysFrame)) {
+        let frame = memory::frame_allocator().allocate_frame().unwrap();
+        f(frame);
+        unsafe { memory::frame_allocator().deallocate_frame(frame) };
+    }
+
+    #[test_case]
+    fn registers_access_device_memory() {
+        with_frame(|frame| {
+            let mmio = memory::map_mmio(frame.start_address(), 0x100).unwrap();
+            mmio.write32(0x10, 0xdead_beef);
+            assert_eq!(mmio.read32(0x10), 0xdead_beef);
+
+            // same memory through the physical memory mapping
+            let phys_ptr: *const u32 =
+                (memory::phys_mem_offset() + frame.start_address().as_u64() + 0x10u64).as_ptr();
+            assert_eq!(unsafe { phys_ptr.read_volatile() }, 0xdead_beef);
+        });
+    }
+
+    #[test_case]
+    fn mapped_uncached() {
+        with_frame(|frame| {
+            let mmio = memory::map_mmio(frame.start_address(), 4).unwrap();
+
+            let mut mapper = memory::mapper();
+            let phys_mem_offset = mapper.phys_offset();
+            let walker = unsafe { PageTableWalker::new(mapper.level_4_table(), phys_mem_offset) };
+            let range = walker
+                .ranges()
+                .find(|range| range.contains(mmio.base()))
+                .unwrap();
+
+            assert!(range.flags.contains(MMIO_FLAGS));
+            assert!(range.flags.contains(PageTableFlags::NO_CACHE));
+            drop(mapper);
+
+            let region = *VMM.lock().find(mmio.base()).unwrap();
+            assert_eq!(region.kind, RegionKind::Mmio);
+        });
+    }
+
+    #[test_case]
+    fn unaligned_physical_address() {
+        with_frame(|frame| {
+            let phys = frame.start_address() + 0x24u64;
+            let mmio = memory::map_mmio(phys, 8).unwrap();
+
+            assert_eq!(mmio.base().as_u64() % 4096, 0x24);
+            assert_eq!(memory::mapper().translate_addr(mmio.base()), Some(phys));
+        });
+    }
+
+    #[test_case]
+    fn unmapped_on_drop() {
+        with_frame(|frame| {
+            let mmio = memory::map_mmio(frame.start_address(), 4096).unwrap();
+            let base = mmio.base();
+            drop(mmio);
+
+            assert!(memory::mapper().translate_addr(base).is_none());
+            assert!(VMM.lock().find(base).is_none());
+        });
+    }
+}
@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::memory::{
+        self,
+        allocator::HEAP_START,
+        paging::{self, MappedRange, MappedRanges, PageTableWalker},
+    };
+    use x86_64::{
+        structures::paging::{PageTableFlags, Translate},
+        VirtAddr,
+    };
+
+    /// Run `f` on the coalesced ranges of the active page tables
+    fn with_ranges<R>(f: impl FnOnce(MappedRanges) -> R) -> R {
+        let mut mapper = memory::mapper();
+        let phys_mem_offset = mapper.phys_offset();
+        let walker = unsafe { PageTableWalker::new(mapper.level_4_table(), phys_mem_offset) };
+        f(walker.ranges())
+    }
+
+    #[test_case]
+    fn ranges_are_sorted_and_disjoint() {
+        with_ranges(|ranges| {
+            let mut previous: Option<MappedRange> = None;
+            for range in ranges {
+                assert!(range.size > 0);
+                if let Some(previous) = previous {
+                    assert!(previous.start < range.start);
+                    assert!(previous.end() <= range.start);
+                }
+                previous = Some(range);
+            }
+            assert!(previous.is_some());
+        });
+    }
+
+    #[test_case]
+    fn heap_is_mapped_writable() {
+        let heap_start = VirtAddr::new(HEAP_START as u64);
+
+        with_ranges(|mut ranges| {
+            let heap: MappedRange = ranges.find(|range| range.contains(heap_start)).unwrap();
+            assert!(heap
+                .flags
+                .contains(PageTableFlags::PRESENT | PageTableFlags::WRITABLE));
+            assert!(heap.size >= 4096);
+        });
+    }
+
+    #[test_case]
+    fn walker_agrees_with_mapper() {
+        let addr = VirtAddr::new(HEAP_START as u64 + 0x123);
+        let expected = memory::mapper().translate_addr(addr);
+
+        let found = with_ranges(|mut ranges| ranges.find_map(|range| range.translate(addr)));
+        assert_eq!(found, expected);
+    }
+
+    #[test_case]
+    fn unmapped_address_is_not_found() {
+        let addr = VirtAddr::new(0xdead_0000_0000);
+        assert!(memory::mapper().translate_addr(addr).is_none());
+
+        let found = with_ranges(|mut ranges| ranges.any(|range| range.contains(addr)));
+        assert!(!found);
+    }
+
+    #[test_case]
+    fn dump_does_not_deadlock() {
+        paging::dump_page_tables();
+    }
+}
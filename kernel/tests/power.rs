@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::acpi::{self, Sdt, Signature};
+    use oros_kernel::power;
+
+    #[test_case]
+    fn parse_s5_byte_prefix() {
+        // Name(\_S5, Package(4) { 0x05, 0x05, Zero, Zero })
+        let aml = [
+            0x10, 0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x0a, 0x04, 0x0a, 0x05, 0x0a, 0x05,
+            0x00, 0x00,
+        ];
+        assert_eq!(power::parse_s5(&aml), Some((5, 5)));
+    }
+
+    #[test_case]
+    fn parse_s5_zero_one() {
+        // Name(_S5, Package(2) { Zero, One })
+        let aml = [0x08, b'_', b'S', b'5', b'_', 0x12, 0x04, 0x02, 0x00, 0x01];
+        assert_eq!(power::parse_s5(&aml), Some((0, 1)));
+    }
+
+    #[test_case]
+    fn parse_s5_ignores_references() {
+        // _S5_ used as a name string without Name op
+        let aml = [0x70, b'_', b'S', b'5', b'_', 0x12, 0x04, 0x02, 0x00, 0x01];
+        assert_eq!(power::parse_s5(&aml), None);
+    }
+
+    #[test_case]
+    fn dsdt_has_s5() {
+        let fadt = acpi::tables().fadt.as_ref().expect("no FADT");
+        let dsdt = unsafe { Sdt::at(fadt.dsdt) }.expect("invalid DSDT");
+        assert_eq!(dsdt.signature, Signature::DSDT);
+        assert!(power::parse_s5(dsdt.body()).is_some());
+    }
+
+    #[test_case]
+    fn reset_available() {
+        // QEMU has a reset register or an 8042, reboot never needs to triple fault
+        let fadt = acpi::tables().fadt.as_ref().expect("no FADT");
+        assert!(fadt.reset_supported() || fadt.has_8042());
+    }
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+//! Resets through `power::reboot`, run under QEMU with `-no-reboot` so the
+//! reset exits QEMU with status 0. Not resetting hangs the test
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::{init, power, serial_print, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    serial_print!("reboot::reboot...\t");
+    init::init(boot_info);
+
+    power::reboot();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+//! Resets through the 8042 reset line, skipping the ACPI reset register.
+//! Run under QEMU with `-no-reboot` so the reset exits QEMU with status 0,
+//! a failed reset reports the error and exits with the failure code
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::{
+    init,
+    port::serial::{exit_qemu, QemuExitCode},
+    power::{self, ResetMethod},
+    serial_print, serial_println, BOOTLOADER_CONFIG,
+};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    serial_print!("reboot_8042::reset...\t");
+    init::init(boot_info);
+
+    if let Err(err) = power::try_reset(ResetMethod::Keyboard) {
+        serial_println!("[failed]\n\nError: {}", err);
+    }
+    exit_qemu(QemuExitCode::Failed);
+    oros_kernel::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
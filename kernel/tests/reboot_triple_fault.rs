@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+//! Resets with a triple fault, skipping the ACPI reset register and the
+//! 8042. Run under QEMU with `-no-reboot` so the reset exits QEMU with
+//! status 0
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::{
+    init,
+    port::serial::{exit_qemu, QemuExitCode},
+    power::{self, ResetMethod},
+    serial_print, serial_println, BOOTLOADER_CONFIG,
+};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    serial_print!("reboot_triple_fault::reset...\t");
+    init::init(boot_info);
+
+    if let Err(err) = power::try_reset(ResetMethod::TripleFault) {
+        serial_println!("[failed]\n\nError: {}", err);
+    }
+    exit_qemu(QemuExitCode::Failed);
+    oros_kernel::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
@@ -0,0 +1,190 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use oros_kernel::time::{
+        self,
+        date::{days_in_month, is_leap_year},
+        rtc::{self, Registers},
+        DateTime, TickSource,
+    };
+
+    fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond: 0,
+        }
+    }
+
+    #[test_case]
+    fn decode_bcd_12_hour() {
+        let registers = Registers {
+            second: 0x59,
+            minute: 0x30,
+            // 11 PM
+            hour: 0x80 | 0x11,
+            day: 0x31,
+            month: 0x12,
+            year: 0x99,
+            century: Some(0x19),
+            status_b: 0,
+        };
+        assert_eq!(registers.decode(), date(1999, 12, 31, 23, 30, 59));
+
+        // 12 AM is midnight
+        let midnight = Registers {
+            hour: 0x12,
+            ..registers
+        };
+        assert_eq!(midnight.decode().hour, 0);
+        let noon = Registers {
+            hour: 0x80 | 0x12,
+            ..registers
+        };
+        assert_eq!(noon.decode().hour, 12);
+    }
+
+    #[test_case]
+    fn decode_binary_24_hour() {
+        let registers = Registers {
+            second: 5,
+            minute: 4,
+            hour: 17,
+            day: 29,
+            month: 2,
+            year: 24,
+            century: None,
+            status_b: rtc::BINARY | rtc::HOURS_24,
+        };
+        // without century register the 2000s are assumed
+        assert_eq!(registers.decode(), date(2024, 2, 29, 17, 4, 5));
+    }
+
+    #[test_case]
+    fn unix_conversion() {
+        assert_eq!(DateTime::from_unix(Duration::ZERO), DateTime::UNIX_EPOCH);
+        assert_eq!(DateTime::UNIX_EPOCH.timestamp(), 0);
+
+        let cases = [
+            (date(2000, 2, 29, 12, 0, 0), 951_825_600),
+            (date(2000, 3, 1, 0, 0, 0), 951_868_800),
+            (date(2038, 1, 19, 3, 14, 8), 2_147_483_648),
+            (date(2100, 3, 1, 0, 0, 0), 4_107_542_400),
+        ];
+        for (date, timestamp) in cases {
+            assert_eq!(date.timestamp(), timestamp);
+            assert_eq!(DateTime::from_unix(Duration::from_secs(timestamp)), date);
+        }
+
+        let subsec = DateTime::from_unix(Duration::new(1, 500));
+        assert_eq!(subsec.nanosecond, 500);
+        assert_eq!(subsec.to_unix(), Duration::new(1, 500));
+    }
+
+    #[test_case]
+    fn calendar() {
+        assert!(is_leap_year(2000));
+        assert!(!is_leap_year(2100));
+        assert!(is_leap_year(2024));
+        assert_eq!(days_in_month(2023, 2), 28);
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2024, 4), 30);
+
+        // Thursday and Saturday
+        assert_eq!(DateTime::UNIX_EPOCH.weekday(), 4);
+        assert_eq!(date(2000, 1, 1, 0, 0, 0).weekday(), 6);
+
+        assert!(date(2024, 2, 29, 23, 59, 59).is_valid());
+        assert!(!date(2023, 2, 29, 0, 0, 0).is_valid());
+        assert!(!date(2023, 13, 1, 0, 0, 0).is_valid());
+    }
+
+    #[test_case]
+    fn wall_clock_read_at_boot() {
+        let now = time::wall_clock();
+        assert!(now.is_valid());
+        // the RTC of any machine running this is set after 2020
+        assert!(now.year >= 2020);
+
+        let rtc = rtc::read();
+        assert!(rtc.is_valid());
+        // the RTC counts whole seconds, the wall clock started within one
+        let diff = rtc.timestamp().abs_diff(now.timestamp());
+        assert!(diff <= 2);
+    }
+
+    #[test_case]
+    fn wall_clock_follows_monotonic_clock() {
+        let uptime = time::uptime();
+        let start = time::unix_time();
+        for _ in 0..10 {
+            x86_64::instructions::hlt();
+        }
+        let elapsed = time::unix_time() - start;
+        assert!(elapsed > Duration::ZERO);
+        assert!(elapsed <= time::uptime() - uptime);
+    }
+
+    #[test_case]
+    fn rtc_tick_source() {
+        let frequency = time::set_tick_source(TickSource::Rtc, 1000);
+        assert_eq!(frequency, 1024);
+        assert_eq!(time::tick_source(), TickSource::Rtc);
+        assert_eq!(time::tick_frequency(), 1024);
+        assert!(rtc::periodic_enabled());
+
+        let start = time::ticks();
+        for _ in 0..5 {
+            x86_64::instructions::hlt();
+        }
+        let ticked = time::ticks() > start;
+
+        time::set_tick_frequency(time::TICK_HZ);
+        assert_eq!(time::tick_source(), TickSource::Pit);
+        assert!(!rtc::periodic_enabled());
+        assert!(ticked);
+    }
+
+    #[test_case]
+    fn rtc_rates() {
+        assert_eq!(rtc::periodic_frequency(rtc::MIN_RATE), 8192);
+        assert_eq!(rtc::periodic_frequency(rtc::MAX_RATE), 2);
+        assert_eq!(rtc::rate(1024), 6);
+        assert_eq!(rtc::rate(1), rtc::MAX_RATE);
+        assert_eq!(rtc::rate(100_000), rtc::MIN_RATE);
+    }
+}
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+//! Powers off through ACPI, QEMU exits with status 0 on success. A failed
+//! shutdown reports the error and exits with the failure code
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::{
+    init,
+    port::serial::{exit_qemu, QemuExitCode},
+    power, serial_print, serial_println, BOOTLOADER_CONFIG,
+};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    serial_print!("shutdown::acpi_shutdown...\t");
+    init::init(boot_info);
+
+    if let Err(err) = power::try_shutdown() {
+        serial_println!("[failed]\n\nError: {}", err);
+    }
+    exit_qemu(QemuExitCode::Failed);
+    oros_kernel::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use oros_kernel::memory::{self, slab::ObjectCache};
+
+    static CACHE: ObjectCache<[u64; 4]> = ObjectCache::new("test-objects");
+
+    #[test_case]
+    fn object_is_reused() {
+        let first = CACHE.alloc([1; 4]).unwrap();
+        let addr = &*first as *const _ as usize;
+        drop(first);
+
+        let second = CACHE.alloc([2; 4]).unwrap();
+        assert_eq!(&*second as *const _ as usize, addr);
+        assert_eq!(*second, [2; 4]);
+    }
+
+    #[test_case]
+    fn slabs_move_between_lists() {
+        let per_slab = CACHE.stats().objects_per_slab;
+
+        let objects: Vec<_> = (0..per_slab * 3)
+            .map(|i| CACHE.alloc([i as u64; 4]).unwrap())
+            .collect();
+
+        let stats = CACHE.stats();
+        assert_eq!(stats.full_slabs, 3);
+        assert_eq!(stats.objects_in_use, per_slab * 3);
+
+        drop(objects);
+
+        let stats = CACHE.stats();
+        assert_eq!(stats.objects_in_use, 0);
+        assert_eq!(stats.full_slabs + stats.partial_slabs, 0);
+        assert!(stats.empty_slabs <= 1);
+    }
+
+    #[test_case]
+    fn empty_slabs_are_given_back() {
+        let used_frames = memory::frame_allocator().used_frames();
+
+        let object = CACHE.alloc([0; 4]).unwrap();
+        drop(object);
+        CACHE.shrink();
+
+        assert_eq!(CACHE.stats().empty_slabs, 0);
+        assert!(memory::frame_allocator().used_frames() <= used_frames);
+    }
+}
@@ -0,0 +1,177 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, string::String, vec::Vec};
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use conquer_once::spin::OnceCell;
+    use oros_kernel::task::{Executor, Spawner};
+    use oros_kernel::time;
+
+    #[test_case]
+    fn block_on_returns_output() {
+        let mut executor = Executor::new();
+        assert_eq!(executor.block_on(async { 42 }), 42);
+    }
+
+    #[test_case]
+    fn spawn_from_task() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let result = executor.block_on(async move {
+            let handle = spawner.spawn(async { 21 });
+            handle.await.unwrap() * 2
+        });
+        assert_eq!(result, 42);
+    }
+
+    // boxed as a trait object, the future's type can't name itself
+    fn countdown(spawner: Spawner, n: u32) -> Pin<Box<dyn Future<Output = u32> + Send>> {
+        Box::pin(async move {
+            if n == 0 {
+                return 0;
+            }
+            let handle = spawner.spawn(countdown(spawner.clone(), n - 1));
+            handle.await.unwrap() + n
+        })
+    }
+
+    #[test_case]
+    fn spawner_in_static() {
+        static SPAWNER: OnceCell<Spawner> = OnceCell::uninit();
+
+        let mut executor = Executor::new();
+        SPAWNER.init_once(|| executor.spawner());
+        let result = executor.block_on(async {
+            let spawner = SPAWNER.get().unwrap();
+            spawner.spawn(async { 5 }).await.unwrap()
+        });
+        assert_eq!(result, 5);
+    }
+
+    #[test_case]
+    fn nested_spawns() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        assert_eq!(executor.block_on(countdown(spawner, 10)), 55);
+    }
+
+    #[test_case]
+    fn non_unit_outputs() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let (name, values) = executor.block_on(async move {
+            let name = spawner.spawn(async { String::from("oros") });
+            let values = spawner.spawn(async { (0..4).collect::<Vec<u32>>() });
+            (name.await.unwrap(), values.await.unwrap())
+        });
+        assert_eq!(name, "oros");
+        assert_eq!(values, [0, 1, 2, 3]);
+    }
+
+    #[test_case]
+    fn many_tasks() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let handles: Vec<_> = (0..50u64)
+            .map(|i| spawner.spawn(async move { i }))
+            .collect();
+        let sum = executor.block_on(async move {
+            let mut sum = 0;
+            for handle in handles {
+                sum += handle.await.unwrap();
+            }
+            sum
+        });
+        assert_eq!(sum, (0..50).sum());
+    }
+
+    #[test_case]
+    fn more_tasks_than_queue_capacity() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let handles: Vec<_> = (0..250u64)
+            .map(|i| {
+                spawner.spawn(async move {
+                    time::sleep(Duration::from_millis(1)).await;
+                    i
+                })
+            })
+            .collect();
+        let sum = executor.block_on(async move {
+            let mut sum = 0;
+            for handle in handles {
+                sum += handle.await.unwrap();
+            }
+            sum
+        });
+        assert_eq!(sum, (0..250).sum());
+    }
+
+    #[test_case]
+    fn detached_task_runs() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let handle = spawner.spawn(async {
+            time::sleep(Duration::from_millis(5)).await;
+            RUNS.fetch_add(1, Ordering::SeqCst);
+        });
+        drop(handle);
+
+        executor.block_on(async {
+            while RUNS.load(Ordering::SeqCst) == 0 {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test_case]
+    fn handle_reports_finished() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let handle = spawner.spawn(async { 7 });
+        assert!(!handle.is_finished());
+        let (finished, output) = executor.block_on(async move {
+            time::sleep(Duration::from_millis(1)).await;
+            (handle.is_finished(), handle.await.unwrap())
+        });
+        assert!(finished);
+        assert_eq!(output, 7);
+    }
+}
@@ -2,24 +2,27 @@
 #![no_main]
 #![feature(abi_x86_interrupt)]
 
+use bootloader_api::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use lazy_static::lazy_static;
 use x86_64::structures::idt::InterruptDescriptorTable;
 
-use oros::{
+use oros_kernel::{
+    hlt_loop,
     port::serial::{exit_qemu, QemuExitCode},
-    serial_print, serial_println,
+    serial_print, serial_println, BOOTLOADER_CONFIG,
 };
 use x86_64::structures::idt::InterruptStackFrame;
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(_boot_info: &'static mut BootInfo) -> ! {
     serial_print!("stack_overflow::stack_overflow...\t");
 
-    oros::interrupts::gdt::init();
+    oros_kernel::interrupts::gdt::init();
     init_test_idt();
 
-    // trigger a stack overflow
+    // trigger a stack overflow on the boot stack
     stack_overflow();
 
     panic!("Execution continued after stack overflow");
@@ -27,7 +30,7 @@ pub extern "C" fn _start() -> ! {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    oros::test_utils::panic_handler(info)
+    oros_kernel::test_utils::panic_handler(info)
 }
 
 #[allow(unconditional_recursion)]
@@ -42,7 +45,7 @@ lazy_static! {
         unsafe {
             idt.double_fault
                 .set_handler_fn(test_double_fault_handler)
-                .set_stack_index(oros::interrupts::gdt::DOUBLE_FAULT_IST_INDEX);
+                .set_stack_index(oros_kernel::interrupts::gdt::DOUBLE_FAULT_IST_INDEX);
         }
 
         idt
@@ -59,5 +62,5 @@ extern "x86-interrupt" fn test_double_fault_handler(
 ) -> ! {
     serial_println!("[ok]");
     exit_qemu(QemuExitCode::Success);
-    loop {}
+    hlt_loop()
 }
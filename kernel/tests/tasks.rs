@@ -0,0 +1,183 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use core::{
+        future,
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    use oros_kernel::task::{Executor, JoinError, Task, TaskState};
+    use oros_kernel::time::{self, Clocksource, Instant};
+
+    /// Sets its flag when dropped
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test_case]
+    fn snapshot_lists_live_tasks() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let start = Instant::now();
+        executor.spawn(Task::named("idle", future::pending()));
+        let sleeper = spawner.spawn_named("sleeper", time::sleep(Duration::from_millis(5)));
+        let unnamed = spawner.spawn(future::pending::<()>());
+
+        let tasks = executor.tasks_snapshot();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].name.as_deref(), Some("idle"));
+        assert_eq!(tasks[1].name.as_deref(), Some("sleeper"));
+        assert_eq!(tasks[1].id, sleeper.id());
+        assert_eq!(tasks[2].name, None);
+        for task in &tasks {
+            assert_eq!(task.state, TaskState::Queued);
+            assert_eq!(task.polls, 0);
+            assert!(task.spawned_at >= start);
+        }
+
+        executor.block_on(async move {
+            sleeper.await.unwrap();
+        });
+        let tasks = executor.tasks_snapshot();
+        // idle and unnamed, sleeper and block_on completed
+        assert_eq!(tasks.len(), 2);
+        assert!(tasks.iter().all(|task| task.state == TaskState::Pending));
+        assert!(tasks.iter().all(|task| task.polls == 1));
+        unnamed.abort();
+    }
+
+    #[test_case]
+    fn running_task_sees_itself() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let tasks = executor.block_on(async move { spawner.tasks_snapshot() });
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].state, TaskState::Running);
+    }
+
+    #[test_case]
+    fn poll_statistics() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let stats = executor.block_on(async move {
+            let handle = spawner.spawn_named("worker", async {
+                for _ in 0..3 {
+                    time::sleep(Duration::from_millis(1)).await;
+                }
+            });
+            let id = handle.id();
+            // wait until the worker slept twice
+            time::sleep(Duration::from_millis(2)).await;
+            while spawner
+                .tasks_snapshot()
+                .iter()
+                .any(|t| t.id == id && t.polls < 3)
+            {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+            let stats = spawner
+                .tasks_snapshot()
+                .into_iter()
+                .find(|t| t.id == id)
+                .unwrap();
+            handle.await.unwrap();
+            stats
+        });
+        assert_eq!(stats.name.as_deref(), Some("worker"));
+        assert!(stats.polls >= 3);
+        // ticks are too coarse to time a poll
+        if time::clocksource() != Clocksource::Pit {
+            assert!(stats.cpu_time > Duration::ZERO);
+        }
+        assert!(executor.tasks_snapshot().is_empty());
+    }
+
+    #[test_case]
+    fn abort_drops_future() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+
+        let flag = DropFlag(dropped.clone());
+        let handle = spawner.spawn(async move {
+            let _flag = flag;
+            future::pending::<u32>().await
+        });
+        let id = handle.id();
+        let result = executor.block_on(async move {
+            // let the task start and wait
+            time::sleep(Duration::from_millis(1)).await;
+            handle.abort();
+            assert!(handle.is_aborted());
+            handle.await
+        });
+
+        assert_eq!(result, Err(JoinError::Cancelled));
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(executor.tasks_snapshot().iter().all(|task| task.id != id));
+    }
+
+    #[test_case]
+    fn abort_before_first_poll() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let ran = Arc::new(AtomicBool::new(false));
+        let task_ran = ran.clone();
+        let handle = spawner.spawn(async move { task_ran.store(true, Ordering::SeqCst) });
+        handle.abort();
+
+        let result = executor.block_on(handle);
+        assert_eq!(result, Err(JoinError::Cancelled));
+        assert!(!ran.load(Ordering::SeqCst));
+        assert!(executor.tasks_snapshot().is_empty());
+    }
+
+    #[test_case]
+    fn abort_after_completion() {
+        let mut executor = Executor::new();
+        let spawner = executor.spawner();
+        let handle = spawner.spawn(async { 3 });
+        let result = executor.block_on(async move {
+            time::sleep(Duration::from_millis(1)).await;
+            assert!(handle.is_finished());
+            handle.abort();
+            assert!(!handle.is_aborted());
+            handle.await
+        });
+        assert_eq!(result, Ok(3));
+    }
+}
@@ -0,0 +1,145 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use oros_kernel::acpi;
+    use oros_kernel::time::{self, pit, tsc, Clocksource, Instant};
+
+    #[test_case]
+    fn pit_divisor() {
+        assert_eq!(pit::divisor(1000), 1193);
+        assert_eq!(pit::frequency(1193), 1000);
+        assert_eq!(pit::divisor(1), pit::DEFAULT_DIVISOR);
+        assert_eq!(pit::divisor(u32::MAX), 1);
+    }
+
+    #[test_case]
+    fn ticks_at_configured_rate() {
+        assert_eq!(time::tick_frequency(), time::TICK_HZ);
+        assert_eq!(time::tick_period(), Duration::from_nanos(999_848));
+    }
+
+    #[test_case]
+    fn ticks_counted() {
+        let start = time::ticks();
+        let uptime = time::uptime();
+        for _ in 0..5 {
+            x86_64::instructions::hlt();
+        }
+        // other interrupts may wake the CPU too, at least one was a tick
+        assert!(time::ticks() > start);
+        assert!(time::uptime() > uptime);
+    }
+
+    fn wait_ticks(count: u64) {
+        let start = time::ticks();
+        while time::ticks() < start + count {
+            x86_64::instructions::hlt();
+        }
+    }
+
+    #[test_case]
+    fn uptime_follows_ticks() {
+        wait_ticks(1);
+        let start = time::uptime();
+        wait_ticks(10);
+        // the first tick may have come right after the read
+        assert!(time::uptime() - start >= time::tick_period() * 9);
+    }
+
+    #[test_case]
+    fn clocksource_picked() {
+        let expected = if tsc::is_invariant() {
+            Clocksource::Tsc
+        } else if acpi::try_tables().and_then(|acpi| acpi.hpet).is_some() {
+            Clocksource::Hpet
+        } else {
+            Clocksource::Pit
+        };
+        // a 32 bit HPET isn't used
+        match (expected, time::clocksource()) {
+            (Clocksource::Hpet, Clocksource::Pit) => {}
+            (expected, source) => assert_eq!(source, expected),
+        }
+        assert!(time::clock_frequency() >= time::TICK_HZ as u64);
+    }
+
+    #[test_case]
+    fn now_monotonic() {
+        let mut last = time::now();
+        for _ in 0..1000 {
+            let now = time::now();
+            assert!(now >= last);
+            last = now;
+        }
+    }
+
+    #[test_case]
+    fn now_finer_than_ticks() {
+        if time::clocksource() == Clocksource::Pit {
+            return;
+        }
+        let start = time::now();
+        let mut now = time::now();
+        while now == start {
+            now = time::now();
+        }
+        assert!(now - start < time::tick_period());
+    }
+
+    #[test_case]
+    fn now_matches_tick_rate() {
+        wait_ticks(1);
+        let start = Instant::now();
+        wait_ticks(20);
+        let elapsed = start.elapsed();
+        // emulators may deliver ticks late, never early
+        assert!(elapsed >= time::tick_period() * 19);
+    }
+
+    #[test_case]
+    fn instant_arithmetic() {
+        let a = Instant::from_nanos(1_500);
+        let b = a + Duration::from_micros(2);
+        assert_eq!(b.as_nanos(), 3_500);
+        assert_eq!(b - a, Duration::from_nanos(2_000));
+        // saturates instead of going negative
+        assert_eq!(a - b, Duration::ZERO);
+        assert_eq!(a.checked_duration_since(b), None);
+        assert_eq!(b - Duration::from_nanos(3_500), Instant::ZERO);
+        assert_eq!(Instant::ZERO.checked_sub(Duration::from_nanos(1)), None);
+        assert_eq!(
+            Instant::from_nanos(u64::MAX).checked_add(Duration::from_nanos(1)),
+            None
+        );
+    }
+}
@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, task::Wake};
+    use core::{
+        future::{self, Future},
+        pin::pin,
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use futures_util::{
+        future::{select, Either},
+        task::noop_waker_ref,
+        StreamExt,
+    };
+    use oros_kernel::time::{self, timer, Elapsed, Instant};
+
+    struct FlagWaker(AtomicBool);
+
+    impl Wake for FlagWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Poll `future` each time it is woken, halting in between
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let woken = Arc::new(FlagWaker(AtomicBool::new(true)));
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if woken.0.swap(false, Ordering::SeqCst) {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+            x86_64::instructions::hlt();
+        }
+    }
+
+    #[test_case]
+    fn sleep_waits() {
+        let start = Instant::now();
+        block_on(time::sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(timer::pending(), 0);
+    }
+
+    #[test_case]
+    fn zero_sleep_ready() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let sleep = pin!(time::sleep(Duration::ZERO));
+        assert_eq!(sleep.poll(&mut cx), Poll::Ready(()));
+    }
+
+    #[test_case]
+    fn dropped_sleep_unregistered() {
+        let pending = timer::pending();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        {
+            let sleep = pin!(time::sleep(Duration::from_secs(60)));
+            assert_eq!(sleep.poll(&mut cx), Poll::Pending);
+            assert_eq!(timer::pending(), pending + 1);
+        }
+        assert_eq!(timer::pending(), pending);
+    }
+
+    #[test_case]
+    fn earlier_deadline_first() {
+        let long = time::sleep(Duration::from_millis(30));
+        let short = time::sleep(Duration::from_millis(10));
+        match block_on(select(long, short)) {
+            Either::Right(_) => {}
+            Either::Left(_) => panic!("longer sleep completed first"),
+        }
+    }
+
+    #[test_case]
+    fn timeout_elapses() {
+        let start = Instant::now();
+        let result = block_on(time::timeout(
+            Duration::from_millis(10),
+            future::pending::<()>(),
+        ));
+        assert_eq!(result, Err(Elapsed));
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[test_case]
+    fn timeout_returns_output() {
+        let result = block_on(time::timeout(Duration::from_secs(1), async { 5 }));
+        assert_eq!(result, Ok(5));
+
+        let result = block_on(time::timeout(
+            Duration::from_secs(1),
+            time::sleep(Duration::from_millis(5)),
+        ));
+        assert_eq!(result, Ok(()));
+        assert_eq!(timer::pending(), 0);
+    }
+
+    #[test_case]
+    fn interval_yields_every_period() {
+        let period = Duration::from_millis(10);
+        let mut interval = time::interval(period);
+        let mut last = interval.next_deadline() - period;
+        for _ in 0..3 {
+            let deadline = block_on(interval.next()).unwrap();
+            assert_eq!(deadline - last, period);
+            assert!(Instant::now() >= deadline);
+            last = deadline;
+        }
+    }
+
+    #[test_case]
+    fn interval_skips_missed_periods() {
+        let period = Duration::from_millis(5);
+        let mut interval = time::interval(period);
+        let first = interval.next_deadline();
+
+        // miss a few periods
+        while Instant::now() < first + period * 4 {
+            x86_64::instructions::hlt();
+        }
+        assert_eq!(block_on(interval.next()), Some(first));
+        assert!(interval.next_deadline() > Instant::now());
+        assert_eq!(
+            (interval.next_deadline() - first).as_nanos() % period.as_nanos(),
+            0
+        );
+    }
+}
@@ -0,0 +1,189 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oros_kernel::test_utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader_api::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use oros_kernel::init;
+use oros_kernel::{hlt_loop, BOOTLOADER_CONFIG};
+
+entry_point!(test_kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn test_kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    init::init(boot_info);
+
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oros_kernel::test_utils::panic_handler(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use oros_kernel::memory::{
+        self,
+        allocator::HEAP_START,
+        vmm::{Backing, RegionKind, VmmError, VMM, VMM_START},
+    };
+    use x86_64::{
+        structures::paging::{PageTableFlags, Translate},
+        VirtAddr,
+    };
+
+    const FLAGS: PageTableFlags = PageTableFlags::WRITABLE;
+
+    #[test_case]
+    fn boot_regions_are_reserved() {
+        let vmm = VMM.lock();
+
+        let heap = vmm.find(VirtAddr::new(HEAP_START as u64)).unwrap();
+        assert_eq!(heap.kind, RegionKind::Heap);
+
+        let phys_mem = vmm.find(memory::phys_mem_offset()).unwrap();
+        assert_eq!(phys_mem.kind, RegionKind::PhysicalMemory);
+
+        // kernel code is mapped by the bootloader
+        let code = VirtAddr::new(super::test_kernel_main as usize as u64);
+        assert!(vmm.find(code).is_some());
+    }
+
+    #[test_case]
+    fn regions_do_not_overlap() {
+        let vmm = VMM.lock();
+        let mut regions = vmm.regions();
+        let mut previous = regions.next().unwrap();
+        for region in regions {
+            assert!(previous.end() <= region.start);
+            previous = region;
+        }
+    }
+
+    #[test_case]
+    fn overlap_is_an_error() {
+        let mut vmm = VMM.lock();
+        let region = vmm
+            .allocate(0x4000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+
+        let result = vmm.reserve(region.start + 0x1000u64, 0x4000, RegionKind::General, FLAGS);
+        assert!(matches!(result, Err(VmmError::Overlap(existing)) if existing == region));
+
+        vmm.release(region.start).unwrap();
+    }
+
+    #[test_case]
+    fn unclaimed_range_spans_regions() {
+        let mut vmm = VMM.lock();
+        // free window to reserve in
+        let start = vmm
+            .allocate(0x10000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap()
+            .start;
+        vmm.release(start).unwrap();
+
+        vmm.reserve(start + 0x1000u64, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+        vmm.reserve(start + 0x5000u64, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+
+        // the gaps around both regions are reserved
+        vmm.reserve_unclaimed(start, 0x10000, RegionKind::Boot, FLAGS)
+            .unwrap();
+        for (offset, kind) in [
+            (0x0000u64, RegionKind::Boot),
+            (0x1000, RegionKind::General),
+            (0x3000, RegionKind::Boot),
+            (0x5000, RegionKind::General),
+            (0xf000, RegionKind::Boot),
+        ] {
+            assert_eq!(vmm.find(start + offset).unwrap().kind, kind);
+        }
+
+        for offset in [0x0000u64, 0x1000, 0x2000, 0x5000, 0x6000] {
+            vmm.release(start + offset).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn allocate_respects_alignment() {
+        let mut vmm = VMM.lock();
+        let first = vmm
+            .allocate(0x1000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+        let second = vmm
+            .allocate(0x3000, 0x20_0000, RegionKind::General, FLAGS)
+            .unwrap();
+
+        assert!(first.start.as_u64() >= VMM_START);
+        assert!(second.start.is_aligned(0x20_0000u64));
+        assert!(!second.overlaps(first.start, first.size));
+
+        vmm.release(first.start).unwrap();
+        vmm.release(second.start).unwrap();
+    }
+
+    #[test_case]
+    fn mapped_region_is_usable() {
+        let region = VMM
+            .lock()
+            .allocate_mapped(0x3000, 0x1000, RegionKind::General, FLAGS)
+            .unwrap();
+        assert_eq!(region.backing, Backing::Allocated);
+
+        let ptr: *mut u64 = region.start.as_mut_ptr();
+        let count = region.size as usize / 8;
+        unsafe {
+            for i in 0..count {
+                ptr.add(i).write_volatile(i as u64);
+            }
+            assert_eq!(ptr.add(count - 1).read_volatile(), count as u64 - 1);
+        }
+
+        let used = memory::frame_allocator().used_frames();
+        VMM.lock().release(region.start).unwrap();
+
+        assert!(memory::mapper().translate_addr(region.start).is_none());
+        assert_eq!(memory::frame_allocator().used_frames(), used - 3);
+        assert!(VMM.lock().find(region.start).is_none());
+    }
+
+    #[test_case]
+    fn physical_region_maps_given_frames() {
+        let phys = {
+            use x86_64::structures::paging::FrameAllocator;
+            memory::frame_allocator().allocate_frame().unwrap()
+        };
+
+        let region = VMM
+            .lock()
+            .allocate_physical(phys.start_address(), 0x1000, RegionKind::Mmio, FLAGS)
+            .unwrap();
+        assert_eq!(
+            memory::mapper().translate_addr(region.start + 0x10u64),
+            Some(phys.start_address() + 0x10u64)
+        );
+
+        VMM.lock().release(region.start).unwrap();
+        // physical frames are owned by the caller
+        assert!(memory::frame_allocator().is_used(phys));
+        unsafe {
+            use x86_64::structures::paging::FrameDeallocator;
+            memory::frame_allocator().deallocate_frame(phys);
+        }
+    }
+
+    #[test_case]
+    fn release_unknown_region() {
+        let result = VMM.lock().release(VirtAddr::new(VMM_START + 0x1234_0000));
+        assert!(matches!(result, Err(VmmError::NotReserved)));
+    }
+}
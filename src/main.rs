@@ -15,6 +15,11 @@ fn main() {
         cmd.arg("-drive")
             .arg(format!("format=raw,file={bios_path}"));
     }
+    // kernel command line, read by the kernel from the firmware configuration
+    if let Ok(cmdline) = std::env::var("OROS_CMDLINE") {
+        cmd.arg("-fw_cfg")
+            .arg(format!("name=opt/oros/cmdline,string={cmdline}"));
+    }
     let mut child = cmd.spawn().unwrap();
     child.wait().unwrap();
 }
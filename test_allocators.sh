@@ -0,0 +1,8 @@
+#! /bin/bash
+
+# Run heap allocation tests once for every global allocator feature
+for feature in alloc-fixed alloc-bump alloc-linked-list alloc-slab alloc-external-linked-list; do
+    echo "Testing heap allocation with $feature"
+    cargo test -p oros-kernel --target x86_64-unknown-none --test heap_allocation \
+        --no-default-features --features "$feature" || exit 1
+done